@@ -1,11 +1,14 @@
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
 
+use std::cell::RefCell;
 use std::time::Instant;
 
 use hybridcvrp::config::Config;
-use hybridcvrp::solver::genetic::GeneticAlgorithm;
+use hybridcvrp::solver::genetic::{GeneticAlgorithm, Individual, Split};
+use hybridcvrp::solver::improvement::LocalSearch;
 use hybridcvrp::solver::{Context, Solver};
 use hybridcvrp::utils;
+use hybridcvrp::utils::Random;
 
 fn code(_lol: usize) {
     let start_time = Instant::now();
@@ -24,8 +27,54 @@ fn code(_lol: usize) {
     solver.run();
 }
 
+// Local search on the same random individual, dominated by the evaluation of moves
+fn local_search(ctx: &Context, split: &mut Split, ls: &mut LocalSearch) {
+    ctx.random.reset();
+    let mut individual = Individual::new_random(ctx, 0);
+    let max_vehicles = ctx.config.borrow().num_vehicles;
+    split.run(ctx, &mut individual, max_vehicles);
+    ls.run(ctx, &mut individual, 1.0);
+}
+
+// Draws as made by the search, on the live stream of the generator
+fn random_draws(random: &Random) -> f64 {
+    let mut sum = 0.0;
+    for i in 1..1000 {
+        sum += random.real();
+        sum += random.range_usize(0, i) as f64;
+    }
+    sum
+}
+
+// The same draws from the generator without a stream, the lower bound of the live stream
+fn rng_draws(rng: &RefCell<fastrand::Rng>) -> f64 {
+    let mut sum = 0.0;
+    for i in 1..1000 {
+        sum += rng.borrow_mut().f64();
+        sum += rng.borrow_mut().usize(0..i) as f64;
+    }
+    sum
+}
+
 pub fn bench(c: &mut Criterion) {
     c.bench_function("Metaheuristic", |b| b.iter(|| code(black_box(0))));
+
+    let mut config = Config::load_yaml_file("config.yml");
+    config.deterministic = true;
+    config.instance_path = "instances/X-n101-k25.vrp".to_owned();
+    let mut parser = utils::ProblemParser::new();
+    parser.parse(&mut config);
+    let ctx = Context::new(parser, config, Instant::now());
+    let mut split = Split::new(&ctx);
+    let mut ls = LocalSearch::new(&ctx, 1.0);
+    c.bench_function("LocalSearch", |b| {
+        b.iter(|| local_search(black_box(&ctx), &mut split, &mut ls))
+    });
+
+    let random = Random::from_seed(1);
+    c.bench_function("Random", |b| b.iter(|| random_draws(black_box(&random))));
+    let rng = RefCell::new(fastrand::Rng::with_seed(1));
+    c.bench_function("Rng", |b| b.iter(|| rng_draws(black_box(&rng))));
 }
 
 criterion_group! {
@@ -5,6 +5,100 @@ use serde::{Deserialize, Serialize};
 use serde_yaml::Value;
 
 use crate::cli::Args;
+use crate::utils::{RandomDraw, EPSILON};
+
+/// How the diagonal of an explicit distance matrix is treated. Only the LOWER_DIAG_ROW format
+/// holds diagonal entries
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub enum DiagonalPolicy {
+    // The diagonal is set to zero
+    AssumeZero,
+    // The diagonal is the cost of visiting a node and is added to the route distances
+    Honor,
+}
+
+/// Term of the lexicographic objective, see `objective_order`
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub enum ObjectiveTerm {
+    // Feasible solutions first
+    Feasibility,
+    // Fewest non-empty routes
+    Vehicles,
+    // Shortest total distance, without penalties
+    Distance,
+    PenalizedCost,
+}
+
+/// How the parents of an offspring are selected from the population
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub enum ParentSelection {
+    // Best biased fitness of `tournament_size` random individuals, drawn with replacement
+    Tournament,
+    // Best biased fitness of `tournament_size` distinct random individuals
+    DistinctTournament,
+    // Random individual, weighted by the rank of the biased fitness
+    RankRoulette,
+    // Uniformly random individual
+    Random,
+}
+
+/// Which individual leaves a subpopulation when an offspring is added
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub enum Replacement {
+    // Grows to the min size plus lambda, then removes the worst biased fitness down to the min
+    // size
+    BiasedFitness,
+    // Removes the worst penalized cost once the min size plus lambda is reached
+    ReplaceWorst,
+    // Removes the worse of the offspring and its closest individual by broken pairs distance
+    // once the min size plus lambda is reached
+    ReplaceMostSimilar,
+    // Removes a random individual other than the best once the min size plus lambda is reached
+    SteadyState,
+}
+
+/// Heuristic constructing an initial individual
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub enum ConstructionKind {
+    // Clarke and Wright savings between correlated customers
+    Savings,
+    // Customers sorted by their angle around the depot, starting at a random angle
+    Sweep,
+    // Repeatedly visits the closest customer that fits
+    NearestNeighbor,
+    // Routes within k-means clusters of the customers
+    KMeans,
+    // Split of a random giant tour
+    Random,
+}
+
+/// Mutation applied to the giant tour of an offspring before it is split
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub enum Mutation {
+    // Reverses a random segment of the tour
+    ReverseSegment,
+    // Shuffles a random segment of the given length
+    InsertionShuffle(usize),
+    // Moves the given number of random customers to random positions
+    RandomRelocate(usize),
+}
+
+/// Family of local search moves
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub enum MoveKind {
+    // Moves a single customer
+    Relocate,
+    // Moves two consecutive customers, in either direction
+    OrOpt,
+    // Exchanges up to two consecutive customers between two positions
+    Swap,
+    // Reconnects the tails of one or two routes
+    TwoOpt,
+    // Exchanges two customers of different routes, each inserted at its best position
+    SwapStar,
+    // Reconnects three removed edges of a route. Only applied if listed in the order
+    ThreeOpt,
+}
 
 /// Contains all the configuration parameters
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -13,39 +107,87 @@ pub struct Config {
     pub instance_path: String,
     pub solution_path: Option<String>,
     pub time_limit: u64,
+    pub hard_deadline: bool,
     pub bks: f64,
     pub max_iterations: Option<u64>,
     pub max_iterations_without_improvement: u64,
     pub num_vehicles: u64,
     pub log_interval: u64,
     pub precompute_distance_size_limit: u64,
+    pub partial_precompute: bool,
+    pub deduplicate_locations: bool,
+    // Correlated customers within the factor times the mean nearest customer distance
+    pub adaptive_correlation: bool,
+    pub correlation_radius_factor: f64,
     pub round_distances: bool,
+    pub diagonal_policy: DiagonalPolicy,
+    // Tolerance of the cost comparisons: the move acceptance in the local search, 3-opt,
+    // Held-Karp and ruin and recreate, the best solution and the split tiebreak. Load checks,
+    // the dominance checks within the split and the cost order of the population keep the
+    // fixed `EPSILON`, as they guard against rounding errors rather than choose a solution
+    pub float_epsilon: f64,
+    pub asymmetric: bool,
     pub decompose_limit: u64,
     pub decomposed_problem_min_size: u64,
+    pub two_stage: bool,
+    pub two_stage_cluster_size: usize,
 
     // Randomization
     pub deterministic: bool,
     pub seed: u64,
+    pub record_rng: bool,
+    pub replay_rng: Option<Vec<RandomDraw>>,
+    // State of the generator to continue from, as returned by `Random::state` after a previous
+    // solve. Uses the seed for hashing
+    pub rng_state_in: Option<u64>,
 
     // Genetic Algorithm
     pub min_population_size: u64,
     pub initial_individuals: u64,
+    pub kmeans_initial_individuals: u64,
+    pub construction_mix: Vec<(ConstructionKind, f64)>,
     pub population_lambda: u64,
+    pub infeasible_min_population_size: Option<u64>,
+    pub infeasible_population_lambda: Option<u64>,
     pub num_elites: u64,
     pub num_diversity_closest: u64,
     pub feasibility_proportion_target: f64,
     pub tournament_size: u64,
+    pub parent_selection: ParentSelection,
+    pub replacement: Replacement,
+    // Priority of the objective terms when ranking solutions. Ties in a term, within
+    // `float_epsilon`, fall through to the next term and finally to the penalized cost
+    pub objective_order: Vec<ObjectiveTerm>,
+    pub mutation: Option<Mutation>,
+    pub mutation_rate: f64,
+    pub crossover_rate: f64,
     pub repair_probability: f64,
+    // Keeps the non-dominated solutions over the vehicles and the distance, and lets a
+    // dominating individual win the parent tournaments
+    pub pareto_front: bool,
+    pub return_population: bool,
+    // Maximum number of educated offspring cached by the fingerprint of their routes
+    pub archive_size: usize,
+    pub collect_metrics: bool,
+    // Times in seconds at which the best solution is captured, sorted when the solver starts
+    pub checkpoints: Vec<f64>,
 
     // Split
     pub split_capacity_factor: f64,
     pub linear_split: bool,
+    // Equal-cost splits prefer fewer routes, and then earlier cuts. Uses the Bellman split
+    pub split_tiebreak: bool,
+    // Weight of the squared unused capacity of every route in the split, which prefers even
+    // loads over the shortest distance. Uses the Bellman split. Only the split decodes with it:
+    // the penalized cost, the local search and R&R do not price it
+    pub split_balance_weight: f64,
 
     // Local Search
     pub local_search_granularity: u64,
     pub dynamic_granularity: bool,
     pub granularity_min: u64,
     pub ls_enabled: bool,
+    pub max_local_search_passes: u64,
 
     // Local Search Moves
     pub relocate_single: bool,
@@ -58,18 +200,35 @@ pub struct Config {
     pub two_opt_inter_reverse: bool,
     pub two_opt_inter: bool,
     pub swap_star: bool,
+    pub local_search_order: Vec<MoveKind>,
+    // Re-sequence the routes of the best solution with at most this many customers optimally
+    // when the search terminates, zero to disable. Capped at 16 customers
+    pub exact_route_threshold: usize,
 
     // Penalties
     pub penalty_capacity: f64,
     pub penalty_update_interval: u64,
     pub penalty_inc_multiplier: f64,
     pub penalty_dec_multiplier: f64,
+    pub capacity_penalty_tiers: Vec<(f64, f64)>,
+    pub min_customers_per_route: Option<usize>,
+    // Co-located customers count as one location. Each location beyond it costs the capacity
+    // penalty. The local search and recreate do not move customers between routes beyond it
+    pub max_distinct_locations_per_route: Option<usize>,
+    pub short_route_penalty: f64,
+    // Penalty per unit of area in which the bounding boxes of two routes intersect. The local
+    // search and R&R price it, while split decodes the routes without it
+    pub overlap_penalty: f64,
+    // Weight of the emissions from the leg cost of the context in the penalized cost. The local
+    // search and R&R price it, while split, 3-opt and the exact route re-sequencing do not
+    pub emissions_weight: f64,
 
     // Ruin Recreate
     pub average_ruin_cardinality: usize,
     pub max_ruin_string_length: usize,
     pub rr_mutation: bool,
     pub rr_probability: f64,
+    pub rr_iterations_per_offspring: u64,
     pub rr_gamma: f64,
     pub rr_final_temp: f64,
     pub rr_start_temp: f64,
@@ -92,39 +251,70 @@ impl Config {
             instance_path: String::new(),
             solution_path: None,
             time_limit: 60,
+            hard_deadline: false,
             bks: f64::INFINITY,
             max_iterations: None,
             max_iterations_without_improvement: 20_000,
             num_vehicles: 1_000_000,
             log_interval: 100,
             precompute_distance_size_limit: 2_000,
+            partial_precompute: false,
+            deduplicate_locations: false,
+            adaptive_correlation: false,
+            correlation_radius_factor: 1.5,
             round_distances: true,
+            diagonal_policy: DiagonalPolicy::AssumeZero,
+            float_epsilon: EPSILON,
+            asymmetric: false,
             decompose_limit: 3000,
             decomposed_problem_min_size: 200,
+            two_stage: false,
+            two_stage_cluster_size: 5,
 
             // Randomization
             deterministic: false,
             seed: 1,
+            record_rng: false,
+            replay_rng: None,
+            rng_state_in: None,
 
             // Genetic Algorithm
             min_population_size: 25,
             initial_individuals: 100,
+            kmeans_initial_individuals: 0,
+            construction_mix: Vec::new(),
             population_lambda: 40,
+            infeasible_min_population_size: None,
+            infeasible_population_lambda: None,
             num_elites: 4,
             num_diversity_closest: 5,
             feasibility_proportion_target: 0.2,
             tournament_size: 2,
+            parent_selection: ParentSelection::Tournament,
+            replacement: Replacement::BiasedFitness,
+            objective_order: Vec::new(),
+            mutation: None,
+            mutation_rate: 0.1,
+            crossover_rate: 1.0,
             repair_probability: 0.5,
+            pareto_front: false,
+            return_population: false,
+            archive_size: 0,
+            collect_metrics: false,
+            checkpoints: Vec::new(),
 
             // Split
             split_capacity_factor: 1.5,
             linear_split: true,
+            split_tiebreak: false,
+            split_balance_weight: 0.0,
 
             // Local Search
             ls_enabled: true,
             local_search_granularity: 20,
             dynamic_granularity: false,
             granularity_min: 10,
+            max_local_search_passes: 1_000,
 
             // Local Search Moves
             relocate_single: true,
@@ -137,12 +327,20 @@ impl Config {
             two_opt_inter_reverse: true,
             two_opt_inter: true,
             swap_star: true,
+            local_search_order: Vec::new(),
+            exact_route_threshold: 0,
 
             // Penalties
             penalty_capacity: 100.0,
             penalty_dec_multiplier: 0.85,
             penalty_inc_multiplier: 1.2,
             penalty_update_interval: 10,
+            capacity_penalty_tiers: Vec::new(),
+            min_customers_per_route: None,
+            max_distinct_locations_per_route: None,
+            short_route_penalty: 100.0,
+            overlap_penalty: 0.0,
+            emissions_weight: 0.0,
 
             // Ruin Recreate
             average_ruin_cardinality: 10,
@@ -150,6 +348,7 @@ impl Config {
             rr_mutation: true,
             rr_gamma: 1.0,
             rr_probability: 1.0,
+            rr_iterations_per_offspring: 1,
             rr_final_temp: 1.0,
             rr_start_temp: 10.0,
             rr_diversify: true,
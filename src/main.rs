@@ -2,7 +2,9 @@ use instant::Instant;
 
 use hybridcvrp::cli::Args;
 use hybridcvrp::config::Config;
-use hybridcvrp::solver::genetic::{DecomposedGeneticAlgorithm, GeneticAlgorithm};
+use hybridcvrp::solver::genetic::{
+    DecomposedGeneticAlgorithm, GeneticAlgorithm, TwoStageGeneticAlgorithm,
+};
 use hybridcvrp::solver::{Context, Solver};
 use hybridcvrp::utils;
 use log::LevelFilter;
@@ -34,16 +36,24 @@ fn main() {
 
     let should_decompose =
         ctx.problem.num_customers() as u64 >= ctx.config.borrow().decompose_limit;
+    let two_stage = ctx.config.borrow().two_stage;
 
-    match should_decompose {
-        true => {
+    match (two_stage, should_decompose) {
+        (true, _) => {
+            let metaheuristic = TwoStageGeneticAlgorithm::new(&ctx);
+            let mut solver = Solver::new(ctx, metaheuristic);
+            log::info!("Algorithm: Two-Stage Genetic Algorithm");
+            solver.run();
+            utils::write_solution_file(&solver.ctx);
+        }
+        (false, true) => {
             let metaheuristic = DecomposedGeneticAlgorithm::new(&ctx);
             let mut solver = Solver::new(ctx, metaheuristic);
             log::info!("Algorithm: Decomposed Genetic Algorithm");
             solver.run();
             utils::write_solution_file(&solver.ctx);
         }
-        false => {
+        (false, false) => {
             let metaheuristic = GeneticAlgorithm::new(&ctx);
             let mut solver = Solver::new(ctx, metaheuristic);
             log::info!("Algorithm: Genetic Algorithm");
@@ -1,12 +1,15 @@
 use std::{
     alloc::{alloc_zeroed, dealloc, Layout},
+    cmp::Ordering,
+    collections::{BinaryHeap, HashMap},
     fmt::Display,
 };
 
+use ahash::RandomState;
 use lazysort::SortedBy;
 
 use crate::{
-    config::Config,
+    config::{Config, DiagonalPolicy},
     models::{Coordinate, Problem},
     utils::FloatCompare,
 };
@@ -91,6 +94,11 @@ impl<T: Copy> Matrix<T> {
         new_matrix
     }
 
+    /// Number of bytes allocated for the elements
+    pub fn bytes(&self) -> usize {
+        self.rows * self.cols * std::mem::size_of::<T>()
+    }
+
     pub fn get_max(&self) -> T
     where
         T: PartialOrd,
@@ -146,7 +154,7 @@ impl Display for Matrix<f64> {
 
 /// Calculates the euclidian distance between two coordinates
 #[inline]
-fn euclidian(c1: &Coordinate, c2: &Coordinate) -> f64 {
+pub(crate) fn euclidian(c1: &Coordinate, c2: &Coordinate) -> f64 {
     ((c2.lng - c1.lng).powi(2) + (c2.lat - c1.lat).powi(2)).sqrt()
 }
 
@@ -156,7 +164,9 @@ pub struct DistanceMatrixBuilder {
     precompute: bool,
     rounded: bool,
     input: Option<Vec<Vec<f64>>>,
+    diagonal_policy: DiagonalPolicy,
     max_distance: Option<f64>,
+    deduplicate: bool,
 }
 
 impl DistanceMatrixBuilder {
@@ -166,7 +176,9 @@ impl DistanceMatrixBuilder {
             precompute: false,
             rounded: false,
             input: None,
+            diagonal_policy: DiagonalPolicy::AssumeZero,
             max_distance: None,
+            deduplicate: false,
         }
     }
 
@@ -190,12 +202,39 @@ impl DistanceMatrixBuilder {
         self
     }
 
+    pub fn diagonal_policy(mut self, diagonal_policy: DiagonalPolicy) -> Self {
+        self.diagonal_policy = diagonal_policy;
+        self
+    }
+
+    /// Stores one row per unique location of a precomputed matrix, shared by the nodes at
+    /// that location. Does not apply to an input matrix
+    pub fn deduplicate(mut self, deduplicate: bool) -> Self {
+        self.deduplicate = deduplicate;
+        self
+    }
+
     pub fn build(mut self) -> DistanceMatrix {
+        let mut location_index = None;
         let matrix = if let Some(input) = self.input {
             self.precompute = true;
             let n = self.locations.len();
             let mut matrix = Matrix::new(n, n);
             for (i, row) in input.iter().enumerate() {
+                // Row `i` holds the distances from node `i + 1` to the nodes before it, and
+                // ends with the diagonal entry in the lower diagonal format
+                let (row, diagonal) = match row.len() == i + 2 {
+                    true => (&row[..=i], Some(row[i + 1])),
+                    false => (&row[..], None),
+                };
+                if let Some(mut diagonal) = diagonal {
+                    if self.rounded {
+                        diagonal = diagonal.round();
+                    }
+                    if self.diagonal_policy == DiagonalPolicy::Honor {
+                        matrix.set(i + 1, i + 1, diagonal);
+                    }
+                }
                 for (j, &distance) in row.iter().enumerate() {
                     let mut distance = distance;
                     if self.rounded {
@@ -220,13 +259,21 @@ impl DistanceMatrixBuilder {
         } else {
             match self.precompute {
                 true => {
-                    let n = self.locations.len();
+                    let unique_locations = if self.deduplicate {
+                        let (index, unique_locations) = unique_locations(&self.locations);
+                        location_index = Some(index);
+                        unique_locations
+                    } else {
+                        self.locations.clone()
+                    };
+                    let n = unique_locations.len();
                     let mut matrix = Matrix::new(n, n);
 
                     // Assumes a symmetic matrix
                     for i in 0..n {
                         for j in (i + 1)..n {
-                            let mut distance = euclidian(&self.locations[i], &self.locations[j]);
+                            let mut distance =
+                                euclidian(&unique_locations[i], &unique_locations[j]);
                             if self.rounded {
                                 distance = distance.round();
                             }
@@ -252,20 +299,109 @@ impl DistanceMatrixBuilder {
             }
         };
 
-        DistanceMatrix::new(
+        let mut distance_matrix = DistanceMatrix::new(
             self.locations,
             matrix,
             self.precompute,
             self.rounded,
             self.max_distance,
-        )
+        );
+        distance_matrix.location_index = location_index;
+        distance_matrix
+    }
+}
+
+/// Maps every location to the index of its first occurrence among the unique locations.
+/// Returns the mapping and the unique locations
+fn unique_locations(locations: &[Coordinate]) -> (Vec<usize>, Vec<Coordinate>) {
+    let mut indices = HashMap::new();
+    let mut unique = Vec::new();
+    let index = locations
+        .iter()
+        .map(|location| {
+            *indices
+                .entry((location.lng.to_bits(), location.lat.to_bits()))
+                .or_insert_with(|| {
+                    unique.push(*location);
+                    unique.len() - 1
+                })
+        })
+        .collect();
+    (index, unique)
+}
+
+/// How node pairs without a path in a road graph are treated. With `Infeasible`, every node is
+/// connected to the depot in both directions and thereby to every other node, so no arcs are
+/// left to forbid
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UnreachablePolicy {
+    // Every node must be connected to the depot in both directions
+    Infeasible,
+    // Pairs without a path get the given distance
+    LargePenalty(f64),
+}
+
+/// Road graph of which `DistanceMatrix::from_graph` cannot compute the distances
+#[derive(Clone, Debug, PartialEq)]
+pub enum GraphError {
+    // Edge (from, to) with an endpoint that is not one of the nodes
+    UnknownNode(usize, usize),
+    // Edge (from, to, length) with a negative length
+    NegativeLength(usize, usize, f64),
+    // Nodes that cannot reach the depot or be reached from it
+    Disconnected(Vec<usize>),
+}
+
+// Node in the Dijkstra queue, ordered such that the closest node is popped first
+#[derive(PartialEq)]
+struct QueueEntry {
+    distance: f64,
+    node: usize,
+}
+
+impl Eq for QueueEntry {}
+
+impl PartialOrd for QueueEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
     }
 }
 
+impl Ord for QueueEntry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .distance
+            .partial_cmp(&self.distance)
+            .unwrap()
+            .then(other.node.cmp(&self.node))
+    }
+}
+
+// Largest number of nodes checked for the metric properties
+const METRIC_CHECK_LIMIT: usize = 100;
+
+/// Triangle inequality violation d(i, k) > d(i, j) + d(j, k) by `excess`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TriangleViolation {
+    pub i: usize,
+    pub j: usize,
+    pub k: usize,
+    pub excess: f64,
+}
+
+/// Whether the distances form a metric, as assumed by some of the moves
+#[derive(Clone, Debug)]
+pub struct MetricReport {
+    pub symmetric: bool,
+    pub triangle_inequality_holds: bool,
+    pub worst_violation: Option<TriangleViolation>,
+}
+
 /// Distance matrix.
 ///
 /// Supports lazy evaluation where the distance is calculated every time
-/// it is queried, in contrast to precomputing the matrix.
+/// it is queried, in contrast to precomputing the matrix. A lazy matrix may
+/// cache the distances between correlated nodes.
 #[derive(Debug)]
 pub struct DistanceMatrix {
     locations: Vec<Coordinate>,
@@ -273,6 +409,11 @@ pub struct DistanceMatrix {
     precomputed: bool,
     rounded: bool,
     max_distance: Option<f64>,
+    // Distances between correlated nodes. Lazy distances are symmetric, so the key is
+    // row * size + col with row < col
+    partial: Option<HashMap<usize, f64, RandomState>>,
+    // Row of the storage for each node, if nodes at the same location share a row
+    location_index: Option<Vec<usize>>,
 }
 
 impl DistanceMatrix {
@@ -289,6 +430,8 @@ impl DistanceMatrix {
             precomputed,
             rounded,
             max_distance,
+            partial: None,
+            location_index: None,
         }
     }
 
@@ -299,25 +442,149 @@ impl DistanceMatrix {
             precomputed: true,
             rounded,
             max_distance,
+            partial: None,
+            location_index: None,
         }
     }
 
+    /// Caches the distances between every node and its correlated nodes, such that only the
+    /// remaining distances are calculated when queried
+    pub fn precompute_partial(&mut self, correlation: &CorrelationMatrix) {
+        if self.precomputed {
+            return;
+        }
+        let size = self.size();
+        let mut partial = HashMap::with_capacity_and_hasher(
+            size * correlation.width(),
+            RandomState::with_seeds(0, 0, 0, 0),
+        );
+        for i in 0..size {
+            for &j in correlation.get(i) {
+                partial.insert(self.partial_key(i, j), self.lazy(i, j));
+            }
+        }
+        partial.shrink_to_fit();
+        self.partial = Some(partial);
+    }
+
     #[inline]
-    pub fn get(&self, row: usize, col: usize) -> f64 {
-        match self.precomputed {
-            true => self.storage.get(row, col),
-            false => {
-                let mut distance = euclidian(&self.locations[row], &self.locations[col]);
-                if self.rounded {
-                    distance = distance.round();
+    fn partial_key(&self, row: usize, col: usize) -> usize {
+        row.min(col) * self.size() + row.max(col)
+    }
+
+    #[inline]
+    fn lazy(&self, row: usize, col: usize) -> f64 {
+        let mut distance = euclidian(&self.locations[row], &self.locations[col]);
+        if self.rounded {
+            distance = distance.round();
+        }
+        distance
+    }
+
+    /// Computes the shortest path distances between the nodes at the `locations` of a road
+    /// graph with the directed, non-negative `edges` (from, to, length) between the indices of
+    /// the locations. Roads that can be travelled in both directions must be given as two
+    /// edges. Runs Dijkstra from every node.
+    ///
+    /// With the `Infeasible` policy, every node must be able to reach the depot (node 0) and be
+    /// reached from it, such that every pair of nodes is connected. Otherwise, the nodes
+    /// violating this are returned as the error. With `LargePenalty`, the pairs without a path
+    /// get the penalty as their distance. The largest distance excludes these pairs.
+    pub fn from_graph(
+        edges: Vec<(usize, usize, f64)>,
+        locations: Vec<Coordinate>,
+        unreachable_policy: UnreachablePolicy,
+    ) -> Result<Self, GraphError> {
+        let n = locations.len();
+        let mut adjacency: Vec<Vec<(usize, f64)>> = vec![Vec::new(); n];
+        for (from, to, length) in edges {
+            if from >= n || to >= n {
+                return Err(GraphError::UnknownNode(from, to));
+            }
+            // Dijkstra settles a node for good once it is popped
+            if length < 0.0 {
+                return Err(GraphError::NegativeLength(from, to, length));
+            }
+            adjacency[from].push((to, length));
+        }
+
+        let mut storage = Matrix::init(f64::INFINITY, n, n);
+        let mut queue = BinaryHeap::new();
+        for source in 0..n {
+            storage.set(source, source, 0.0);
+            queue.push(QueueEntry {
+                distance: 0.0,
+                node: source,
+            });
+            while let Some(QueueEntry { distance, node }) = queue.pop() {
+                if distance > storage.get(source, node) {
+                    continue;
+                }
+                for &(next, length) in adjacency[node].iter() {
+                    let next_distance = distance + length;
+                    if next_distance < storage.get(source, next) {
+                        storage.set(source, next, next_distance);
+                        queue.push(QueueEntry {
+                            distance: next_distance,
+                            node: next,
+                        });
+                    }
                 }
-                distance
             }
         }
+
+        let max_distance = storage
+            .slice(0, 0, n * n)
+            .iter()
+            .filter(|distance| distance.is_finite())
+            .fold(None, |max: Option<f64>, &distance| {
+                Some(max.map_or(distance, |max| max.max(distance)))
+            });
+
+        match unreachable_policy {
+            UnreachablePolicy::Infeasible => {
+                let disconnected: Vec<usize> = (1..n)
+                    .filter(|&node| {
+                        storage.get(0, node).is_infinite() || storage.get(node, 0).is_infinite()
+                    })
+                    .collect();
+                if !disconnected.is_empty() {
+                    return Err(GraphError::Disconnected(disconnected));
+                }
+            }
+            UnreachablePolicy::LargePenalty(penalty) => {
+                for i in 0..n {
+                    for j in 0..n {
+                        if storage.get(i, j).is_infinite() {
+                            storage.set(i, j, penalty);
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(Self::new(locations, storage, true, false, max_distance))
     }
 
-    pub fn get_vec(&self, row: usize, col: usize, number: usize) -> Vec<f64> {
+    #[inline]
+    pub fn get(&self, row: usize, col: usize) -> f64 {
         match self.precomputed {
+            true => match &self.location_index {
+                Some(location_index) => self.storage.get(location_index[row], location_index[col]),
+                None => self.storage.get(row, col),
+            },
+            false => match &self.partial {
+                Some(partial) => match partial.get(&self.partial_key(row, col)) {
+                    Some(&distance) => distance,
+                    None => self.lazy(row, col),
+                },
+                None => self.lazy(row, col),
+            },
+        }
+    }
+
+    pub fn get_vec(&self, row: usize, col: usize, number: usize) -> Vec<f64> {
+        match self.precomputed && self.location_index.is_none() {
             true => self
                 .storage
                 .slice(row, col, number)
@@ -348,10 +615,106 @@ impl DistanceMatrix {
         self.locations.len()
     }
 
+    /// Distance of the route starting and ending at the depot. The depot is not part of `route`
+    pub fn route_distance(&self, route: &[usize]) -> f64 {
+        let mut distance = 0.0;
+        let mut last_node = 0;
+        for &node in route.iter().chain(std::iter::once(&0)) {
+            distance += self.get(last_node, node);
+            last_node = node;
+        }
+        distance
+    }
+
+    /// Returns true if the route has the same distance in both directions, in which
+    /// case reversing it does not change its cost
+    pub fn is_reversible(&self, route: &[usize]) -> bool {
+        let reversed: Vec<usize> = route.iter().rev().copied().collect();
+        self.route_distance(route)
+            .approx_eq(self.route_distance(&reversed))
+    }
+
     pub fn max(&self) -> Option<f64> {
         self.max_distance
     }
 
+    /// Iterates over the distances between all pairs of customers, calculating them as needed.
+    /// With `symmetric` distances every unordered pair is yielded once as (i, j) with i < j
+    pub fn pairs(&self, symmetric: bool) -> impl Iterator<Item = (usize, usize, f64)> + '_ {
+        let size = self.size();
+        (1..size).flat_map(move |i| {
+            let start = if symmetric { i + 1 } else { 1 };
+            (start..size)
+                .filter(move |&j| j != i)
+                .map(move |j| (i, j, self.get(i, j)))
+        })
+    }
+
+    /// Checks symmetry and the triangle inequality. All nodes are checked if there are at most
+    /// `METRIC_CHECK_LIMIT` of them, otherwise an evenly spaced sample of that size
+    pub fn check_metric(&self) -> MetricReport {
+        let size = self.size();
+        let step = size.div_ceil(METRIC_CHECK_LIMIT);
+        let nodes: Vec<usize> = (0..size).step_by(step.max(1)).collect();
+
+        let mut symmetric = true;
+        let mut worst_violation: Option<TriangleViolation> = None;
+        for &i in nodes.iter() {
+            for &k in nodes.iter() {
+                let direct = self.get(i, k);
+                if !direct.approx_eq(self.get(k, i)) {
+                    symmetric = false;
+                }
+                for &j in nodes.iter() {
+                    let excess = direct - (self.get(i, j) + self.get(j, k));
+                    let worst_excess = worst_violation.map_or(0.0, |violation| violation.excess);
+                    if excess.approx_gt(worst_excess) {
+                        worst_violation = Some(TriangleViolation { i, j, k, excess });
+                    }
+                }
+            }
+        }
+        MetricReport {
+            symmetric,
+            triangle_inequality_holds: worst_violation.is_none(),
+            worst_violation,
+        }
+    }
+
+    pub fn is_precomputed(&self) -> bool {
+        self.precomputed
+    }
+
+    pub fn is_partially_precomputed(&self) -> bool {
+        self.partial.is_some()
+    }
+
+    /// Index of the unique location of the node, shared by all nodes with the same coordinates
+    /// if the rows are deduplicated
+    #[inline]
+    pub fn location(&self, node: usize) -> usize {
+        match &self.location_index {
+            Some(location_index) => location_index[node],
+            None => node,
+        }
+    }
+
+    /// Number of distinct rows of the matrix
+    pub fn unique_locations(&self) -> usize {
+        match &self.location_index {
+            Some(_) => self.storage.rows,
+            None => self.size(),
+        }
+    }
+
+    /// Number of bytes allocated for the precomputed distances. Estimated for the partial cache
+    pub fn bytes(&self) -> usize {
+        let partial_bytes = self.partial.as_ref().map_or(0, |partial| {
+            partial.capacity() * (std::mem::size_of::<usize>() + std::mem::size_of::<f64>())
+        });
+        self.storage.bytes() + partial_bytes
+    }
+
     pub fn from_mapping(&self, mapping: &[usize]) -> Self {
         let locations = mapping
             .iter()
@@ -375,16 +738,22 @@ impl DistanceMatrix {
             precomputed: true,
             rounded: self.rounded,
             max_distance,
+            partial: None,
+            location_index: None,
         }
     }
 }
 
-const CORRELATION_LIMIT: usize = 200;
+pub(crate) const CORRELATION_LIMIT: usize = 200;
 
 #[derive(Debug, Clone)]
 pub struct CorrelationMatrix {
     storage: Matrix<usize>,
     width: usize,
+    // Nearest predecessors of each node by the distance from them, for asymmetric distances
+    predecessors: Option<Matrix<usize>>,
+    // Number of correlated customers of each node, if it varies by node
+    widths: Option<Vec<usize>>,
 }
 
 impl CorrelationMatrix {
@@ -392,28 +761,135 @@ impl CorrelationMatrix {
         let size = distance_matrix.size();
         let width = CORRELATION_LIMIT.min(size - 2);
         let mut matrix: Matrix<usize> = Matrix::new(size, width);
+        // Nodes at the same location share the sorted neighbors, which only differ in the
+        // node itself
+        let mut groups = vec![Vec::new(); distance_matrix.unique_locations()];
         for i in 0..size {
-            distance_matrix
-                .get_vec(i, 0, size)
+            groups[distance_matrix.location(i)].push(i);
+        }
+        for nodes in groups.iter() {
+            let neighbors: Vec<usize> = distance_matrix
+                .get_vec(nodes[0], 0, size)
                 .iter()
                 .enumerate()
-                .filter(|&(j, _)| j > 0 && j != i)
-                .sorted_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
-                .take(width)
+                .filter(|&(j, _)| j > 0)
+                // Ties are broken by index, so truncating the neighbors is reproducible
+                .sorted_by(|a, b| a.1.partial_cmp(&b.1).unwrap().then(a.0.cmp(&b.0)))
+                .take(width + 1)
                 .map(|(index, _)| index)
-                .enumerate()
-                .for_each(|(number, index)| {
-                    matrix.set(i, number, index);
-                });
+                .collect();
+            for &node in nodes.iter() {
+                neighbors
+                    .iter()
+                    .filter(|&&j| j != node)
+                    .take(width)
+                    .enumerate()
+                    .for_each(|(number, &index)| {
+                        matrix.set(node, number, index);
+                    });
+            }
         }
         Self {
             storage: matrix,
             width,
+            predecessors: None,
+            widths: None,
+        }
+    }
+
+    /// Narrows the correlated customers of every customer to those within `radius_factor` times
+    /// the mean distance from a customer to its nearest customer, keeping at least the nearest.
+    /// Customers in dense regions keep more correlated customers than isolated ones
+    pub fn with_adaptive_widths(
+        mut self,
+        distance_matrix: &DistanceMatrix,
+        radius_factor: f64,
+    ) -> Self {
+        let size = distance_matrix.size();
+        if self.width == 0 || size < 2 {
+            return self;
         }
+        let nearest_total: f64 = (1..size)
+            .map(|i| distance_matrix.get(i, self.storage.get(i, 0)))
+            .sum();
+        let radius = radius_factor * nearest_total / (size - 1) as f64;
+        let widths = (0..size)
+            .map(|i| match i {
+                0 => self.width,
+                _ => self
+                    .get(i)
+                    .iter()
+                    .take_while(|&&j| distance_matrix.get(i, j).approx_lte(radius))
+                    .count()
+                    .max(1),
+            })
+            .collect();
+        self.widths = Some(widths);
+        self
+    }
+
+    /// Also stores the nearest predecessors of every node, sorted by the distance from the
+    /// predecessor to the node. Ties are broken by index
+    pub fn with_predecessors(mut self, distance_matrix: &DistanceMatrix) -> Self {
+        let size = distance_matrix.size();
+        let mut matrix: Matrix<usize> = Matrix::new(size, self.width);
+        for i in 0..size {
+            (1..size)
+                .filter(|&j| j != i)
+                .map(|j| (j, distance_matrix.get(j, i)))
+                .sorted_by(|a, b| a.1.partial_cmp(&b.1).unwrap().then(a.0.cmp(&b.0)))
+                .take(self.width)
+                .enumerate()
+                .for_each(|(number, (index, _))| {
+                    matrix.set(i, number, index);
+                });
+        }
+        self.predecessors = Some(matrix);
+        self
     }
 
     pub fn get(&self, index: usize) -> &[usize] {
-        self.slice(index, 0, self.width)
+        self.slice(index, 0, self.node_width(index))
+    }
+
+    /// Nearest nodes by the distance from the node to them
+    pub fn get_successors(&self, index: usize) -> &[usize] {
+        self.get(index)
+    }
+
+    /// Nearest nodes by the distance from them to the node. Without stored predecessors, the
+    /// distances are symmetric and these are the successors
+    pub fn get_predecessors(&self, index: usize) -> &[usize] {
+        match &self.predecessors {
+            Some(predecessors) => predecessors.slice(index, 0, self.node_width(index)),
+            None => self.get(index),
+        }
+    }
+
+    pub fn has_predecessors(&self) -> bool {
+        self.predecessors.is_some()
+    }
+
+    /// Number of correlated customers stored for each node
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Number of correlated customers of the node, at most the stored width
+    #[inline]
+    pub fn node_width(&self, index: usize) -> usize {
+        match &self.widths {
+            Some(widths) => widths[index],
+            None => self.width,
+        }
+    }
+
+    pub fn bytes(&self) -> usize {
+        self.storage.bytes()
+            + self
+                .predecessors
+                .as_ref()
+                .map_or(0, |predecessors| predecessors.bytes())
     }
 
     pub fn top_slice(&self, index: usize, number: usize) -> &[usize] {
@@ -424,11 +900,36 @@ impl CorrelationMatrix {
         self.storage.slice_mut(index, 0, number)
     }
 
+    pub fn top_predecessors_mut(&self, index: usize, number: usize) -> &mut [usize] {
+        match &self.predecessors {
+            Some(predecessors) => predecessors.slice_mut(index, 0, number),
+            None => self.top_slice_mut(index, number),
+        }
+    }
+
     fn slice(&self, row: usize, start: usize, number: usize) -> &[usize] {
         self.storage.slice(row, start, number)
     }
 }
 
+/// Whether distances are stored or calculated every time they are queried
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MatrixMode {
+    Precomputed,
+    // Lazy, except for the cached distances between correlated nodes
+    Partial,
+    Lazy,
+}
+
+/// Memory allocated by the matrices of a `MatrixProvider`
+#[derive(Clone, Debug)]
+pub struct MatrixStats {
+    pub mode: MatrixMode,
+    pub distance_bytes: usize,
+    pub correlation_bytes: usize,
+    pub correlation_width: usize,
+}
+
 #[derive(Debug)]
 pub struct MatrixProvider {
     pub distance: DistanceMatrix,
@@ -444,15 +945,34 @@ impl MatrixProvider {
         let mut distance_builder = DistanceMatrixBuilder::new()
             .locations(locations)
             .precompute(precompute)
-            .rounded(rounded);
+            .rounded(rounded)
+            .diagonal_policy(config.diagonal_policy)
+            .deduplicate(config.deduplicate_locations);
 
+        let input_provided = input_matrix.is_some();
         if let Some(input) = input_matrix {
             distance_builder = distance_builder.input(input);
         }
 
-        let distance = distance_builder.build();
+        let mut distance = distance_builder.build();
+        if input_provided {
+            let report = distance.check_metric();
+            if !report.symmetric || !report.triangle_inequality_holds {
+                log::warn!("Distance matrix is not a metric: {:?}", report);
+            }
+        }
 
-        let correlation = CorrelationMatrix::new(&distance);
+        let mut correlation = CorrelationMatrix::new(&distance);
+        if config.adaptive_correlation {
+            correlation =
+                correlation.with_adaptive_widths(&distance, config.correlation_radius_factor);
+        }
+        if config.asymmetric {
+            correlation = correlation.with_predecessors(&distance);
+        }
+        if config.partial_precompute {
+            distance.precompute_partial(&correlation);
+        }
 
         Self {
             distance,
@@ -462,10 +982,428 @@ impl MatrixProvider {
 
     pub fn from_mapping(&self, mapping: &[usize]) -> Self {
         let distance = self.distance.from_mapping(mapping);
-        let correlation = CorrelationMatrix::new(&distance);
+        let mut correlation = CorrelationMatrix::new(&distance);
+        if self.correlation.has_predecessors() {
+            correlation = correlation.with_predecessors(&distance);
+        }
         Self {
             distance,
             correlation,
         }
     }
+
+    /// Reports how the distances are stored and the memory used by the matrices
+    pub fn stats(&self) -> MatrixStats {
+        MatrixStats {
+            mode: if self.distance.is_precomputed() {
+                MatrixMode::Precomputed
+            } else if self.distance.is_partially_precomputed() {
+                MatrixMode::Partial
+            } else {
+                MatrixMode::Lazy
+            },
+            distance_bytes: self.distance.bytes(),
+            correlation_bytes: self.correlation.bytes(),
+            correlation_width: self.correlation.width(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::coordinate_problem;
+
+    // Customers on a diagonal line
+    fn problem(num_customers: usize) -> Problem {
+        let coords: Vec<(f64, f64)> = (0..=num_customers)
+            .map(|id| (id as f64, id as f64))
+            .collect();
+        coordinate_problem(&coords, 10.0)
+    }
+
+    #[test]
+    fn matrix_stats() {
+        let config = Config::default();
+        let stats = MatrixProvider::new(&problem(10), &config, None).stats();
+        assert_eq!(MatrixMode::Precomputed, stats.mode);
+        assert_eq!(11 * 11 * std::mem::size_of::<f64>(), stats.distance_bytes);
+        assert_eq!(9, stats.correlation_width);
+        assert_eq!(
+            11 * 9 * std::mem::size_of::<usize>(),
+            stats.correlation_bytes
+        );
+
+        // Too many customers to precompute the distances
+        let mut config = Config::default();
+        config.precompute_distance_size_limit = 100;
+        let stats = MatrixProvider::new(&problem(300), &config, None).stats();
+        assert_eq!(MatrixMode::Lazy, stats.mode);
+        assert_eq!(0, stats.distance_bytes);
+        assert_eq!(CORRELATION_LIMIT, stats.correlation_width);
+        assert_eq!(
+            301 * CORRELATION_LIMIT * std::mem::size_of::<usize>(),
+            stats.correlation_bytes
+        );
+    }
+
+    #[test]
+    fn input_diagonal() {
+        // Lower rows ending with the diagonal entry, which is larger than every distance
+        let input = vec![
+            vec![10.0, 1.0],
+            vec![10.0, 5.0, 2.0],
+            vec![20.0, 15.0, 15.0, 30.0],
+        ];
+        let locations = vec![Coordinate { lng: 0.0, lat: 0.0 }; 4];
+        for &policy in &[DiagonalPolicy::AssumeZero, DiagonalPolicy::Honor] {
+            let distance = DistanceMatrixBuilder::new()
+                .locations(locations.clone())
+                .input(input.clone())
+                .diagonal_policy(policy)
+                .build();
+            let diagonal: Vec<f64> = (1..4).map(|i| distance.get(i, i)).collect();
+            match policy {
+                DiagonalPolicy::AssumeZero => assert_eq!(vec![0.0; 3], diagonal),
+                DiagonalPolicy::Honor => assert_eq!(vec![1.0, 2.0, 30.0], diagonal),
+            }
+            assert_eq!(15.0, distance.get(2, 3));
+            assert_eq!(Some(20.0), distance.max());
+        }
+    }
+
+    #[test]
+    fn partial_precompute() {
+        let problem = problem(1_000);
+        let mut config = Config::default();
+        config.precompute_distance_size_limit = 100;
+        let lazy = MatrixProvider::new(&problem, &config, None);
+        config.partial_precompute = true;
+        let partial = MatrixProvider::new(&problem, &config, None);
+        assert_eq!(MatrixMode::Partial, partial.stats().mode);
+
+        let size = problem.nodes.len();
+        for i in 0..size {
+            for &j in partial.correlation.get(i) {
+                assert_eq!(lazy.distance.get(i, j), partial.distance.get(i, j));
+                assert_eq!(lazy.distance.get(j, i), partial.distance.get(j, i));
+            }
+        }
+        // Distances outside of the cache are calculated
+        assert_eq!(
+            lazy.distance.get(0, size - 1),
+            partial.distance.get(0, size - 1)
+        );
+
+        let full_bytes = size * size * std::mem::size_of::<f64>();
+        let partial_bytes = partial.stats().distance_bytes;
+        assert!(0 < partial_bytes && partial_bytes < full_bytes);
+    }
+
+    #[test]
+    fn customer_pairs() {
+        let num_customers = 10;
+        let locations: Vec<Coordinate> = problem(num_customers)
+            .nodes
+            .iter()
+            .map(|node| node.coord)
+            .collect();
+        for &precompute in &[true, false] {
+            let distance = DistanceMatrixBuilder::new()
+                .locations(locations.clone())
+                .precompute(precompute)
+                .build();
+            let pairs: Vec<(usize, usize, f64)> = distance.pairs(true).collect();
+            assert_eq!(num_customers * (num_customers - 1) / 2, pairs.len());
+            for &(i, j, value) in pairs.iter() {
+                assert!(0 < i && i < j);
+                assert_eq!(distance.get(i, j), value);
+            }
+            assert_eq!(
+                num_customers * (num_customers - 1),
+                distance.pairs(false).count()
+            );
+        }
+    }
+
+    #[test]
+    fn metric_violation() {
+        // Going from 1 to 3 directly is longer than through 2
+        let distances = [
+            [0.0, 1.0, 1.0, 1.0],
+            [1.0, 0.0, 1.0, 5.0],
+            [1.0, 1.0, 0.0, 1.0],
+            [1.0, 5.0, 1.0, 0.0],
+        ];
+        let mut storage = Matrix::new(4, 4);
+        for (i, row) in distances.iter().enumerate() {
+            for (j, &distance) in row.iter().enumerate() {
+                storage.set(i, j, distance);
+            }
+        }
+        let locations = vec![Coordinate { lng: 0.0, lat: 0.0 }; 4];
+        let distance = DistanceMatrix::new(locations.clone(), storage.clone(), true, false, None);
+        let report = distance.check_metric();
+        assert!(report.symmetric);
+        assert!(!report.triangle_inequality_holds);
+        let violation = report.worst_violation.unwrap();
+        assert_eq!((1, 0, 3), (violation.i, violation.j, violation.k));
+        assert_eq!(3.0, violation.excess);
+
+        storage.set(3, 1, 2.0);
+        let distance = DistanceMatrix::new(locations, storage, true, false, None);
+        assert!(!distance.check_metric().symmetric);
+
+        let distance = DistanceMatrixBuilder::new()
+            .locations(problem(10).nodes.iter().map(|node| node.coord).collect())
+            .precompute(true)
+            .build();
+        let report = distance.check_metric();
+        assert!(report.symmetric && report.triangle_inequality_holds);
+        assert!(report.worst_violation.is_none());
+    }
+
+    // Locations of the nodes of a road graph, one unit apart on a line
+    fn graph_locations(n: usize) -> Vec<Coordinate> {
+        (0..n)
+            .map(|node| Coordinate {
+                lng: node as f64,
+                lat: 0.0,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn road_graph_distances() {
+        // Two-way roads 0-1 (2), 1-2 (3), 0-2 (10) and a one-way road 2 -> 3 -> 0 (1 and 4)
+        let edges = vec![
+            (0, 1, 2.0),
+            (1, 0, 2.0),
+            (1, 2, 3.0),
+            (2, 1, 3.0),
+            (0, 2, 10.0),
+            (2, 0, 10.0),
+            (2, 3, 1.0),
+            (3, 0, 4.0),
+        ];
+        let distance = DistanceMatrix::from_graph(
+            edges.clone(),
+            graph_locations(4),
+            UnreachablePolicy::Infeasible,
+        )
+        .unwrap();
+        let expected = [
+            [0.0, 2.0, 5.0, 6.0],
+            [2.0, 0.0, 3.0, 4.0],
+            [5.0, 3.0, 0.0, 1.0],
+            [4.0, 6.0, 9.0, 0.0],
+        ];
+        for (i, row) in expected.iter().enumerate() {
+            for (j, &value) in row.iter().enumerate() {
+                assert_eq!(value, distance.get(i, j), "distance from {} to {}", i, j);
+            }
+        }
+        assert_eq!(Some(9.0), distance.max());
+        assert!(!distance.check_metric().symmetric);
+        let lng: Vec<f64> = distance.locations.iter().map(|coord| coord.lng).collect();
+        assert_eq!(vec![0.0, 1.0, 2.0, 3.0], lng);
+
+        // Node 4 has no roads and node 3 can no longer return to the depot
+        let edges: Vec<(usize, usize, f64)> = edges
+            .into_iter()
+            .filter(|&(from, _, _)| from != 3)
+            .collect();
+        let from_graph = |edges: Vec<(usize, usize, f64)>, n: usize| {
+            DistanceMatrix::from_graph(edges, graph_locations(n), UnreachablePolicy::Infeasible)
+                .map(|_| ())
+        };
+        assert_eq!(
+            Err(GraphError::Disconnected(vec![3, 4])),
+            from_graph(edges.clone(), 5)
+        );
+
+        // Edges must be between known nodes and not be negative
+        let mut unknown = edges.clone();
+        unknown.push((2, 5, 1.0));
+        assert_eq!(Err(GraphError::UnknownNode(2, 5)), from_graph(unknown, 5));
+        let mut negative = edges;
+        negative.push((4, 0, -1.0));
+        assert_eq!(
+            Err(GraphError::NegativeLength(4, 0, -1.0)),
+            from_graph(negative, 5)
+        );
+    }
+
+    #[test]
+    fn unreachable_policy() {
+        // The component of nodes 3 and 4 is not connected to the depot
+        let edges = vec![
+            (0, 1, 2.0),
+            (1, 0, 2.0),
+            (0, 2, 3.0),
+            (2, 0, 3.0),
+            (3, 4, 1.0),
+            (4, 3, 1.0),
+        ];
+        assert_eq!(
+            Err(GraphError::Disconnected(vec![3, 4])),
+            DistanceMatrix::from_graph(
+                edges.clone(),
+                graph_locations(5),
+                UnreachablePolicy::Infeasible
+            )
+            .map(|_| ())
+        );
+
+        let policy = UnreachablePolicy::LargePenalty(1_000.0);
+        let distance = DistanceMatrix::from_graph(edges, graph_locations(5), policy).unwrap();
+        assert_eq!(5.0, distance.get(1, 2));
+        assert_eq!(1.0, distance.get(3, 4));
+        assert_eq!(1_000.0, distance.get(0, 3));
+        assert_eq!(1_000.0, distance.get(4, 2));
+        assert_eq!(Some(5.0), distance.max());
+    }
+
+    #[test]
+    fn correlation_ties_are_broken_by_index() {
+        // Customers 2 to 5 are all at distance 1 from customer 1
+        let locations = [
+            (0.0, 10.0),
+            (0.0, 0.0),
+            (0.0, 1.0),
+            (-1.0, 0.0),
+            (1.0, 0.0),
+            (0.0, -1.0),
+        ]
+        .iter()
+        .map(|&(lng, lat)| Coordinate { lng, lat })
+        .collect();
+        let distance = DistanceMatrixBuilder::new()
+            .locations(locations)
+            .precompute(true)
+            .rounded(true)
+            .build();
+        let correlation = CorrelationMatrix::new(&distance);
+        assert_eq!(&[2, 3], correlation.top_slice(1, 2));
+        assert_eq!(&[2, 3, 4], correlation.top_slice(1, 3));
+        assert_eq!(&[2, 3, 4, 5], correlation.get(1));
+    }
+
+    #[test]
+    fn asymmetric_neighbors() {
+        // Nodes on a line, where driving to a lower index costs three times as much
+        let size = 6;
+        let mut storage = Matrix::new(size, size);
+        for i in 0..size {
+            for j in 0..size {
+                let distance = if i < j {
+                    (j - i) as f64
+                } else {
+                    3.0 * (i - j) as f64
+                };
+                storage.set(i, j, distance);
+            }
+        }
+        let locations = vec![Coordinate { lng: 0.0, lat: 0.0 }; size];
+        let distance = DistanceMatrix::new(locations, storage, true, false, None);
+        let symmetric = CorrelationMatrix::new(&distance);
+        assert_eq!(symmetric.get(2), symmetric.get_predecessors(2));
+
+        let correlation = CorrelationMatrix::new(&distance).with_predecessors(&distance);
+        assert_eq!(&[3, 4, 1, 5], correlation.get_successors(2));
+        assert_eq!(&[1, 3, 4, 5], correlation.get_predecessors(2));
+        for i in 0..size {
+            let successors = correlation.get_successors(i);
+            let predecessors = correlation.get_predecessors(i);
+            assert_eq!(size - 2, successors.len());
+            for pair in successors.windows(2) {
+                assert!(distance.get(i, pair[0]) <= distance.get(i, pair[1]));
+            }
+            for pair in predecessors.windows(2) {
+                assert!(distance.get(pair[0], i) <= distance.get(pair[1], i));
+            }
+        }
+        assert_eq!(2 * symmetric.bytes(), correlation.bytes());
+    }
+
+    #[test]
+    fn adaptive_correlation_widths() {
+        // A dense cluster of ten customers and five isolated customers
+        let cluster = (0..10).map(|i| ((i % 5) as f64 * 0.2, (i / 5) as f64 * 0.2));
+        let outliers = vec![
+            (100.0, 0.0),
+            (0.0, 100.0),
+            (-100.0, 0.0),
+            (0.0, -100.0),
+            (100.0, 100.0),
+        ];
+        let coords: Vec<(f64, f64)> = std::iter::once((0.0, 0.0))
+            .chain(cluster)
+            .chain(outliers)
+            .collect();
+        let problem = coordinate_problem(&coords, 10.0);
+        let mut config = Config::default();
+        config.round_distances = false;
+        let fixed = MatrixProvider::new(&problem, &config, None).correlation;
+        config.adaptive_correlation = true;
+        let adaptive = MatrixProvider::new(&problem, &config, None).correlation;
+
+        assert_eq!(fixed.width(), adaptive.width());
+        for customer in 1..=10 {
+            assert_eq!(9, adaptive.node_width(customer));
+            assert_eq!(&fixed.get(customer)[..9], adaptive.get(customer));
+        }
+        for customer in 11..=15 {
+            assert_eq!(1, adaptive.node_width(customer));
+        }
+    }
+
+    #[test]
+    fn deduplicated_locations() {
+        use crate::solver::genetic::GeneticAlgorithm;
+        use crate::solver::Solver;
+        use crate::utils::problem_context;
+
+        // Every location is shared by two customers
+        let coords: Vec<(f64, f64)> = (0..=100)
+            .map(|id| {
+                let location = (id + 1) / 2;
+                ((location * 37 % 101) as f64, (location * 53 % 97) as f64)
+            })
+            .collect();
+        let problem = coordinate_problem(&coords, 10.0);
+
+        let mut config = Config::default();
+        config.deterministic = true;
+        config.max_iterations = Some(100);
+        config.initial_individuals = 10;
+        let mut best_costs = Vec::new();
+        for &deduplicate in [false, true].iter() {
+            config.deduplicate_locations = deduplicate;
+            let ctx = problem_context(coordinate_problem(&coords, 10.0), None, config.clone());
+            let distance = &ctx.matrix_provider.distance;
+            let unique_locations = if deduplicate { 51 } else { 101 };
+            assert_eq!(unique_locations, distance.unique_locations());
+            assert_eq!(
+                unique_locations * unique_locations * std::mem::size_of::<f64>(),
+                distance.bytes()
+            );
+
+            let metaheuristic = GeneticAlgorithm::new(&ctx);
+            let mut solver = Solver::new(ctx, metaheuristic);
+            solver.run();
+            best_costs.push(solver.ctx.search_history.borrow().best_cost);
+        }
+        assert_eq!(best_costs[0], best_costs[1]);
+
+        let full = MatrixProvider::new(&problem, &Config::default(), None);
+        config.deduplicate_locations = true;
+        let deduplicated = MatrixProvider::new(&problem, &config, None);
+        for i in 0..problem.nodes.len() {
+            assert_eq!(full.correlation.get(i), deduplicated.correlation.get(i));
+            for j in 0..problem.nodes.len() {
+                assert_eq!(full.distance.get(i, j), deduplicated.distance.get(i, j));
+            }
+        }
+    }
 }
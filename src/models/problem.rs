@@ -1,11 +1,85 @@
+use std::collections::{HashMap, HashSet};
+
+use lazysort::SortedBy;
 use serde::{Deserialize, Serialize};
 
+use crate::models::euclidian;
+use crate::utils::FloatCompare;
+
 #[derive(Copy, Clone, Debug, Deserialize, Serialize)]
 pub struct Coordinate {
     pub lng: f64,
     pub lat: f64,
 }
 
+// Sign of the cross product of (b - a) and (c - a)
+fn orientation(a: Coordinate, b: Coordinate, c: Coordinate) -> f64 {
+    let cross = (b.lng - a.lng) * (c.lat - a.lat) - (b.lat - a.lat) * (c.lng - a.lng);
+    if cross.approx_eq(0.0) {
+        0.0
+    } else {
+        cross.signum()
+    }
+}
+
+/// Returns true if the segments (a, b) and (c, d) properly cross each other
+fn segments_intersect(a: Coordinate, b: Coordinate, c: Coordinate, d: Coordinate) -> bool {
+    let (o1, o2) = (orientation(a, b, c), orientation(a, b, d));
+    let (o3, o4) = (orientation(c, d, a), orientation(c, d, b));
+    o1 * o2 < 0.0 && o3 * o4 < 0.0
+}
+
+// Area of the convex hull of the points, using the monotone chain algorithm
+fn convex_hull_area(points: &[Coordinate]) -> f64 {
+    let cross = |o: &Coordinate, a: &Coordinate, b: &Coordinate| {
+        (a.lng - o.lng) * (b.lat - o.lat) - (a.lat - o.lat) * (b.lng - o.lng)
+    };
+    let mut sorted = points.to_vec();
+    sorted.sort_by(|a, b| {
+        a.lng
+            .partial_cmp(&b.lng)
+            .unwrap()
+            .then(a.lat.partial_cmp(&b.lat).unwrap())
+    });
+
+    // Lower and upper hull, the last point of each is the first point of the other
+    let mut hull: Vec<Coordinate> = Vec::with_capacity(2 * sorted.len());
+    for pass in 0..2 {
+        let start = hull.len();
+        for point in sorted.iter() {
+            while hull.len() >= start + 2
+                && cross(&hull[hull.len() - 2], &hull[hull.len() - 1], point) <= 0.0
+            {
+                hull.pop();
+            }
+            hull.push(*point);
+        }
+        hull.pop();
+        if pass == 0 {
+            sorted.reverse();
+        }
+    }
+
+    let area: f64 = (0..hull.len())
+        .map(|i| {
+            let (a, b) = (&hull[i], &hull[(i + 1) % hull.len()]);
+            a.lng * b.lat - b.lng * a.lat
+        })
+        .sum();
+    area.abs() / 2.0
+}
+
+/// Geometric measures of how spread out the customers of a route are
+#[derive(Copy, Clone, Debug, Serialize)]
+pub struct Compactness {
+    /// Largest distance between two customers of the route
+    pub span: f64,
+    /// Area of the convex hull of the customers
+    pub convex_hull_area: f64,
+    /// Average distance between consecutive customers
+    pub mean_interstop_distance: f64,
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Node {
     pub id: usize,
@@ -23,17 +97,29 @@ pub struct Vehicle {
 pub struct ProblemBuilder {
     pub nodes: Vec<Node>,
     pub vehicle: Vehicle,
+    #[serde(default)]
+    pub clusters: Vec<Vec<usize>>,
 }
 
 impl ProblemBuilder {
     pub fn new(nodes: Vec<Node>, vehicle: Vehicle) -> Self {
-        Self { nodes, vehicle }
+        Self {
+            nodes,
+            vehicle,
+            clusters: Vec::new(),
+        }
+    }
+
+    pub fn clusters(mut self, clusters: Vec<Vec<usize>>) -> Self {
+        self.clusters = clusters;
+        self
     }
 
     pub fn build(self) -> Problem {
         Problem {
             nodes: self.nodes,
             vehicle: self.vehicle,
+            clusters: self.clusters,
         }
     }
 }
@@ -42,6 +128,8 @@ impl ProblemBuilder {
 pub struct Problem {
     pub nodes: Vec<Node>,
     pub vehicle: Vehicle,
+    // Groups of customers that must be served by the same route, in any order
+    pub clusters: Vec<Vec<usize>>,
 }
 
 impl Problem {
@@ -66,6 +154,31 @@ impl Problem {
             .max_by(|a, b| a.partial_cmp(&b).unwrap())
     }
 
+    /// Returns the minimum number of vehicles from the LP bin packing problem, and the number of
+    /// vehicles used by first-fit-decreasing, which is a feasible packing of the demands
+    pub fn min_vehicles(&self) -> (u64, u64) {
+        let capacity = self.vehicle.cap;
+        let lower_bound = (self.total_demand() / capacity).ceil() as u64;
+
+        let demands = self
+            .nodes
+            .iter()
+            .map(|node| node.demand)
+            .filter(|&demand| demand > 0.0)
+            .sorted_by(|a, b| b.partial_cmp(a).unwrap());
+        let mut loads: Vec<f64> = Vec::new();
+        for demand in demands {
+            match loads
+                .iter_mut()
+                .find(|load| (**load + demand).approx_lte(capacity))
+            {
+                Some(load) => *load += demand,
+                None => loads.push(demand),
+            }
+        }
+        (lower_bound, loads.len() as u64)
+    }
+
     pub fn get_angle(&self, node: usize) -> i32 {
         let x = self.nodes[node].coord.lng - self.nodes[0].coord.lng;
         let y = self.nodes[node].coord.lat - self.nodes[0].coord.lat;
@@ -74,6 +187,171 @@ impl Problem {
         angle
     }
 
+    /// Returns the (min, max) corners of the box enclosing the `nodes`.
+    ///
+    /// Panics if `nodes` is empty
+    pub fn bounding_box(&self, nodes: &[usize]) -> (Coordinate, Coordinate) {
+        assert!(!nodes.is_empty(), "Bounding box of an empty set of nodes");
+        let first = self.nodes[nodes[0]].coord;
+        nodes.iter().map(|&node| self.nodes[node].coord).fold(
+            (first, first),
+            |(min, max), coord| {
+                (
+                    Coordinate {
+                        lng: min.lng.min(coord.lng),
+                        lat: min.lat.min(coord.lat),
+                    },
+                    Coordinate {
+                        lng: max.lng.max(coord.lng),
+                        lat: max.lat.max(coord.lat),
+                    },
+                )
+            },
+        )
+    }
+
+    /// Returns the total area in which the bounding boxes of two routes intersect, summed over
+    /// all pairs of routes. Empty routes are skipped
+    pub fn route_overlap_area<'a, I>(&self, routes: I) -> f64
+    where
+        I: IntoIterator<Item = &'a [usize]>,
+    {
+        let boxes: Vec<(Coordinate, Coordinate)> = routes
+            .into_iter()
+            .filter(|route| !route.is_empty())
+            .map(|route| self.bounding_box(route))
+            .collect();
+
+        let mut area = 0.0;
+        for (i, (min_one, max_one)) in boxes.iter().enumerate() {
+            for (min_two, max_two) in boxes.iter().skip(i + 1) {
+                let width = max_one.lng.min(max_two.lng) - min_one.lng.max(min_two.lng);
+                let height = max_one.lat.min(max_two.lat) - min_one.lat.max(min_two.lat);
+                area += width.max(0.0) * height.max(0.0);
+            }
+        }
+        area
+    }
+
+    /// Returns the pairs of edges of the route that intersect each other. Edge `i` goes from
+    /// the `i`-th to the `(i + 1)`-th node of the route when it starts and ends at the depot
+    pub fn route_crossings(&self, route: &[usize]) -> Vec<(usize, usize)> {
+        let tour: Vec<Coordinate> = std::iter::once(&0)
+            .chain(route.iter())
+            .chain(std::iter::once(&0))
+            .map(|&node| self.nodes[node].coord)
+            .collect();
+        let num_edges = tour.len() - 1;
+
+        let mut crossings = Vec::new();
+        for i in 0..num_edges {
+            // Adjacent edges share a node and are not checked
+            for j in (i + 2)..num_edges {
+                if i == 0 && j == num_edges - 1 {
+                    continue;
+                }
+                if segments_intersect(tour[i], tour[i + 1], tour[j], tour[j + 1]) {
+                    crossings.push((i, j));
+                }
+            }
+        }
+        crossings
+    }
+
+    /// Returns the Euclidean compactness measures of the route. The depot is not part of them
+    pub fn route_compactness(&self, route: &[usize]) -> Compactness {
+        let coords: Vec<Coordinate> = route.iter().map(|&node| self.nodes[node].coord).collect();
+        let span = coords
+            .iter()
+            .enumerate()
+            .flat_map(|(i, a)| coords.iter().skip(i + 1).map(move |b| euclidian(a, b)))
+            .fold(0.0, f64::max);
+        let mean_interstop_distance = if coords.len() > 1 {
+            coords
+                .windows(2)
+                .map(|pair| euclidian(&pair[0], &pair[1]))
+                .sum::<f64>()
+                / (coords.len() - 1) as f64
+        } else {
+            0.0
+        };
+        Compactness {
+            span,
+            convex_hull_area: convex_hull_area(&coords),
+            mean_interstop_distance,
+        }
+    }
+
+    /// Number of distinct coordinates visited by the route, where co-located customers count once
+    pub fn num_locations(&self, route: &[usize]) -> usize {
+        route
+            .iter()
+            .map(|&node| self.location(node))
+            .collect::<HashSet<(u64, u64)>>()
+            .len()
+    }
+
+    /// Key of the node's coordinate, equal for co-located nodes
+    #[inline]
+    pub fn location(&self, node: usize) -> (u64, u64) {
+        let coord = self.nodes[node].coord;
+        (coord.lng.to_bits(), coord.lat.to_bits())
+    }
+
+    /// Snaps the customer coordinates to the closest point of a grid with the given cell size.
+    /// With `merge`, customers in the same cell are merged into one with the summed demand.
+    ///
+    /// Returns the new problem and for every node in it the indices of the original nodes
+    /// it represents. The depot is kept at its coordinate and is never merged
+    pub fn snap_to_grid(&self, cell_size: f64, merge: bool) -> (Self, Vec<Vec<usize>>) {
+        assert!(cell_size > 0.0, "Cell size must be positive");
+        let mut nodes = vec![self.nodes[0].clone()];
+        let mut merged = vec![vec![0]];
+        let mut cells: HashMap<(i64, i64), usize> = HashMap::new();
+
+        for (index, node) in self.nodes.iter().enumerate().skip(1) {
+            let cell = (
+                (node.coord.lng / cell_size).round() as i64,
+                (node.coord.lat / cell_size).round() as i64,
+            );
+            match cells.get(&cell) {
+                Some(&new_index) if merge => {
+                    nodes[new_index].demand += node.demand;
+                    merged[new_index].push(index);
+                }
+                _ => {
+                    cells.insert(cell, nodes.len());
+                    nodes.push(Node {
+                        id: nodes.len() + 1,
+                        coord: Coordinate {
+                            lng: cell.0 as f64 * cell_size,
+                            lat: cell.1 as f64 * cell_size,
+                        },
+                        demand: node.demand,
+                    });
+                    merged.push(vec![index]);
+                }
+            }
+        }
+
+        let num_merged = self.nodes.len() - nodes.len();
+        if num_merged > 0 {
+            log::info!("Snapping to grid merged {} customers", num_merged);
+        }
+        let mut new_indices = vec![0; self.nodes.len()];
+        for (new_index, indices) in merged.iter().enumerate() {
+            for &index in indices {
+                new_indices[index] = new_index;
+            }
+        }
+        let problem = Self {
+            nodes,
+            vehicle: self.vehicle.clone(),
+            clusters: self.map_clusters(|index| Some(new_indices[index])),
+        };
+        (problem, merged)
+    }
+
     pub fn from_mapping(&self, mapping: &[usize]) -> Self {
         Self {
             nodes: mapping
@@ -81,6 +359,133 @@ impl Problem {
                 .map(|&index| self.nodes[index].clone())
                 .collect(),
             vehicle: self.vehicle.clone(),
+            clusters: self.map_clusters(|index| mapping.iter().position(|&mapped| mapped == index)),
+        }
+    }
+
+    /// Index of the cluster of every node, if it is in one
+    pub fn cluster_index(&self) -> Vec<Option<usize>> {
+        let mut index = vec![None; self.nodes.len()];
+        for (cluster_index, cluster) in self.clusters.iter().enumerate() {
+            for &customer in cluster.iter() {
+                index[customer] = Some(cluster_index);
+            }
         }
+        index
+    }
+
+    // Clusters with the customers given new indices. Customers without a new index are dropped,
+    // and so are clusters left with fewer than two customers
+    fn map_clusters<F>(&self, new_index: F) -> Vec<Vec<usize>>
+    where
+        F: Fn(usize) -> Option<usize>,
+    {
+        self.clusters
+            .iter()
+            .map(|cluster| {
+                let mut mapped: Vec<usize> = cluster
+                    .iter()
+                    .filter_map(|&index| new_index(index))
+                    .collect();
+                mapped.sort();
+                mapped.dedup();
+                mapped
+            })
+            .filter(|cluster| cluster.len() > 1)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::{coordinate_problem, demand_problem};
+
+    fn problem(coords: &[(f64, f64)]) -> Problem {
+        coordinate_problem(coords, 10.0)
+    }
+
+    #[test]
+    fn snap_to_grid() {
+        let mut problem = problem(&[(0.0, 0.0), (10.2, 0.1), (9.9, -0.3), (0.4, 9.7)]);
+        problem.nodes[2].demand = 2.0;
+
+        let (snapped, merged) = problem.snap_to_grid(1.0, false);
+        assert_eq!(4, snapped.dim());
+        assert_eq!(vec![vec![0], vec![1], vec![2], vec![3]], merged);
+
+        let (snapped, merged) = problem.snap_to_grid(1.0, true);
+        assert_eq!(3, snapped.dim());
+        assert_eq!(vec![vec![0], vec![1, 2], vec![3]], merged);
+        assert_eq!(3.0, snapped.nodes[1].demand);
+        assert_eq!(
+            (10.0, 0.0),
+            (snapped.nodes[1].coord.lng, snapped.nodes[1].coord.lat)
+        );
+        assert_eq!(
+            (0.0, 10.0),
+            (snapped.nodes[2].coord.lng, snapped.nodes[2].coord.lat)
+        );
+        assert_eq!(problem.total_demand(), snapped.total_demand());
+    }
+
+    #[test]
+    fn route_crossings() {
+        let problem = problem(&[(0.0, 0.0), (2.0, 0.0), (2.0, 2.0), (0.0, 2.0)]);
+        assert!(problem.route_crossings(&[1, 2, 3]).is_empty());
+
+        // The edge from the depot to node 2 crosses the edge from node 1 to node 3
+        assert_eq!(vec![(0, 2)], problem.route_crossings(&[2, 1, 3]));
+    }
+
+    #[test]
+    fn route_overlap_area() {
+        let problem = problem(&[(0.0, 0.0), (0.0, 0.0), (2.0, 2.0), (1.0, 1.0), (3.0, 4.0)]);
+        let routes: Vec<Vec<usize>> = vec![vec![1, 2], vec![3, 4], Vec::new()];
+        let area = problem.route_overlap_area(routes.iter().map(|route| route.as_slice()));
+        assert_eq!(1.0, area);
+        assert_eq!(
+            0.0,
+            problem.route_overlap_area(vec![&[1, 3][..], &[2, 4][..]])
+        );
+    }
+
+    #[test]
+    fn min_vehicles() {
+        let unit_demands = problem(&vec![(0.0, 0.0); 26]);
+        assert_eq!(2.5 * unit_demands.vehicle.cap, unit_demands.total_demand());
+        assert_eq!((3, 3), unit_demands.min_vehicles());
+
+        // Every pair of customers exceeds the capacity
+        let large_demands = demand_problem(
+            &[(0.0, 0.0), (1.0, 0.0), (2.0, 0.0), (3.0, 0.0)],
+            &[6.0; 3],
+            10.0,
+        );
+        assert_eq!((2, 3), large_demands.min_vehicles());
+    }
+
+    #[test]
+    fn route_compactness() {
+        let problem = problem(&[
+            (0.0, 0.0),
+            (1.0, 1.0),
+            (1.0, 2.0),
+            (2.0, 2.0),
+            (2.0, 1.0),
+            (1.5, 1.5),
+            (10.0, 0.0),
+            (10.0, 10.0),
+            (0.0, 10.0),
+        ]);
+        let tight = problem.route_compactness(&[1, 2, 5, 3, 4]);
+        assert_eq!(1.0, tight.convex_hull_area);
+        assert!(tight.span.approx_eq(2f64.sqrt()));
+
+        let spread = problem.route_compactness(&[1, 6, 7, 8]);
+        assert!(spread.convex_hull_area > tight.convex_hull_area);
+        assert!(spread.span > tight.span);
+        assert!(spread.mean_interstop_distance > tight.mean_interstop_distance);
+        assert_eq!(0.0, problem.route_compactness(&[6]).convex_hull_area);
     }
 }
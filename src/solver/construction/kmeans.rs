@@ -0,0 +1,162 @@
+use crate::models::Coordinate;
+use crate::solver::genetic::{Individual, Split};
+use crate::solver::Context;
+use crate::utils::FloatCompare;
+
+// Maximum number of assignment and update steps in the clustering
+const MAX_KMEANS_ITERATIONS: usize = 100;
+
+#[inline]
+fn squared_distance(c1: &Coordinate, c2: &Coordinate) -> f64 {
+    (c2.lng - c1.lng).powi(2) + (c2.lat - c1.lat).powi(2)
+}
+
+/// Clusters the customers with k-means, where k is the lower bound on the number of vehicles.
+/// The initial centroids are sampled among the customers
+pub fn kmeans_clusters(ctx: &Context) -> Vec<Vec<usize>> {
+    let nodes = &ctx.problem.nodes;
+    let customers: Vec<usize> = (1..ctx.problem.dim()).collect();
+    let k = (ctx.vehicle_lower_bound() as usize)
+        .max(1)
+        .min(customers.len());
+
+    let mut centroids: Vec<Coordinate> = ctx
+        .random
+        .sample_from_vec(customers.clone(), k)
+        .iter()
+        .map(|&customer| nodes[customer].coord)
+        .collect();
+    let mut assignment = vec![0; ctx.problem.dim()];
+
+    for iteration in 0..MAX_KMEANS_ITERATIONS {
+        // Assign every customer to the closest centroid
+        let mut changed = false;
+        for &customer in customers.iter() {
+            let coord = &nodes[customer].coord;
+            let closest = (0..k)
+                .min_by(|&a, &b| {
+                    squared_distance(coord, &centroids[a])
+                        .partial_cmp(&squared_distance(coord, &centroids[b]))
+                        .unwrap()
+                })
+                .expect("No centroids");
+            if iteration == 0 || assignment[customer] != closest {
+                assignment[customer] = closest;
+                changed = true;
+            }
+        }
+        if !changed {
+            break;
+        }
+
+        // Move the centroids to the mean of their customers. Empty clusters keep their centroid
+        let mut sums = vec![(0.0, 0.0, 0usize); k];
+        for &customer in customers.iter() {
+            let sum = &mut sums[assignment[customer]];
+            sum.0 += nodes[customer].coord.lng;
+            sum.1 += nodes[customer].coord.lat;
+            sum.2 += 1;
+        }
+        for (centroid, &(lng, lat, count)) in centroids.iter_mut().zip(sums.iter()) {
+            if count > 0 {
+                centroid.lng = lng / count as f64;
+                centroid.lat = lat / count as f64;
+            }
+        }
+    }
+
+    let mut clusters = vec![Vec::new(); k];
+    for &customer in customers.iter() {
+        clusters[assignment[customer]].push(customer);
+    }
+    clusters.retain(|cluster| !cluster.is_empty());
+    clusters
+}
+
+/// Orders the customers of a cluster by repeatedly visiting the closest unvisited customer,
+/// starting from the depot
+fn nearest_neighbor_sequence(ctx: &Context, cluster: &[usize]) -> Vec<usize> {
+    let distance_matrix = &ctx.matrix_provider.distance;
+    let mut unvisited = cluster.to_vec();
+    let mut sequence = Vec::with_capacity(cluster.len());
+    let mut last_node = 0;
+    while !unvisited.is_empty() {
+        let (index, _) = unvisited
+            .iter()
+            .enumerate()
+            .min_by(|(_, &a), (_, &b)| {
+                distance_matrix
+                    .get(last_node, a)
+                    .partial_cmp(&distance_matrix.get(last_node, b))
+                    .unwrap()
+            })
+            .expect("No unvisited customer");
+        last_node = unvisited.remove(index);
+        sequence.push(last_node);
+    }
+    sequence
+}
+
+/// Constructs an individual from k-means clusters of the customers. Every cluster is
+/// sequenced with nearest neighbor and cut into routes wherever the capacity is exceeded.
+///
+/// If this needs more routes than there are vehicles, the giant tour of the sequences is split
+pub fn construct_kmeans(ctx: &Context, split: &mut Split, number: u64) -> Individual {
+    let capacity = ctx.problem.vehicle.cap;
+    let mut routes: Vec<Vec<usize>> = Vec::new();
+    for cluster in kmeans_clusters(ctx) {
+        let mut route = Vec::new();
+        let mut load = 0.0;
+        for customer in nearest_neighbor_sequence(ctx, &cluster) {
+            let demand = ctx.problem.nodes[customer].demand;
+            if !route.is_empty() && (load + demand).approx_gt(capacity) {
+                routes.push(std::mem::take(&mut route));
+                load = 0.0;
+            }
+            route.push(customer);
+            load += demand;
+        }
+        routes.push(route);
+    }
+    Individual::from_routes(ctx, split, routes, number)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::utils::{coordinate_problem, problem_context};
+
+    #[test]
+    fn kmeans_routes_follow_clusters() {
+        // Two clusters of three customers with a demand of one vehicle each
+        let coords = [
+            (0.0, 0.0),
+            (-50.0, 1.0),
+            (50.0, 0.0),
+            (-51.0, -1.0),
+            (51.0, 1.0),
+            (-49.0, 0.0),
+            (50.0, -2.0),
+        ];
+        let mut config = Config::default();
+        config.deterministic = true;
+        let ctx = problem_context(coordinate_problem(&coords, 3.0), None, config);
+        let mut split = Split::new(&ctx);
+
+        let individual = construct_kmeans(&ctx, &mut split, 0);
+        assert!(individual.is_feasible());
+        let mut routes: Vec<Vec<usize>> = individual
+            .phenotype
+            .iter()
+            .filter(|route| !route.is_empty())
+            .map(|route| {
+                let mut route = route.clone();
+                route.sort_unstable();
+                route
+            })
+            .collect();
+        routes.sort();
+        assert_eq!(vec![vec![1, 3, 5], vec![2, 4, 6]], routes);
+    }
+}
@@ -0,0 +1,11 @@
+mod kmeans;
+pub use self::kmeans::*;
+
+mod nearest_neighbor;
+pub use self::nearest_neighbor::*;
+
+mod savings;
+pub use self::savings::*;
+
+mod sweep;
+pub use self::sweep::*;
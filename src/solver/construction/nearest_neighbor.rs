@@ -0,0 +1,93 @@
+use crate::models::{MatrixProvider, Problem};
+use crate::utils::FloatCompare;
+
+/// Builds routes by repeatedly visiting the closest unvisited customer that fits in the
+/// vehicle, starting a new route from the depot when none fits. Returns the routes and their
+/// total distance, a quick upper bound on the optimal cost.
+///
+/// A customer with a demand above the capacity gets a route of its own
+pub fn nearest_neighbor_tour(
+    problem: &Problem,
+    matrix_provider: &MatrixProvider,
+) -> (Vec<Vec<usize>>, f64) {
+    let distance_matrix = &matrix_provider.distance;
+    let capacity = problem.vehicle.cap;
+    let mut unvisited: Vec<usize> = (1..problem.dim()).collect();
+    let mut routes = Vec::new();
+    let mut route = Vec::new();
+    let mut load = 0.0;
+    let mut last_node = 0;
+    while !unvisited.is_empty() {
+        // Ties are broken by the lowest customer number
+        let closest = unvisited
+            .iter()
+            .enumerate()
+            .filter(|&(_, &customer)| {
+                route.is_empty() || (load + problem.nodes[customer].demand).approx_lte(capacity)
+            })
+            .min_by(|(_, &a), (_, &b)| {
+                distance_matrix
+                    .get(last_node, a)
+                    .partial_cmp(&distance_matrix.get(last_node, b))
+                    .unwrap()
+                    .then(a.cmp(&b))
+            })
+            .map(|(index, _)| index);
+
+        match closest {
+            Some(index) => {
+                last_node = unvisited.remove(index);
+                load += problem.nodes[last_node].demand;
+                route.push(last_node);
+            }
+            None => {
+                routes.push(std::mem::take(&mut route));
+                load = 0.0;
+                last_node = 0;
+            }
+        }
+    }
+    if !route.is_empty() {
+        routes.push(route);
+    }
+
+    let distance = routes
+        .iter()
+        .map(|route| distance_matrix.route_distance(route))
+        .sum();
+    (routes, distance)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solver::genetic::GeneticAlgorithm;
+    use crate::solver::Solver;
+    use crate::utils::instance_context;
+
+    #[test]
+    fn nearest_neighbor_is_feasible_upper_bound() {
+        let ctx = instance_context(|config| {
+            config.max_iterations = Some(100);
+            config.initial_individuals = 10;
+        });
+
+        let (routes, distance) = nearest_neighbor_tour(&ctx.problem, &ctx.matrix_provider);
+        let mut customers: Vec<usize> = routes.iter().flatten().copied().collect();
+        customers.sort_unstable();
+        assert_eq!((1..ctx.problem.dim()).collect::<Vec<_>>(), customers);
+        for route in routes.iter() {
+            let load: f64 = route
+                .iter()
+                .map(|&node| ctx.problem.nodes[node].demand)
+                .sum();
+            assert!(load.approx_lte(ctx.problem.vehicle.cap));
+        }
+
+        let metaheuristic = GeneticAlgorithm::new(&ctx);
+        let mut solver = Solver::new(ctx, metaheuristic);
+        solver.run();
+        let best = solver.metaheuristic.best_solution.unwrap();
+        assert!(distance > best.penalized_cost());
+    }
+}
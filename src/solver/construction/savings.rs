@@ -0,0 +1,105 @@
+use crate::models::{MatrixProvider, Problem};
+use crate::utils::FloatCompare;
+
+/// Builds routes with the parallel savings algorithm of Clarke and Wright. Every customer starts
+/// on a route of its own, and the routes ending in two customers are joined in decreasing order
+/// of the distance saved, as long as the joined route fits in the vehicle.
+///
+/// Only the savings between correlated customers are considered, ties are broken by the lowest
+/// customer numbers
+pub fn savings_routes(problem: &Problem, matrix_provider: &MatrixProvider) -> Vec<Vec<usize>> {
+    let distance_matrix = &matrix_provider.distance;
+    let dim = problem.dim();
+    let mut savings: Vec<(f64, usize, usize)> = Vec::new();
+    for i in 1..dim {
+        for &j in matrix_provider.correlation.get(i) {
+            if i < j {
+                let saving = distance_matrix.get(i, 0) + distance_matrix.get(0, j)
+                    - distance_matrix.get(i, j);
+                savings.push((saving, i, j));
+            }
+        }
+    }
+    savings.sort_by(|a, b| {
+        b.0.partial_cmp(&a.0)
+            .unwrap()
+            .then(a.1.cmp(&b.1))
+            .then(a.2.cmp(&b.2))
+    });
+
+    let mut routes: Vec<Vec<usize>> = (0..dim).map(|customer| vec![customer]).collect();
+    let mut loads: Vec<f64> = problem.nodes.iter().map(|node| node.demand).collect();
+    let mut route_of: Vec<usize> = (0..dim).collect();
+    for &(saving, i, j) in savings.iter() {
+        let (a, b) = (route_of[i], route_of[j]);
+        if saving.approx_lte(0.0) || a == b || (loads[a] + loads[b]).approx_gt(problem.vehicle.cap)
+        {
+            continue;
+        }
+        let is_end = |route: &Vec<usize>, customer: usize| {
+            route.first() == Some(&customer) || route.last() == Some(&customer)
+        };
+        if !is_end(&routes[a], i) || !is_end(&routes[b], j) {
+            continue;
+        }
+
+        // Join the routes such that i is followed by j
+        let mut first = std::mem::take(&mut routes[a]);
+        let mut second = std::mem::take(&mut routes[b]);
+        if first.last() != Some(&i) {
+            first.reverse();
+        }
+        if second.first() != Some(&j) {
+            second.reverse();
+        }
+        for &customer in second.iter() {
+            route_of[customer] = a;
+        }
+        first.extend(second);
+        routes[a] = first;
+        loads[a] += loads[b];
+    }
+
+    routes
+        .into_iter()
+        .skip(1)
+        .filter(|route| !route.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::solver::construction::nearest_neighbor_tour;
+    use crate::utils::coordinate_problem;
+
+    #[test]
+    fn savings_join_routes() {
+        // Two groups of customers on opposite sides of the depot
+        let coords = [
+            (0.0, 0.0),
+            (10.0, 0.0),
+            (11.0, 1.0),
+            (12.0, 0.0),
+            (-10.0, 0.0),
+            (-11.0, 1.0),
+        ];
+        let problem = coordinate_problem(&coords, 3.0);
+        let matrix_provider = MatrixProvider::new(&problem, &Config::default(), None);
+
+        let routes = savings_routes(&problem, &matrix_provider);
+        let mut customers: Vec<usize> = routes.iter().flatten().copied().collect();
+        customers.sort_unstable();
+        assert_eq!(vec![1, 2, 3, 4, 5], customers);
+        assert_eq!(2, routes.len());
+        assert!(routes.iter().all(|route| route.len() <= 3));
+
+        let distance: f64 = routes
+            .iter()
+            .map(|route| matrix_provider.distance.route_distance(route))
+            .sum();
+        let (_, nearest_neighbor_distance) = nearest_neighbor_tour(&problem, &matrix_provider);
+        assert!(distance.approx_lte(nearest_neighbor_distance));
+    }
+}
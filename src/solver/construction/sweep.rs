@@ -0,0 +1,55 @@
+use std::f64::consts::PI;
+
+use crate::models::Problem;
+use crate::utils::FloatCompare;
+
+/// Builds routes by sweeping a ray around the depot, starting at `start_angle` in radians.
+/// The customers are visited in the order the ray passes them and a new route is started
+/// whenever the next customer does not fit in the vehicle. Ties are broken by customer number
+pub fn sweep_routes(problem: &Problem, start_angle: f64) -> Vec<Vec<usize>> {
+    let depot = &problem.nodes[0].coord;
+    let angle = |customer: usize| {
+        let coord = &problem.nodes[customer].coord;
+        ((coord.lat - depot.lat).atan2(coord.lng - depot.lng) - start_angle).rem_euclid(2.0 * PI)
+    };
+    let mut customers: Vec<(f64, usize)> = (1..problem.dim())
+        .map(|customer| (angle(customer), customer))
+        .collect();
+    customers.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap().then(a.1.cmp(&b.1)));
+
+    let mut routes = Vec::new();
+    let mut route = Vec::new();
+    let mut load = 0.0;
+    for &(_, customer) in customers.iter() {
+        let demand = problem.nodes[customer].demand;
+        if !route.is_empty() && (load + demand).approx_gt(problem.vehicle.cap) {
+            routes.push(std::mem::take(&mut route));
+            load = 0.0;
+        }
+        route.push(customer);
+        load += demand;
+    }
+    if !route.is_empty() {
+        routes.push(route);
+    }
+    routes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::coordinate_problem;
+
+    #[test]
+    fn sweep_order() {
+        // Customers at the four compass points
+        let coords = [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0), (-1.0, 0.0), (0.0, -1.0)];
+        let problem = coordinate_problem(&coords, 2.0);
+
+        assert_eq!(vec![vec![1, 2], vec![3, 4]], sweep_routes(&problem, 0.0));
+        assert_eq!(
+            vec![vec![2, 3], vec![4, 1]],
+            sweep_routes(&problem, PI / 4.0)
+        );
+    }
+}
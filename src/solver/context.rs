@@ -1,11 +1,34 @@
 use std::cell::RefCell;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 use crate::config::Config;
-use crate::models::{MatrixProvider, Problem};
-use crate::solver::SearchHistory;
+use crate::models::{MatrixMode, MatrixProvider, Problem, CORRELATION_LIMIT};
+use crate::solver::improvement::MAX_EXACT_ROUTE;
+use crate::solver::{LegCost, SearchHistory};
 use crate::utils::{ProblemParser, Random};
 
+/// Setting chosen by the solver instead of the configured or default value
+#[derive(Clone, Debug, PartialEq)]
+pub struct Adjustment {
+    pub field: String,
+    pub original: String,
+    pub chosen: String,
+    pub reason: String,
+}
+
+impl Adjustment {
+    fn new(field: &str, original: impl ToString, chosen: impl ToString, reason: String) -> Self {
+        Self {
+            field: field.to_owned(),
+            original: original.to_string(),
+            chosen: chosen.to_string(),
+            reason,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Context {
     pub problem: Problem,
@@ -14,34 +37,147 @@ pub struct Context {
     pub random: Random,
     pub search_history: RefCell<SearchHistory>,
     pub iteration: RefCell<u64>,
+    // Set when the hard deadline is reached. Shared with the watchdog thread and subproblems
+    pub deadline: Arc<AtomicBool>,
+    // Settings adjusted to the problem when the context was created
+    pub adjustments: Vec<Adjustment>,
+    // Cost of every leg, summed into the emissions of a solution
+    pub leg_cost: Option<LegCost>,
 }
 
 impl Context {
-    pub fn new(mut parser: ProblemParser, config: Config, start_time: Instant) -> Self {
+    pub fn new(mut parser: ProblemParser, mut config: Config, start_time: Instant) -> Self {
         let problem = parser.problem.take().expect("Failed to parse problem");
-        let random = if config.deterministic {
+        let random = if config.deterministic || config.rng_state_in.is_some() {
             log::info!("Deterministic with seed: {}", config.seed);
             Random::from_seed(config.seed)
         } else {
             Random::new()
         };
+        if let Some(state) = config.rng_state_in {
+            log::info!("Continuing from random state: {}", state);
+            random.set_state(state);
+        }
+
+        // The replayed stream takes precedence over recording
+        if let Some(draws) = config.replay_rng.take() {
+            log::info!("Replaying {} random draws", draws.len());
+            random.replay(draws);
+        } else if config.record_rng {
+            random.start_recording();
+        }
 
         let matrix_provider = MatrixProvider::new(&problem, &config, parser.matrix.take());
-        log::info!("Matrices built!");
+        log::info!("Matrices built! {:?}", matrix_provider.stats());
+        let mut adjustments = Self::matrix_adjustments(&problem, &config, &matrix_provider);
+        if config.exact_route_threshold > MAX_EXACT_ROUTE {
+            adjustments.push(Adjustment::new(
+                "exact_route_threshold",
+                config.exact_route_threshold,
+                MAX_EXACT_ROUTE,
+                "Held-Karp takes time and memory exponential in the route length".to_owned(),
+            ));
+            config.exact_route_threshold = MAX_EXACT_ROUTE;
+        }
+        assert!(
+            config
+                .checkpoints
+                .iter()
+                .all(|checkpoint| *checkpoint >= 0.0),
+            "Checkpoints must be non-negative times in seconds: {:?}",
+            config.checkpoints
+        );
+        let mut checkpoints = config.checkpoints.clone();
+        checkpoints.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        if checkpoints != config.checkpoints {
+            adjustments.push(Adjustment::new(
+                "checkpoints",
+                format!("{:?}", config.checkpoints),
+                format!("{:?}", checkpoints),
+                "Checkpoints are captured in order of time".to_owned(),
+            ));
+            config.checkpoints = checkpoints;
+        }
+        let configured_num_vehicles = config.num_vehicles;
+        let configured_penalty = config.penalty_capacity;
 
-        let context = Self {
+        let mut context = Self {
             problem,
             matrix_provider,
             config: RefCell::new(config),
             random,
             search_history: RefCell::new(SearchHistory::new(start_time)),
             iteration: RefCell::new(0),
+            deadline: Arc::new(AtomicBool::new(false)),
+            adjustments,
+            leg_cost: None,
         };
 
         context.setup();
+        let config = context.config.borrow().clone();
+        if config.num_vehicles != configured_num_vehicles {
+            let reason = format!(
+                "20% + 2 above the bin packing lower bound of {} vehicles",
+                context.vehicle_lower_bound()
+            );
+            context.adjustments.push(Adjustment::new(
+                "num_vehicles",
+                configured_num_vehicles,
+                config.num_vehicles,
+                reason,
+            ));
+        }
+        if config.penalty_capacity != configured_penalty {
+            context.adjustments.push(Adjustment::new(
+                "penalty_capacity",
+                configured_penalty,
+                config.penalty_capacity,
+                "Estimated from the largest distance and demand".to_owned(),
+            ));
+        }
+        for adjustment in context.adjustments.iter() {
+            log::info!("Adjusted: {:?}", adjustment);
+        }
         context
     }
 
+    // Storage of the distances and width of the correlation matrix chosen for the problem size
+    fn matrix_adjustments(
+        problem: &Problem,
+        config: &Config,
+        matrix_provider: &MatrixProvider,
+    ) -> Vec<Adjustment> {
+        let stats = matrix_provider.stats();
+        let mut adjustments = Vec::new();
+        if stats.mode != MatrixMode::Precomputed {
+            let reason = format!(
+                "{} customers exceed the precompute_distance_size_limit of {}, \
+                 which bounds the memory of the distance matrix",
+                problem.num_customers(),
+                config.precompute_distance_size_limit
+            );
+            adjustments.push(Adjustment::new(
+                "distance_mode",
+                format!("{:?}", MatrixMode::Precomputed),
+                format!("{:?}", stats.mode),
+                reason,
+            ));
+        }
+        if stats.correlation_width < CORRELATION_LIMIT {
+            let reason = format!(
+                "Each customer has only {} other customers",
+                stats.correlation_width
+            );
+            adjustments.push(Adjustment::new(
+                "correlation_width",
+                CORRELATION_LIMIT,
+                stats.correlation_width,
+                reason,
+            ));
+        }
+        adjustments
+    }
+
     pub fn setup(&self) {
         self.config.borrow_mut().num_vehicles = self.initial_num_vehicles();
         self.reset_penalty();
@@ -59,9 +195,16 @@ impl Context {
         self.search_history.borrow().start_time.elapsed()
     }
 
+    /// Returns true if the hard deadline has been signalled
+    #[inline]
+    pub fn deadline_reached(&self) -> bool {
+        self.deadline.load(Ordering::Relaxed)
+    }
+
     pub fn terminate(&self) -> bool {
         let config = self.config.borrow();
-        self.elapsed_as_secs() >= config.time_limit
+        self.deadline_reached()
+            || self.elapsed_as_secs() >= config.time_limit
             || config.max_iterations.map_or(false, |max_iterations| {
                 *self.iteration.borrow() >= max_iterations
             })
@@ -110,6 +253,40 @@ impl Context {
             random: self.random.clone(),
             search_history: RefCell::new(search_history),
             iteration: RefCell::new(0),
+            deadline: self.deadline.clone(),
+            adjustments: Vec::new(),
+            leg_cost: self.leg_cost.clone(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::instance_context;
+
+    #[test]
+    fn lazy_distances_are_reported() {
+        let ctx = instance_context(|config| config.precompute_distance_size_limit = 50);
+        let adjustment = ctx
+            .adjustments
+            .iter()
+            .find(|adjustment| adjustment.field == "distance_mode")
+            .expect("No adjustment of the distance mode");
+        assert_eq!("Precomputed", adjustment.original);
+        assert_eq!("Lazy", adjustment.chosen);
+        assert!(adjustment
+            .reason
+            .contains("precompute_distance_size_limit of 50"));
+
+        let ctx = instance_context(|config| config.precompute_distance_size_limit = 1_000);
+        let fields: Vec<&str> = ctx
+            .adjustments
+            .iter()
+            .map(|adjustment| adjustment.field.as_str())
+            .collect();
+        assert_eq!(
+            vec!["correlation_width", "num_vehicles", "penalty_capacity"],
+            fields
+        );
+    }
+}
@@ -1,9 +1,198 @@
+use std::fmt::{self, Debug};
+use std::sync::Arc;
+
+use crate::config::DiagonalPolicy;
+use crate::models::{MatrixProvider, Problem};
 use crate::solver::Context;
 use crate::utils::FloatCompare;
 
+/// Penalty for the overload of a route. Without tiers, every unit of overload costs `penalty`.
+/// Each tier (threshold, factor) scales the per-unit penalty by `factor` for the overload
+/// beyond `threshold` times the capacity, up to the threshold of the next tier. The tiers are
+/// sorted by threshold, and the overload below the first threshold is not scaled
+#[inline]
+pub fn capacity_penalty(overload: f64, capacity: f64, penalty: f64, tiers: &[(f64, f64)]) -> f64 {
+    if tiers.is_empty() {
+        return penalty * 0f64.max(overload);
+    }
+    let mut scaled_overload = 0.0;
+    let mut tier_start = 0.0;
+    let mut factor = 1.0;
+    for &(threshold, tier_factor) in tiers.iter() {
+        let tier_end = threshold * capacity;
+        if overload <= tier_end {
+            break;
+        }
+        scaled_overload += factor * (tier_end - tier_start);
+        tier_start = tier_end;
+        factor = tier_factor;
+    }
+    penalty * 0f64.max(scaled_overload + factor * (overload - tier_start))
+}
+
+#[inline]
+pub fn route_cost(
+    distance: f64,
+    overload: f64,
+    capacity: f64,
+    penalty: f64,
+    tiers: &[(f64, f64)],
+) -> f64 {
+    distance + capacity_penalty(overload, capacity, penalty, tiers)
+}
+
+/// Penalty for a non-empty route visiting fewer than `min_customers` customers
+#[inline]
+pub fn short_route_penalty(
+    num_customers: usize,
+    min_customers: Option<usize>,
+    penalty: f64,
+) -> f64 {
+    match min_customers {
+        Some(min_customers) if num_customers > 0 && num_customers < min_customers => penalty,
+        _ => 0.0,
+    }
+}
+
+/// Penalty for a route visiting `locations` distinct locations, for each location beyond
+/// `max_locations`
 #[inline]
-pub fn route_cost(distance: f64, overload: f64, penalty: f64) -> f64 {
-    distance + penalty * 0f64.max(overload)
+pub fn location_penalty(locations: usize, max_locations: Option<usize>, penalty: f64) -> f64 {
+    match max_locations {
+        Some(max_locations) if locations > max_locations => {
+            penalty * (locations - max_locations) as f64
+        }
+        _ => 0.0,
+    }
+}
+
+/// Cost of serving each customer on a route of its own, the round trip from the depot. The
+/// baseline for the savings of consolidating customers. Indexed by node, the depot costs zero
+pub fn standalone_costs(problem: &Problem, matrix_provider: &MatrixProvider) -> Vec<f64> {
+    (0..problem.dim())
+        .map(|node| match node {
+            0 => 0.0,
+            _ => 2.0 * matrix_provider.distance.get(0, node),
+        })
+        .collect()
+}
+
+/// Lower bound on the distance of any solution with symmetric distances. A solution with `m`
+/// routes visits the depot `2m` times and connects the customers by `m` paths, so it costs at
+/// least the `2m` shortest depot edges, each used at most twice, plus a minimum spanning forest
+/// of the customers with `m` components. The forest is the minimum spanning tree from Prim's
+/// algorithm without its `m - 1` longest edges. The bound is the smallest over all `m` from the
+/// bin packing lower bound on the number of vehicles
+pub fn mst_lower_bound(problem: &Problem, matrix_provider: &MatrixProvider) -> f64 {
+    let distance_matrix = &matrix_provider.distance;
+    let num_customers = problem.num_customers();
+    if num_customers == 0 {
+        return 0.0;
+    }
+
+    // Prim's algorithm over the customers
+    let mut in_tree = vec![false; problem.dim()];
+    let mut connection = vec![f64::INFINITY; problem.dim()];
+    let mut tree_edges = Vec::with_capacity(num_customers - 1);
+    connection[1] = 0.0;
+    for step in 0..num_customers {
+        let next = (1..problem.dim())
+            .filter(|&customer| !in_tree[customer])
+            .min_by(|&a, &b| connection[a].partial_cmp(&connection[b]).unwrap())
+            .expect("No customer outside of the tree");
+        in_tree[next] = true;
+        if step > 0 {
+            tree_edges.push(connection[next]);
+        }
+        for customer in 1..problem.dim() {
+            if !in_tree[customer] {
+                connection[customer] =
+                    connection[customer].min(distance_matrix.get(next, customer));
+            }
+        }
+    }
+    tree_edges.sort_by(|a, b| b.partial_cmp(a).unwrap());
+    let mut depot_edges: Vec<f64> = (1..problem.dim())
+        .map(|customer| distance_matrix.get(0, customer))
+        .collect();
+    depot_edges.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+    let min_routes = ((problem.total_demand() / problem.vehicle.cap).ceil() as usize).max(1);
+    let tree: f64 = tree_edges.iter().sum();
+    (min_routes.min(num_customers)..=num_customers)
+        .map(|routes| {
+            let forest = tree - tree_edges.iter().take(routes - 1).sum::<f64>();
+            let depot = 2.0 * depot_edges.iter().take(routes).sum::<f64>();
+            forest + depot
+        })
+        .fold(f64::INFINITY, f64::min)
+}
+
+/// Cost of a leg from the node it starts at, the node it ends at, the load carried on it and its
+/// distance. The load is the demand of the customers not yet visited on the route
+#[derive(Clone)]
+pub struct LegCost(Arc<dyn Fn(usize, usize, f64, f64) -> f64 + Send + Sync>);
+
+impl LegCost {
+    pub fn new(cost: impl Fn(usize, usize, f64, f64) -> f64 + Send + Sync + 'static) -> Self {
+        Self(Arc::new(cost))
+    }
+
+    /// Total cost of the legs of a route starting and ending at the depot
+    pub fn route_cost(&self, ctx: &Context, route: &[usize]) -> f64 {
+        let mut load: f64 = route
+            .iter()
+            .map(|&node| ctx.problem.nodes[node].demand)
+            .sum();
+        let mut last_node = 0;
+        let mut cost = 0.0;
+        for &node in route.iter().chain(std::iter::once(&0)) {
+            let distance = ctx.matrix_provider.distance.get(last_node, node);
+            cost += (self.0)(last_node, node, load, distance);
+            load -= ctx.problem.nodes[node].demand;
+            last_node = node;
+        }
+        cost
+    }
+}
+
+impl Debug for LegCost {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "LegCost")
+    }
+}
+
+/// Constraint broken by a change of a solution
+#[derive(Clone, Debug, PartialEq)]
+pub enum ConstraintViolation {
+    // Load of the route and the vehicle capacity
+    Capacity(f64, f64),
+    // Distinct locations on the route and the maximum per route
+    DistinctLocations(usize, usize),
+    // Customer of the same cluster on another route
+    Cluster(usize),
+}
+
+impl fmt::Display for ConstraintViolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Capacity(load, capacity) => {
+                write!(f, "Load {} exceeds the capacity {}", load, capacity)
+            }
+            Self::DistinctLocations(locations, max) => write!(
+                f,
+                "{} distinct locations exceed the maximum of {}",
+                locations, max
+            ),
+            Self::Cluster(customer) => {
+                write!(
+                    f,
+                    "Customer {} of the same cluster is on another route",
+                    customer
+                )
+            }
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -33,6 +222,15 @@ pub struct SolutionEvaluation {
     pub penalized_cost: f64,
     pub feasible: bool,
 
+    // Total penalty for routes with too few customers. Included in the penalized cost
+    pub short_route_penalty: f64,
+
+    // Penalty for the overlap of the route bounding boxes. Included in the penalized cost
+    pub overlap_penalty: f64,
+
+    // Total leg cost of the context. Included in the penalized cost with the emissions weight
+    pub emissions: f64,
+
     // Evaluation of routes
     pub routes: Vec<RouteEvaluation>,
 
@@ -47,6 +245,9 @@ impl SolutionEvaluation {
         Self {
             penalized_cost: f64::INFINITY,
             feasible: false,
+            short_route_penalty: 0.0,
+            overlap_penalty: 0.0,
+            emissions: 0.0,
             routes: Vec::new(),
             predecessors: Vec::new(),
             successors: Vec::new(),
@@ -57,6 +258,11 @@ impl SolutionEvaluation {
         self.feasible
     }
 
+    // Total distance of the routes, without any penalties
+    pub fn distance(&self) -> f64 {
+        self.routes.iter().map(|route| route.distance).sum()
+    }
+
     fn reset(&mut self, num_routes: usize, num_nodes: usize) {
         self.routes.resize(num_routes, RouteEvaluation::empty());
         self.predecessors.resize(num_nodes, 0);
@@ -71,9 +277,16 @@ impl SolutionEvaluation {
         let depot_node = 0;
         let capacity = ctx.problem.vehicle.cap;
         let penalty_capacity = ctx.config.borrow().penalty_capacity;
+        let capacity_tiers = ctx.config.borrow().capacity_penalty_tiers.clone();
+        let min_customers = ctx.config.borrow().min_customers_per_route;
+        let penalty_short_route = ctx.config.borrow().short_route_penalty;
+        let honor_diagonal = ctx.config.borrow().diagonal_policy == DiagonalPolicy::Honor;
+        let penalty_overlap = ctx.config.borrow().overlap_penalty;
+        let max_locations = ctx.config.borrow().max_distinct_locations_per_route;
 
         // Total cost of the solution
         let mut total_penalized_cost: f64 = 0.0;
+        let mut total_short_route_penalty: f64 = 0.0;
 
         // If the solution is feasible
         let mut feasible = true;
@@ -89,6 +302,9 @@ impl SolutionEvaluation {
             for &node in route.iter() {
                 // Update distance
                 route_distance += ctx.matrix_provider.distance.get(last_node, node);
+                if honor_diagonal {
+                    route_distance += ctx.matrix_provider.distance.get(node, node);
+                }
 
                 // Update load on route
                 load += ctx.problem.nodes[node].demand;
@@ -113,17 +329,256 @@ impl SolutionEvaluation {
             self.routes[route_index].distance = route_distance;
             self.routes[route_index].overload = overload;
 
+            // Co-located customers are a single location for the driver
+            let locations = match max_locations {
+                Some(_) => ctx.problem.num_locations(route),
+                None => 0,
+            };
+
             // Add the penalized cost
-            self.routes[route_index].penalized_cost =
-                route_cost(route_distance, overload, penalty_capacity);
+            let short_penalty =
+                short_route_penalty(route.len(), min_customers, penalty_short_route);
+            self.routes[route_index].penalized_cost = route_cost(
+                route_distance,
+                overload,
+                capacity,
+                penalty_capacity,
+                &capacity_tiers,
+            ) + short_penalty
+                + location_penalty(locations, max_locations, penalty_capacity);
             total_penalized_cost += self.routes[route_index].penalized_cost;
+            total_short_route_penalty += short_penalty;
 
             if overload.approx_gt(0.0) {
                 // Update feasibility if the capacity is violated
                 feasible = false;
             }
+
+            // Update feasibility if the route visits too many locations
+            if let Some(max_locations) = max_locations {
+                if locations > max_locations {
+                    feasible = false;
+                }
+            }
         }
+
+        // Penalize routes covering the same area
+        self.overlap_penalty = if penalty_overlap > 0.0 {
+            penalty_overlap
+                * ctx
+                    .problem
+                    .route_overlap_area(solution.iter().map(|route| route.as_slice()))
+        } else {
+            0.0
+        };
+
+        self.emissions = match &ctx.leg_cost {
+            Some(leg_cost) => solution
+                .iter()
+                .filter(|route| !route.is_empty())
+                .map(|route| leg_cost.route_cost(ctx, route))
+                .sum(),
+            None => 0.0,
+        };
+
         self.feasible = feasible;
-        self.penalized_cost = total_penalized_cost;
+        self.penalized_cost = total_penalized_cost
+            + self.overlap_penalty
+            + ctx.config.borrow().emissions_weight * self.emissions;
+        self.short_route_penalty = total_short_route_penalty;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::utils::{coordinate_problem, demand_problem, problem_context};
+
+    fn evaluate_with_diagonal(diagonal_policy: DiagonalPolicy) -> SolutionEvaluation {
+        let problem = coordinate_problem(&[(0.0, 0.0); 4], 10.0);
+        // Lower rows including the diagonal, which is the cost of visiting the customer
+        let matrix = vec![
+            vec![10.0, 1.0],
+            vec![10.0, 5.0, 2.0],
+            vec![20.0, 15.0, 15.0, 3.0],
+        ];
+        let mut config = Config::default();
+        config.diagonal_policy = diagonal_policy;
+        let ctx = problem_context(problem, Some(matrix), config);
+
+        let mut evaluation = SolutionEvaluation::new();
+        evaluation.evaluate(&ctx, &vec![vec![1, 2], vec![3]]);
+        evaluation
+    }
+
+    #[test]
+    fn emissions_from_leg_cost() {
+        // Customers on a line with demands 1, 2 and 3
+        let coords = [(0.0, 0.0), (1.0, 0.0), (2.0, 0.0), (3.0, 0.0)];
+        let problem = demand_problem(&coords, &[1.0, 2.0, 3.0], 10.0);
+        let mut ctx = problem_context(problem, None, Config::default());
+        let solution = vec![vec![1, 2], vec![3]];
+
+        let mut evaluation = SolutionEvaluation::new();
+        evaluation.evaluate(&ctx, &solution);
+        assert_eq!(0.0, evaluation.emissions);
+        let penalized_cost = evaluation.penalized_cost;
+
+        ctx.leg_cost = Some(LegCost::new(|_, _, load, distance| 0.5 * load * distance));
+        evaluation.evaluate(&ctx, &solution);
+        // Legs 0-1, 1-2 and 2-0 with loads 3, 2 and 0, and legs 0-3 and 3-0 with loads 3 and 0
+        let expected = 0.5 * (3.0 * 1.0 + 2.0 * 1.0 + 0.0 * 2.0 + 3.0 * 3.0 + 0.0 * 3.0);
+        assert!(evaluation.emissions.approx_eq(expected));
+        assert_eq!(penalized_cost, evaluation.penalized_cost);
+
+        ctx.config.borrow_mut().emissions_weight = 2.0;
+        evaluation.evaluate(&ctx, &solution);
+        assert!(evaluation
+            .penalized_cost
+            .approx_eq(penalized_cost + 2.0 * expected));
+    }
+
+    // Customers 1 to 3 are orders at the same location, customer 4 is elsewhere
+    fn colocated_context(max_locations: Option<usize>) -> Context {
+        let coords = [(1.0, 0.0), (1.0, 0.0), (1.0, 0.0), (1.0, 0.0), (2.0, 0.0)];
+        let mut config = Config::default();
+        config.max_distinct_locations_per_route = max_locations;
+        problem_context(coordinate_problem(&coords, 10.0), None, config)
+    }
+
+    #[test]
+    fn max_distinct_locations() {
+        let ctx = colocated_context(Some(1));
+        assert_eq!(1, ctx.problem.num_locations(&[1, 2, 3]));
+        assert_eq!(2, ctx.problem.num_locations(&[1, 2, 3, 4]));
+
+        let mut evaluation = SolutionEvaluation::new();
+        evaluation.evaluate(&ctx, &vec![vec![1, 2, 3], vec![4]]);
+        assert!(evaluation.is_feasible());
+        let distance = evaluation.distance();
+        evaluation.evaluate(&ctx, &vec![vec![1, 2], vec![3, 4]]);
+        assert!(!evaluation.is_feasible());
+        // The second location of the route costs the capacity penalty
+        let penalty_capacity = ctx.config.borrow().penalty_capacity;
+        assert!(evaluation
+            .penalized_cost
+            .approx_eq(distance + penalty_capacity));
+
+        let ctx = colocated_context(None);
+        evaluation.evaluate(&ctx, &vec![vec![1, 2, 3, 4]]);
+        assert!(evaluation.is_feasible());
+    }
+
+    #[test]
+    fn capacity_penalty_tiers() {
+        // Cheap up to 5% over the capacity, steep beyond 20%
+        let tiers = [(0.0, 0.1), (0.05, 1.0), (0.2, 10.0)];
+        let samples = [
+            (-5.0, 0.0),
+            (3.0, 3.0),
+            (5.0, 5.0),
+            (10.0, 55.0),
+            (20.0, 155.0),
+            (30.0, 1155.0),
+        ];
+        for &(overload, penalty) in samples.iter() {
+            let tiered = capacity_penalty(overload, 100.0, 10.0, &tiers);
+            assert!(tiered.approx_eq(penalty), "{} {}", overload, tiered);
+        }
+        assert_eq!(300.0, capacity_penalty(30.0, 100.0, 10.0, &[]));
+        assert_eq!(0.0, capacity_penalty(-5.0, 100.0, 10.0, &[]));
+    }
+
+    #[test]
+    fn diagonal_policy() {
+        let evaluation = evaluate_with_diagonal(DiagonalPolicy::AssumeZero);
+        assert_eq!(25.0, evaluation.routes[0].distance);
+        assert_eq!(40.0, evaluation.routes[1].distance);
+        assert_eq!(65.0, evaluation.penalized_cost);
+
+        let evaluation = evaluate_with_diagonal(DiagonalPolicy::Honor);
+        assert_eq!(28.0, evaluation.routes[0].distance);
+        assert_eq!(43.0, evaluation.routes[1].distance);
+        assert_eq!(71.0, evaluation.penalized_cost);
+    }
+
+    #[test]
+    fn mst_lower_bound_brackets_optimum() {
+        use crate::solver::genetic::{Individual, Split};
+
+        let coords = [
+            (0.0, 0.0),
+            (10.0, 1.0),
+            (12.0, 4.0),
+            (9.0, 7.0),
+            (-8.0, 3.0),
+            (-11.0, -2.0),
+            (-1.0, -9.0),
+        ];
+        let mut config = Config::default();
+        config.round_distances = false;
+        let ctx = problem_context(coordinate_problem(&coords, 3.0), None, config);
+
+        fn permutations(items: &[usize]) -> Vec<Vec<usize>> {
+            if items.len() <= 1 {
+                return vec![items.to_vec()];
+            }
+            let mut result = Vec::new();
+            for (index, &first) in items.iter().enumerate() {
+                let mut rest = items.to_vec();
+                rest.remove(index);
+                for mut tail in permutations(&rest) {
+                    tail.insert(0, first);
+                    result.push(tail);
+                }
+            }
+            result
+        }
+
+        // The optimal split of the best giant tour is optimal
+        let mut split = Split::new(&ctx);
+        let mut optimum = f64::INFINITY;
+        let customers: Vec<usize> = (1..ctx.problem.dim()).collect();
+        for tour in permutations(&customers) {
+            let mut individual = Individual::new(tour, 0);
+            split.run(&ctx, &mut individual, ctx.config.borrow().num_vehicles);
+            if individual.is_feasible() {
+                optimum = optimum.min(individual.penalized_cost());
+            }
+        }
+
+        let bound = mst_lower_bound(&ctx.problem, &ctx.matrix_provider);
+        let closest = (1..ctx.problem.dim())
+            .map(|customer| ctx.matrix_provider.distance.get(0, customer))
+            .fold(f64::INFINITY, f64::min);
+        let bin_packing_bound = 2.0 * ctx.vehicle_lower_bound() as f64 * closest;
+        assert!(bound.approx_lte(optimum));
+        assert!(bound > bin_packing_bound);
+    }
+
+    #[test]
+    fn standalone_costs_are_round_trips() {
+        let coords = [(0.0, 0.0), (3.0, 4.0), (30.0, 40.0), (-6.0, 8.0)];
+        let problem = coordinate_problem(&coords, 10.0);
+        for &limit in [2_000, 0].iter() {
+            let mut config = Config::default();
+            config.precompute_distance_size_limit = limit;
+            let matrix_provider = MatrixProvider::new(&problem, &config, None);
+            assert_eq!(limit > 0, matrix_provider.distance.is_precomputed());
+
+            let costs = standalone_costs(&problem, &matrix_provider);
+            assert_eq!(vec![0.0, 10.0, 100.0, 20.0], costs);
+            for customer in 1..problem.dim() {
+                assert_eq!(
+                    2.0 * matrix_provider.distance.get(0, customer),
+                    costs[customer]
+                );
+            }
+            let farthest = (1..problem.dim())
+                .max_by(|&a, &b| costs[a].partial_cmp(&costs[b]).unwrap())
+                .unwrap();
+            assert_eq!(2, farthest);
+        }
     }
 }
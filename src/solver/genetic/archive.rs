@@ -0,0 +1,155 @@
+use std::collections::{BTreeMap, HashMap};
+use std::hash::BuildHasher;
+
+use ahash::RandomState;
+
+use crate::solver::genetic::Individual;
+
+/// Key of an individual in the archive: its non-empty routes in sorted order and their hash
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArchiveKey {
+    fingerprint: u64,
+    routes: Vec<Vec<usize>>,
+}
+
+impl ArchiveKey {
+    pub fn new(individual: &Individual) -> Self {
+        let mut routes: Vec<Vec<usize>> = individual
+            .phenotype
+            .iter()
+            .filter(|route| !route.is_empty())
+            .cloned()
+            .collect();
+        routes.sort_unstable();
+        Self {
+            fingerprint: RandomState::with_seeds(0, 0, 0, 0).hash_one(&routes),
+            routes,
+        }
+    }
+}
+
+/// Educated individuals by the routes before the education, such that offspring seen before
+/// take the cached result instead of being educated again. Holds at most `capacity`
+/// individuals and evicts the least recently used
+#[derive(Debug, Clone)]
+pub struct SolutionArchive {
+    capacity: usize,
+    // Routes before the education, individual and the time it was last used by fingerprint
+    entries: HashMap<u64, (Vec<Vec<usize>>, Individual, u64), RandomState>,
+    // Fingerprint of the entries by the time they were last used
+    last_used: BTreeMap<u64, u64>,
+    clock: u64,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl SolutionArchive {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: HashMap::with_hasher(RandomState::with_seeds(0, 0, 0, 0)),
+            last_used: BTreeMap::new(),
+            clock: 0,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.capacity > 0
+    }
+
+    /// Returns the individual with the key and marks it as used. An individual with the same
+    /// fingerprint but other routes is not returned
+    pub fn get(&mut self, key: &ArchiveKey) -> Option<&Individual> {
+        self.clock += 1;
+        match self.entries.get_mut(&key.fingerprint) {
+            Some((routes, individual, last_used)) if *routes == key.routes => {
+                self.last_used.remove(last_used);
+                self.last_used.insert(self.clock, key.fingerprint);
+                *last_used = self.clock;
+                self.hits += 1;
+                Some(individual)
+            }
+            _ => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    /// Adds the individual, evicting the least recently used one if the archive is full. An
+    /// individual with the same fingerprint is replaced
+    pub fn insert(&mut self, key: ArchiveKey, individual: Individual) {
+        if !self.is_enabled() {
+            return;
+        }
+        self.clock += 1;
+        if let Some((_, _, last_used)) = self.entries.remove(&key.fingerprint) {
+            self.last_used.remove(&last_used);
+        } else if self.entries.len() >= self.capacity {
+            if let Some((_, oldest)) = self.last_used.pop_first() {
+                self.entries.remove(&oldest);
+            }
+        }
+        self.last_used.insert(self.clock, key.fingerprint);
+        self.entries
+            .insert(key.fingerprint, (key.routes, individual, self.clock));
+    }
+
+    pub fn individuals(&self) -> impl Iterator<Item = &Individual> {
+        self.entries.values().map(|(_, individual, _)| individual)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn individual(routes: Vec<Vec<usize>>) -> Individual {
+        let mut individual = Individual::new(routes.iter().flatten().copied().collect(), 0);
+        individual.phenotype = routes;
+        individual
+    }
+
+    #[test]
+    fn fingerprint_collision_is_a_miss() {
+        let mut archive = SolutionArchive::new(2);
+        let first = individual(vec![vec![1, 2], vec![3]]);
+        archive.insert(ArchiveKey::new(&first), first.clone());
+
+        // Same routes in another order and with empty routes
+        let same = individual(vec![vec![3], Vec::new(), vec![1, 2]]);
+        assert!(archive.get(&ArchiveKey::new(&same)).is_some());
+
+        let mut colliding = ArchiveKey::new(&individual(vec![vec![2, 1], vec![3]]));
+        colliding.fingerprint = ArchiveKey::new(&first).fingerprint;
+        assert!(archive.get(&colliding).is_none());
+        assert_eq!((1, 1), (archive.hits, archive.misses));
+    }
+
+    #[test]
+    fn least_recently_used_is_evicted() {
+        let mut archive = SolutionArchive::new(2);
+        let individuals: Vec<Individual> =
+            (1..=3).map(|node| individual(vec![vec![node]])).collect();
+        archive.insert(ArchiveKey::new(&individuals[0]), individuals[0].clone());
+        archive.insert(ArchiveKey::new(&individuals[1]), individuals[1].clone());
+        assert!(archive.get(&ArchiveKey::new(&individuals[0])).is_some());
+        archive.insert(ArchiveKey::new(&individuals[2]), individuals[2].clone());
+
+        assert_eq!(2, archive.len());
+        assert!(archive.get(&ArchiveKey::new(&individuals[1])).is_none());
+        assert!(archive.get(&ArchiveKey::new(&individuals[0])).is_some());
+        assert!(archive.get(&ArchiveKey::new(&individuals[2])).is_some());
+        assert_eq!(2, archive.last_used.len());
+    }
+}
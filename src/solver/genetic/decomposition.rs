@@ -39,7 +39,7 @@ impl DecomposedGeneticAlgorithm {
         let mut child = Individual::new_random(ctx, 0);
         ctx.config.borrow_mut().penalty_capacity *= 1_000_000.0;
         log::info!("Running split");
-        self.global_ga.split.run(ctx, &mut child, max_routes);
+        child.routes(ctx, &mut self.global_ga.split, max_routes);
         self.global_ga.child = child;
         self.global_ga.educate(ctx);
         self.global_ga
@@ -239,15 +239,13 @@ impl<'a> Decomposition<'a> {
             }
         }
 
-        let new_genotype: Vec<usize> = new_phenotype.iter().flatten().copied().collect();
         let extra_empty_routes = ((new_phenotype.len() as f64 / 10.0) + 2.0).round() as usize;
         for _ in 0..extra_empty_routes {
             new_phenotype.push(Vec::new());
         }
 
         let mut sub_solution = Individual::empty();
-        sub_solution.genotype = new_genotype;
-        sub_solution.phenotype = new_phenotype;
+        sub_solution.set_routes(new_phenotype);
 
         global.genotype = global
             .genotype
@@ -1,6 +1,15 @@
+use std::cmp::Ordering;
 use std::collections::HashSet;
 
-use crate::solver::genetic::{Individual, Population, Split};
+use std::f64::consts::PI;
+
+use crate::config::{ConstructionKind, MoveKind, Mutation};
+use crate::solver::construction::{
+    construct_kmeans, nearest_neighbor_tour, savings_routes, sweep_routes,
+};
+use crate::solver::genetic::{
+    ArchiveKey, Individual, ParetoFront, Population, SolutionArchive, Split,
+};
 use crate::solver::improvement::{LocalSearch, RuinRecreate};
 use crate::solver::{Context, Metaheuristic};
 use crate::utils::FloatCompare;
@@ -14,6 +23,18 @@ enum State {
     Terminated,
 }
 
+/// How the search converged, collected when `collect_metrics` is enabled
+#[derive(Clone, Debug, Default)]
+pub struct ConvergenceDiagnostics {
+    // Iteration in which the best solution was found
+    pub generations_to_best: u64,
+    // Number of times the population was reset without improvement
+    pub restarts: u64,
+    // Capacity penalty and feasible fraction of the offspring after each penalty update
+    pub penalty_history: Vec<f64>,
+    pub feasible_ratio_history: Vec<f64>,
+}
+
 pub struct GeneticAlgorithm {
     state: State,
     pub population: Population,
@@ -26,6 +47,8 @@ pub struct GeneticAlgorithm {
 
     pub child: Individual,
     pub num_initialized: u64,
+    // Deterministic construction heuristics that already built an initial individual
+    pub constructed: Vec<ConstructionKind>,
 
     pub best_solution: Option<Individual>,
     pub best_iteration: u64,
@@ -33,6 +56,31 @@ pub struct GeneticAlgorithm {
     pub diversify: bool,
     pub current_best_solution_cost: f64,
     pub diversity: f64,
+
+    // Number of ruin and recreate searches performed on offspring
+    pub rr_searches: u64,
+
+    // Number of offspring cloned from a parent instead of crossing two parents
+    pub clones: u64,
+
+    // Non-dominated solutions found during the search when `pareto_front` is enabled
+    pub pareto_front: ParetoFront,
+
+    // Copy of the population at termination when `return_population` is enabled
+    pub final_population: Option<Vec<Individual>>,
+
+    // Best solution at each of the `checkpoints` in seconds, captured at the first generation
+    // past the checkpoint. None if there was no feasible solution yet
+    pub checkpoint_solutions: Vec<(f64, Option<Individual>)>,
+    pub next_checkpoint: usize,
+
+    pub diagnostics: Option<ConvergenceDiagnostics>,
+
+    // Educated offspring by their routes before the education
+    pub archive: SolutionArchive,
+
+    // Best individual of the initial population, to find the customers the search never moved
+    pub initial_solution: Option<Individual>,
 }
 
 impl GeneticAlgorithm {
@@ -46,6 +94,7 @@ impl GeneticAlgorithm {
             iterations: 0,
             child: Individual::new_random(ctx, 0),
             num_initialized: 0,
+            constructed: Vec::new(),
             // Update penalty at this iteration
             next_penalty_update: 0,
             next_log_interval: 0,
@@ -56,6 +105,19 @@ impl GeneticAlgorithm {
             current_best_solution_cost: f64::INFINITY,
             best_iteration: 0,
             diversity: 1.0,
+            rr_searches: 0,
+            clones: 0,
+            pareto_front: ParetoFront::new(),
+            final_population: None,
+            checkpoint_solutions: Vec::new(),
+            next_checkpoint: 0,
+            diagnostics: if ctx.config.borrow().collect_metrics {
+                Some(ConvergenceDiagnostics::default())
+            } else {
+                None
+            },
+            archive: SolutionArchive::new(ctx.config.borrow().archive_size),
+            initial_solution: None,
         }
     }
 
@@ -162,35 +224,87 @@ impl GeneticAlgorithm {
             // Make sure the penalty is in the range [0.01, 10_000.0]
             config.penalty_capacity =
                 0.0001f64.max(10_000_000.0f64.min(config.penalty_capacity as f64));
+
+            if let Some(diagnostics) = self.diagnostics.as_mut() {
+                diagnostics.penalty_history.push(config.penalty_capacity);
+                diagnostics.feasible_ratio_history.push(feasible_fraction);
+            }
         }
 
         for individual in self.population.infeasible.population.iter_mut() {
             individual.evaluate(ctx);
         }
 
-        self.population.infeasible.population.sort();
+        self.population.infeasible.sort(ctx);
     }
 
     pub fn educate(&mut self, ctx: &Context) {
+        // Offspring seen before take the cached result of their education, evaluated with the
+        // current penalty
+        let key = match self.archive.is_enabled() {
+            true => Some(ArchiveKey::new(&self.child)),
+            false => None,
+        };
+        let archive = &mut self.archive;
+        if let Some(educated) = key.as_ref().and_then(|key| archive.get(key)) {
+            let number = self.child.number;
+            self.child = educated.clone();
+            self.child.number = number;
+            self.child.evaluate(ctx);
+            self.repair(ctx);
+            self.update_best(ctx);
+            return;
+        }
+
         // Local search
         let child = &mut self.child;
         if ctx.config.borrow().ls_enabled {
             self.ls.run(ctx, child, 1.0);
         }
+        let three_opt = ctx
+            .config
+            .borrow()
+            .local_search_order
+            .contains(&MoveKind::ThreeOpt);
+        if three_opt {
+            child.improve_three_opt(ctx);
+        }
 
-        // R&R search
+        // R&R search, followed by local search if there are more iterations
         let rnd = ctx.random.real();
         if ctx.config.borrow().rr_mutation && rnd < ctx.config.borrow().rr_probability {
-            self.rr.load(ctx, child);
-            while !self.rr.complete() {
-                self.rr.search();
+            let iterations = ctx.config.borrow().rr_iterations_per_offspring;
+            for iteration in 0..iterations {
+                if ctx.deadline_reached() {
+                    break;
+                }
+                if iteration > 0 && ctx.config.borrow().ls_enabled {
+                    self.ls.run(ctx, child, 1.0);
+                }
+                self.rr.load(ctx, child);
+                while !self.rr.complete() {
+                    self.rr.search();
+                }
+                self.rr.get_best_solution(child);
+                self.rr_searches += 1;
             }
-            self.rr.get_best_solution(child);
         }
 
-        // Repair with probability using local search with higher penalty
-        if !child.is_feasible() && ctx.random.real() < ctx.config.borrow().repair_probability {
-            let unrepaired_child = child.clone();
+        self.repair(ctx);
+
+        if let Some(key) = key {
+            self.archive.insert(key, self.child.clone());
+        }
+
+        // Update best solution
+        self.update_best(ctx);
+    }
+
+    // Repair with probability using local search with higher penalty. A repaired child is
+    // added to the population, while the child itself is left unrepaired
+    fn repair(&mut self, ctx: &Context) {
+        if !self.child.is_feasible() && ctx.random.real() < ctx.config.borrow().repair_probability {
+            let unrepaired_child = self.child.clone();
             if ctx.config.borrow().ls_enabled {
                 self.ls.run(ctx, &mut self.child, 10.0);
             }
@@ -201,9 +315,6 @@ impl GeneticAlgorithm {
             }
             self.child = unrepaired_child;
         }
-
-        // Update best solution
-        self.update_best(ctx);
     }
 
     fn log(&mut self, ctx: &Context) {
@@ -219,20 +330,19 @@ impl GeneticAlgorithm {
             "Feas {} {:.2} {:.2} | ",
             self.population.feasible.size(),
             self.population.feasible.get_best_cost(),
-            self.population.feasible.get_average_cost(ctx)
+            self.population.feasible.get_average_cost()
         ));
         log_text.push_str(&format!(
             "Inf {} {:.2} {:.2} | ",
             self.population.infeasible.size(),
             self.population.infeasible.get_best_cost(),
-            self.population.infeasible.get_average_cost(ctx)
+            self.population.infeasible.get_average_cost()
         ));
-        self.diversity =
-            self.population.feasible.get_diversity(ctx) / (ctx.problem.dim() - 1) as f64;
+        self.diversity = self.population.feasible.get_diversity() / (ctx.problem.dim() - 1) as f64;
         log_text.push_str(&format!(
             "Div {:.2} {:.2} | ",
             self.diversity,
-            self.population.infeasible.get_diversity(ctx) / (ctx.problem.dim() - 1) as f64
+            self.population.infeasible.get_diversity() / (ctx.problem.dim() - 1) as f64
         ));
         log_text.push_str(&format!(
             "Feas {:.2} | ",
@@ -243,23 +353,74 @@ impl GeneticAlgorithm {
     }
 
     pub fn update_best(&mut self, ctx: &Context) {
-        if self.child.is_feasible()
-            && self
-                .child
-                .penalized_cost()
-                .approx_lt(self.current_best_solution_cost)
+        if ctx.config.borrow().pareto_front {
+            self.pareto_front.add(&self.child);
+        }
+        let epsilon = ctx.config.borrow().float_epsilon;
+        if !self.child.is_feasible() {
+            return;
+        }
+        if self
+            .child
+            .penalized_cost()
+            .approx_lt_eps(self.current_best_solution_cost, epsilon)
         {
             self.best_iteration = self.iterations;
             self.current_best_solution_cost = self.child.penalized_cost();
+        }
+
+        // The best solution is the one with the lowest penalized cost, unless an objective
+        // order is configured. The current best cost is never below the best cost
+        let order = ctx.config.borrow().objective_order.clone();
+        let new_best = if order.is_empty() {
+            self.child
+                .penalized_cost()
+                .approx_lt_eps(ctx.search_history.borrow().best_cost, epsilon)
+        } else {
+            match self.best_solution.as_ref() {
+                Some(best) => {
+                    self.child.compare_objectives(best, &order, epsilon) == Ordering::Less
+                }
+                None => true,
+            }
+        };
+        if new_best {
             let mut search_history = ctx.search_history.borrow_mut();
-            if self
-                .current_best_solution_cost
-                .approx_lt(search_history.best_cost)
-            {
-                self.best_solution = Some(self.child.clone());
-                search_history.add_message(format!("New best: {:.2}", self.child.penalized_cost()));
-                search_history.add(ctx, &self.child);
+            self.best_solution = Some(self.child.clone());
+            if let Some(diagnostics) = self.diagnostics.as_mut() {
+                diagnostics.generations_to_best = self.iterations;
+            }
+            search_history.add_message(format!("New best: {:.2}", self.child.penalized_cost()));
+            search_history.add(ctx, &self.child);
+        }
+    }
+
+    // Re-sequences the small routes of the best solution optimally, unless the hard deadline
+    // has been reached
+    fn polish(&mut self, ctx: &Context) {
+        let threshold = ctx.config.borrow().exact_route_threshold;
+        if threshold == 0 || ctx.deadline_reached() {
+            return;
+        }
+        if let Some(best) = self.best_solution.as_ref() {
+            let mut polished = best.clone();
+            if polished.improve_exact(ctx, threshold) {
+                self.child = polished;
+                self.update_best(ctx);
+            }
+        }
+    }
+
+    // Captures the best solution at the checkpoints passed at `elapsed` seconds
+    fn capture_checkpoints(&mut self, ctx: &Context, elapsed: f64) {
+        let config = ctx.config.borrow();
+        while let Some(&checkpoint) = config.checkpoints.get(self.next_checkpoint) {
+            if elapsed < checkpoint {
+                break;
             }
+            self.checkpoint_solutions
+                .push((checkpoint, self.best_solution.clone()));
+            self.next_checkpoint += 1;
         }
     }
 
@@ -273,7 +434,53 @@ impl GeneticAlgorithm {
         self.current_best_solution_cost = f64::INFINITY;
         self.best_iteration = self.iterations;
         self.num_initialized = 0;
+        self.constructed.clear();
         self.state = State::Created;
+        if let Some(diagnostics) = self.diagnostics.as_mut() {
+            diagnostics.restarts += 1;
+        }
+    }
+
+    /// Up to `count` distinct feasible solutions with a cost within the relative `max_gap` of the
+    /// best solution, starting with the best. Every pair of solutions is at least `min_distance`
+    /// broken pairs apart. Taken from the archive and from the final population if it was
+    /// returned, otherwise from the current population
+    pub fn alternatives(&self, count: usize, max_gap: f64, min_distance: i64) -> Vec<Individual> {
+        let mut candidates = match &self.final_population {
+            Some(population) => population.clone(),
+            None => self.population.snapshot(),
+        };
+        candidates.extend(self.archive.individuals().cloned());
+        candidates.retain(|individual| individual.is_feasible());
+        candidates.sort_by(|a, b| a.penalized_cost().partial_cmp(&b.penalized_cost()).unwrap());
+        let best = match self.best_solution.as_ref().or_else(|| candidates.first()) {
+            Some(best) => best.clone(),
+            None => return Vec::new(),
+        };
+        let max_cost = best.penalized_cost() * (1.0 + max_gap);
+
+        let mut alternatives = vec![best];
+        for candidate in candidates {
+            if alternatives.len() >= count || candidate.penalized_cost().approx_gt(max_cost) {
+                break;
+            }
+            if alternatives.iter().all(|alternative| {
+                alternative.calculate_broken_pairs_distance(&candidate) >= min_distance
+            }) {
+                alternatives.push(candidate);
+            }
+        }
+        alternatives.truncate(count);
+        alternatives
+    }
+
+    /// Customers of the best solution in the same route and position as in the best initial
+    /// individual. Many of them may indicate that the granularity is too narrow to reach them
+    pub fn static_customers(&self, ctx: &Context) -> Vec<usize> {
+        match (&self.best_solution, &self.initial_solution) {
+            (Some(best), Some(initial)) => best.static_customers(ctx, initial),
+            _ => Vec::new(),
+        }
     }
 
     pub fn add_initial(&mut self, ctx: &Context, individual: Individual) {
@@ -286,11 +493,67 @@ impl GeneticAlgorithm {
         self.population.add_individual(ctx, individual, false)
     }
 
+    /// Draws the heuristic constructing the next initial individual from the construction mix.
+    /// Without a mix, the first `kmeans_initial_individuals` use k-means and the rest are random
+    fn next_construction(&self, ctx: &Context) -> ConstructionKind {
+        let config = ctx.config.borrow();
+        if config.construction_mix.is_empty() {
+            return if self.num_initialized < config.kmeans_initial_individuals {
+                ConstructionKind::KMeans
+            } else {
+                ConstructionKind::Random
+            };
+        }
+        let total: f64 = config
+            .construction_mix
+            .iter()
+            .map(|(_, weight)| weight)
+            .sum();
+        let mut draw = ctx.random.real() * total;
+        for &(kind, weight) in config.construction_mix.iter() {
+            if draw < weight {
+                return kind;
+            }
+            draw -= weight;
+        }
+        config.construction_mix.last().expect("No construction").0
+    }
+
     pub fn create_initial_individual(&mut self, ctx: &Context) -> Individual {
         let max_routes = ctx.config.borrow().num_vehicles;
-        let mut new_child = Individual::new_random(ctx, self.num_initialized);
+        let number = self.num_initialized;
+        let construction = self.next_construction(ctx);
+        let mut new_child = match construction {
+            ConstructionKind::Savings | ConstructionKind::NearestNeighbor => {
+                let routes = match construction {
+                    ConstructionKind::Savings => savings_routes(&ctx.problem, &ctx.matrix_provider),
+                    _ => nearest_neighbor_tour(&ctx.problem, &ctx.matrix_provider).0,
+                };
+                let mut individual = Individual::from_routes(ctx, &mut self.split, routes, number);
+                // Both heuristics build the same routes every time, so repeated draws relocate
+                // a tenth of the customers in the giant tour to keep the population diverse
+                if self.constructed.contains(&construction) {
+                    let relocations = (ctx.problem.dim() / 10).max(1);
+                    individual.apply_mutation(ctx, Mutation::RandomRelocate(relocations));
+                    individual.routes(ctx, &mut self.split, max_routes);
+                } else {
+                    self.constructed.push(construction);
+                }
+                individual
+            }
+            ConstructionKind::Sweep => {
+                let routes = sweep_routes(&ctx.problem, 2.0 * PI * ctx.random.real());
+                Individual::from_routes(ctx, &mut self.split, routes, number)
+            }
+            ConstructionKind::KMeans => construct_kmeans(ctx, &mut self.split, number),
+            ConstructionKind::Random => {
+                let mut individual = Individual::new_random(ctx, number);
+                individual.routes(ctx, &mut self.split, max_routes);
+                individual
+            }
+        };
+        new_child.construction = Some(construction);
         std::mem::swap(&mut new_child, &mut self.child);
-        self.split.run(ctx, &mut self.child, max_routes);
         self.educate(ctx);
         std::mem::swap(&mut new_child, &mut self.child);
         return new_child;
@@ -299,8 +562,12 @@ impl GeneticAlgorithm {
 
 impl Metaheuristic for GeneticAlgorithm {
     fn iterate(&mut self, ctx: &Context) {
-        if ctx.terminate() {
+        if ctx.terminate() && self.state != State::Terminated {
             self.state = State::Terminated;
+            self.polish(ctx);
+            if ctx.config.borrow().return_population {
+                self.final_population = Some(self.population.snapshot());
+            }
         }
         match self.state {
             State::Created => {
@@ -311,7 +578,7 @@ impl Metaheuristic for GeneticAlgorithm {
                     // Setup Elite Education
                     self.state = State::EliteEducation;
                     let max_routes = ctx.config.borrow().num_vehicles as usize;
-                    self.split.run(ctx, &mut self.child, max_routes as u64);
+                    self.child.routes(ctx, &mut self.split, max_routes as u64);
                     self.educate(ctx);
                     self.rr.setup_elite_education(ctx);
                     self.rr.load(ctx, &mut self.child);
@@ -341,6 +608,7 @@ impl Metaheuristic for GeneticAlgorithm {
                     self.num_initialized += 1;
                 } else {
                     self.state = State::Cycle;
+                    self.initial_solution = self.population.snapshot().into_iter().min();
                 }
             }
             State::Cycle => {
@@ -349,11 +617,28 @@ impl Metaheuristic for GeneticAlgorithm {
                 // Select two parents and perform crossover
                 let parent_one = self.population.get_parent(ctx);
                 let parent_two = self.population.get_parent(ctx);
-                self.child = self.crossover(ctx, parent_one, parent_two);
+                let crossover_rate = ctx.config.borrow().crossover_rate;
+                if crossover_rate >= 1.0 || ctx.random.real() < crossover_rate {
+                    self.child = self.crossover(ctx, parent_one, parent_two);
+                    self.child.mutate(ctx);
+                } else {
+                    // Clone the first parent and always mutate the clone
+                    self.child = Individual::new(
+                        parent_one.genotype.clone(),
+                        self.population.total_individuals_count,
+                    );
+                    let mutation = ctx
+                        .config
+                        .borrow()
+                        .mutation
+                        .unwrap_or(Mutation::ReverseSegment);
+                    self.child.apply_mutation(ctx, mutation);
+                    self.clones += 1;
+                }
 
                 // Max number of routes the child is allowed to get
                 let max_routes = parent_one.num_nonempty_routes();
-                self.split.run(ctx, &mut self.child, max_routes as u64);
+                self.child.routes(ctx, &mut self.split, max_routes as u64);
 
                 // Educate child
                 self.educate(ctx);
@@ -381,6 +666,8 @@ impl Metaheuristic for GeneticAlgorithm {
 
                 // Update number of iterations
                 self.iterations += 1;
+
+                self.capture_checkpoints(ctx, ctx.elapsed_as_secs_f64());
             }
             State::Terminated => {}
         }
@@ -389,3 +676,628 @@ impl Metaheuristic for GeneticAlgorithm {
         self.state == State::Terminated
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::config::{Config, ParentSelection};
+    use crate::models::Problem;
+    use crate::solver::genetic::solve_pareto;
+    use crate::solver::improvement::MAX_EXACT_ROUTE;
+    use crate::solver::Solver;
+    use crate::utils::{coordinate_problem, demand_problem, instance_context, problem_context};
+
+    fn run(ctx: Context) -> Solver<GeneticAlgorithm> {
+        let metaheuristic = GeneticAlgorithm::new(&ctx);
+        let mut solver = Solver::new(ctx, metaheuristic);
+        solver.run();
+        solver
+    }
+
+    #[test]
+    fn recorded_run_is_replayed() {
+        let configure = |config: &mut Config| {
+            config.max_iterations = Some(100);
+            config.initial_individuals = 10;
+        };
+        let recorded = run(instance_context(|config| {
+            configure(config);
+            config.record_rng = true;
+        }));
+        let draws = recorded.ctx.random.recorded_draws();
+        assert!(!draws.is_empty());
+
+        // Without the replay an unseeded run would take other values
+        let replayed = run(instance_context(|config| {
+            configure(config);
+            config.deterministic = false;
+            config.replay_rng = Some(draws.clone());
+        }));
+
+        let recorded_best = recorded.metaheuristic.best_solution.expect("No solution");
+        let replayed_best = replayed.metaheuristic.best_solution.expect("No solution");
+        assert_eq!(recorded_best.phenotype, replayed_best.phenotype);
+        assert!(recorded_best
+            .penalized_cost()
+            .approx_eq(replayed_best.penalized_cost()));
+    }
+
+    #[test]
+    fn rng_state_is_threaded() {
+        let configure = |config: &mut Config| {
+            config.max_iterations = Some(50);
+            config.initial_individuals = 10;
+        };
+
+        // Both solves drawing from a single generator
+        let first = run(instance_context(configure));
+        let mut ctx = instance_context(configure);
+        ctx.random = first.ctx.random;
+        let single = run(ctx);
+
+        // The second solve continues from the state returned by the first
+        let state = run(instance_context(configure)).ctx.random.state();
+        let threaded = run(instance_context(|config| {
+            configure(config);
+            config.rng_state_in = Some(state);
+        }));
+
+        assert_eq!(single.ctx.random.state(), threaded.ctx.random.state());
+        let phenotype = |solver: &Solver<GeneticAlgorithm>| {
+            solver
+                .metaheuristic
+                .best_solution
+                .as_ref()
+                .map(|best| best.phenotype.clone())
+        };
+        assert_eq!(phenotype(&single), phenotype(&threaded));
+        assert_eq!(
+            single.ctx.search_history.borrow().best_cost,
+            threaded.ctx.search_history.borrow().best_cost
+        );
+    }
+
+    #[test]
+    fn alternatives_are_close_and_diverse() {
+        let (count, max_gap, min_distance) = (3, 0.05, 10);
+
+        let solver = run(instance_context(|config| {
+            config.max_iterations = Some(500);
+            config.initial_individuals = 30;
+            config.return_population = true;
+        }));
+        let alternatives = solver
+            .metaheuristic
+            .alternatives(count, max_gap, min_distance);
+        assert!(alternatives.len() > 1 && alternatives.len() <= count);
+        let best = solver.metaheuristic.best_solution.as_ref().unwrap();
+        assert_eq!(best.phenotype, alternatives[0].phenotype);
+        for (index, alternative) in alternatives.iter().enumerate() {
+            assert!(alternative.is_feasible());
+            assert!(alternative
+                .penalized_cost()
+                .approx_lte(best.penalized_cost() * (1.0 + max_gap)));
+            for other in alternatives[index + 1..].iter() {
+                assert!(alternative.calculate_broken_pairs_distance(other) >= min_distance);
+            }
+        }
+    }
+
+    #[test]
+    fn archive_skips_education() {
+        let ctx = instance_context(|config| config.archive_size = 2);
+        let mut ga = GeneticAlgorithm::new(&ctx);
+
+        let offspring: Vec<Individual> = (0..3)
+            .map(|number| {
+                let mut individual = Individual::new_random(&ctx, number);
+                ga.split
+                    .run(&ctx, &mut individual, ctx.config.borrow().num_vehicles);
+                individual
+            })
+            .collect();
+        ga.child = offspring[0].clone();
+        ga.educate(&ctx);
+        let educated_cost = ga.child.penalized_cost();
+        let rr_searches = ga.rr_searches;
+        assert_eq!((0, 1), (ga.archive.hits, ga.archive.misses));
+
+        ga.child = offspring[0].clone();
+        ga.educate(&ctx);
+        assert_eq!((1, 1), (ga.archive.hits, ga.archive.misses));
+        assert_eq!(educated_cost, ga.child.penalized_cost());
+        assert_eq!(rr_searches, ga.rr_searches);
+
+        // The first offspring is the least recently used after the other two are added
+        for individual in offspring.iter().skip(1) {
+            ga.child = individual.clone();
+            ga.educate(&ctx);
+        }
+        assert_eq!(2, ga.archive.len());
+        ga.child = offspring[0].clone();
+        ga.educate(&ctx);
+        assert_eq!((1, 4), (ga.archive.hits, ga.archive.misses));
+    }
+
+    #[test]
+    fn archive_hit_is_reevaluated() {
+        let mut ctx = instance_context(|config| config.archive_size = 2);
+        // Too little capacity for any solution to be feasible
+        ctx.problem.vehicle.cap /= 4.0;
+        ctx.setup();
+        let mut ga = GeneticAlgorithm::new(&ctx);
+
+        let mut offspring = Individual::new_random(&ctx, 0);
+        ga.split
+            .run(&ctx, &mut offspring, ctx.config.borrow().num_vehicles);
+        ga.child = offspring.clone();
+        ga.educate(&ctx);
+        assert!(!ga.child.is_feasible());
+        let educated_cost = ga.child.penalized_cost();
+
+        ctx.config.borrow_mut().penalty_capacity *= 10.0;
+        ga.child = offspring;
+        ga.educate(&ctx);
+        assert_eq!(1, ga.archive.hits);
+        let mut reevaluated = ga.child.clone();
+        reevaluated.evaluate(&ctx);
+        assert_eq!(reevaluated.penalized_cost(), ga.child.penalized_cost());
+        assert!(ga.child.penalized_cost() > educated_cost);
+    }
+
+    // Twenty customers on a circle around the depot and one far outlier with a large demand
+    fn circle_with_outlier() -> Problem {
+        let circle = (0..20).map(|i| {
+            let angle = i as f64 * std::f64::consts::PI / 10.0;
+            (10.0 * angle.cos(), 10.0 * angle.sin())
+        });
+        let coords: Vec<(f64, f64)> = std::iter::once((0.0, 0.0))
+            .chain(circle)
+            .chain(std::iter::once((1000.0, 0.0)))
+            .collect();
+        let mut demands = vec![1.0; 20];
+        demands.push(20.0);
+        demand_problem(&coords, &demands, 20.0)
+    }
+
+    #[test]
+    fn static_customers() {
+        let mut config = Config::default();
+        config.deterministic = true;
+        config.local_search_granularity = 3;
+        let ctx = problem_context(circle_with_outlier(), None, config);
+        let mut ga = GeneticAlgorithm::new(&ctx);
+
+        // The circle is visited in a scrambled order, and the outlier fills a vehicle
+        let mut genotype: Vec<usize> = (0..20).map(|i| 1 + (i * 7) % 20).collect();
+        genotype.push(21);
+        let mut individual = Individual::new(genotype, 0);
+        ga.split
+            .run(&ctx, &mut individual, ctx.config.borrow().num_vehicles);
+        assert!(individual.phenotype.contains(&vec![21]));
+        let initial = individual.clone();
+        ga.ls.run(&ctx, &mut individual, 1.0);
+        assert!(individual.phenotype.contains(&vec![21]));
+
+        // The circle is visited in order, where only customers 1 and 11 keep their index
+        let circle: Vec<usize> = (1..=20).collect();
+        assert!(individual.phenotype.contains(&circle));
+        assert_eq!(vec![1, 11, 21], individual.static_customers(&ctx, &initial));
+    }
+
+    // Three customers close to the depot, but far away from each other
+    fn scattered_customers(config: Config) -> Context {
+        let coords = [(0.0, 0.0), (10.0, 0.0), (0.0, 10.0), (-10.0, 0.0)];
+        let matrix = vec![vec![10.0], vec![10.0, 100.0], vec![10.0, 100.0, 100.0]];
+        problem_context(coordinate_problem(&coords, 10.0), Some(matrix), config)
+    }
+
+    fn single_stop_routes(solver: &Solver<GeneticAlgorithm>) -> usize {
+        let best = solver
+            .metaheuristic
+            .best_solution
+            .as_ref()
+            .expect("No solution");
+        best.phenotype
+            .iter()
+            .filter(|route| route.len() == 1)
+            .count()
+    }
+
+    #[test]
+    fn short_route_penalty_merges_routes() {
+        let mut config = Config::default();
+        config.deterministic = true;
+        config.max_iterations = Some(50);
+        config.local_search_granularity = 2;
+
+        let solver = run(scattered_customers(config.clone()));
+        assert_eq!(3, single_stop_routes(&solver));
+
+        config.min_customers_per_route = Some(2);
+        config.short_route_penalty = 1000.0;
+        let solver = run(scattered_customers(config));
+        assert_eq!(0, single_stop_routes(&solver));
+        let best = solver.metaheuristic.best_solution.as_ref().unwrap();
+        assert_eq!(0.0, best.evaluation.short_route_penalty);
+    }
+
+    // Customers east of the depot with demand 6 and west of it with demand 4. Pairing them
+    // across the depot needs the fewest vehicles, while pairing the western ones is shorter
+    fn east_west_customers() -> Problem {
+        let mut coords = vec![(0.0, 0.0)];
+        coords.extend((0..4).map(|i| (100.0, 10.0 * i as f64)));
+        coords.extend((0..4).map(|i| (-100.0, 10.0 * i as f64)));
+        demand_problem(&coords, &[6.0, 6.0, 6.0, 6.0, 4.0, 4.0, 4.0, 4.0], 10.0)
+    }
+
+    #[test]
+    fn pareto_front_is_non_dominated() {
+        let mut config = Config::default();
+        config.deterministic = true;
+        config.max_iterations = Some(200);
+        config.initial_individuals = 10;
+
+        let front = solve_pareto(east_west_customers(), config);
+        let problem = east_west_customers();
+        for (i, a) in front.iter().enumerate() {
+            assert_eq!(Ok(()), a.validate_against(&problem));
+            for b in front.iter().skip(i + 1) {
+                assert!(a.routes.len() < b.routes.len() && a.cost.approx_gt(b.cost));
+            }
+        }
+        let vehicles: HashSet<usize> = front.iter().map(|solution| solution.routes.len()).collect();
+        assert!(vehicles.len() >= 2);
+    }
+
+    #[test]
+    fn subpopulation_size_limits() {
+        let solver = run(instance_context(|config| {
+            config.max_iterations = Some(200);
+            config.initial_individuals = 10;
+            config.min_population_size = 8;
+            config.population_lambda = 8;
+            config.infeasible_min_population_size = Some(3);
+            config.infeasible_population_lambda = Some(2);
+        }));
+        let population = &solver.metaheuristic.population;
+        assert!(population.feasible.size() > 0 && population.feasible.size() < 16);
+        assert!(population.infeasible.size() > 0 && population.infeasible.size() < 5);
+
+        let best = solver.metaheuristic.best_solution.as_ref().unwrap();
+        let best_feasible = population.feasible.get_best().unwrap();
+        assert!(best_feasible.is_feasible());
+        assert!(best
+            .penalized_cost()
+            .approx_eq(best_feasible.penalized_cost()));
+    }
+
+    #[test]
+    fn rr_iterations_per_offspring() {
+        let configure = |config: &mut Config| {
+            config.max_iterations = Some(20);
+            config.initial_individuals = 5;
+        };
+
+        let single = run(instance_context(configure));
+        assert!(single.metaheuristic.rr_searches > 0);
+
+        let triple = run(instance_context(|config| {
+            configure(config);
+            config.rr_iterations_per_offspring = 3;
+        }));
+        assert_eq!(
+            3 * single.metaheuristic.rr_searches,
+            triple.metaheuristic.rr_searches
+        );
+    }
+
+    #[test]
+    fn hard_deadline_stops_search() {
+        let solver = run(instance_context(|config| {
+            config.instance_path = "instances/X-n1001-k43.vrp".to_owned();
+            config.time_limit = 1;
+            config.hard_deadline = true;
+            // A single education takes far longer than the time limit
+            config.rr_iterations_per_offspring = 1000;
+        }));
+
+        // The first education was interrupted by the deadline
+        assert!(solver.ctx.deadline_reached());
+        assert!(solver.metaheuristic.terminated());
+        assert!(solver.metaheuristic.rr_searches < 1000);
+    }
+
+    #[test]
+    fn final_population_is_returned() {
+        let solver = run(instance_context(|config| {
+            config.max_iterations = Some(200);
+            config.initial_individuals = 30;
+            config.return_population = true;
+        }));
+        let config = solver.ctx.config.borrow();
+        let max_population_size = 2 * (config.min_population_size + config.population_lambda);
+        let population = solver.metaheuristic.final_population.as_ref().unwrap();
+        assert!(population.len() as u64 >= config.min_population_size);
+        assert!(population.len() as u64 <= max_population_size);
+
+        let best = solver.metaheuristic.best_solution.as_ref().unwrap();
+        assert!(population.iter().any(|individual| {
+            individual.phenotype == best.phenotype
+                && individual.penalized_cost().approx_eq(best.penalized_cost())
+        }));
+    }
+
+    fn best_overlap_area(solver: &Solver<GeneticAlgorithm>) -> f64 {
+        let best = solver.metaheuristic.best_solution.as_ref().unwrap();
+        solver
+            .ctx
+            .problem
+            .route_overlap_area(best.phenotype.iter().map(|route| route.as_slice()))
+    }
+
+    #[test]
+    fn overlap_penalty_separates_routes() {
+        let configure = |config: &mut Config| {
+            config.max_iterations = Some(200);
+            config.initial_individuals = 10;
+        };
+
+        let solver = run(instance_context(configure));
+        let overlap = best_overlap_area(&solver);
+        let best = solver.metaheuristic.best_solution.as_ref().unwrap();
+        assert_eq!(0.0, best.evaluation.overlap_penalty);
+
+        let solver = run(instance_context(|config| {
+            configure(config);
+            config.overlap_penalty = 1.0;
+        }));
+        let penalized_overlap = best_overlap_area(&solver);
+        let best = solver.metaheuristic.best_solution.as_ref().unwrap();
+        assert!(best.evaluation.overlap_penalty.approx_eq(penalized_overlap));
+        assert!(penalized_overlap < 0.5 * overlap);
+    }
+
+    #[test]
+    fn checkpoint_solutions() {
+        let ctx = instance_context(|config| {
+            config.initial_individuals = 10;
+            // Far beyond the running time, such that only the elapsed times below pass them
+            config.checkpoints = vec![3000.0, 1000.0, 2000.0];
+        });
+        assert_eq!(
+            vec![1000.0, 2000.0, 3000.0],
+            ctx.config.borrow().checkpoints
+        );
+        let mut ga = GeneticAlgorithm::new(&ctx);
+
+        // No solution before the first generation
+        ga.capture_checkpoints(&ctx, 1000.0);
+        for &(iterations, elapsed) in [(50, 2000.0), (200, 3000.0)].iter() {
+            while ga.iterations < iterations {
+                ga.iterate(&ctx);
+            }
+            ga.capture_checkpoints(&ctx, elapsed);
+        }
+
+        let checkpoints = &ga.checkpoint_solutions;
+        let times: Vec<f64> = checkpoints.iter().map(|(time, _)| *time).collect();
+        assert_eq!(vec![1000.0, 2000.0, 3000.0], times);
+        assert!(checkpoints[0].1.is_none());
+        let first = checkpoints[1]
+            .1
+            .as_ref()
+            .expect("No solution at the checkpoint");
+        let second = checkpoints[2]
+            .1
+            .as_ref()
+            .expect("No solution at the checkpoint");
+        assert!(first.is_feasible() && second.is_feasible());
+        assert!(second.penalized_cost().approx_lte(first.penalized_cost()));
+    }
+
+    #[test]
+    fn clusters_share_a_route() {
+        let mut ctx = instance_context(|config| {
+            config.max_iterations = Some(100);
+            config.initial_individuals = 10;
+            config.return_population = true;
+        });
+        // Customers with small demands far away from each other
+        let clusters = vec![vec![7, 29], vec![34, 64, 87]];
+        ctx.problem.clusters = clusters.clone();
+
+        let solver = run(ctx);
+        let best = solver.metaheuristic.best_solution.clone().unwrap();
+        let population = solver.metaheuristic.final_population.as_ref().unwrap();
+        for individual in population.iter().chain(std::iter::once(&best)) {
+            for cluster in clusters.iter() {
+                let route = individual
+                    .phenotype
+                    .iter()
+                    .find(|route| route.contains(&cluster[0]))
+                    .unwrap();
+                assert!(cluster.iter().all(|node| route.contains(node)));
+            }
+        }
+    }
+
+    #[test]
+    fn reported_objective_is_recomputed() {
+        let solver = run(instance_context(|config| {
+            config.max_iterations = Some(200);
+            config.initial_individuals = 10;
+        }));
+        let history = solver.ctx.search_history.borrow();
+        let reported = &history.last_entry().unwrap().solution;
+        let distance: f64 = reported
+            .routes
+            .iter()
+            .map(|route| solver.ctx.matrix_provider.distance.route_distance(route))
+            .sum();
+        assert_eq!(0.0, reported.cost.fract());
+        assert_eq!(distance, reported.cost);
+        let best = solver.metaheuristic.best_solution.as_ref().unwrap();
+        assert!(best.penalized_cost().approx_eq(reported.cost));
+    }
+
+    #[test]
+    fn parent_selection() {
+        let solver = run(instance_context(|config| {
+            config.max_iterations = Some(50);
+            config.initial_individuals = 20;
+        }));
+        let ctx = &solver.ctx;
+        let population = &solver.metaheuristic.population;
+        let size = population.size();
+        let individuals = population.snapshot();
+        let best_fitness = individuals
+            .iter()
+            .map(|individual| individual.fitness)
+            .fold(f64::INFINITY, f64::min);
+
+        ctx.config.borrow_mut().parent_selection = ParentSelection::DistinctTournament;
+        ctx.config.borrow_mut().tournament_size = size as u64;
+        for _ in 0..10 {
+            assert_eq!(best_fitness, population.get_parent(ctx).fitness);
+        }
+
+        // Every individual is drawn about equally often
+        ctx.config.borrow_mut().parent_selection = ParentSelection::Random;
+        let draws_per_individual = 200;
+        let mut counts: HashMap<u64, usize> = HashMap::new();
+        for _ in 0..size * draws_per_individual {
+            *counts.entry(population.get_parent(ctx).number).or_insert(0) += 1;
+        }
+        assert_eq!(size, counts.len());
+        for &count in counts.values() {
+            assert!(count > draws_per_individual / 2 && count < 3 * draws_per_individual / 2);
+        }
+    }
+
+    #[test]
+    fn crossover_rate() {
+        let solvers: Vec<Solver<GeneticAlgorithm>> = [1.0, 0.0]
+            .iter()
+            .map(|&rate| {
+                run(instance_context(|config| {
+                    config.max_iterations = Some(50);
+                    config.initial_individuals = 5;
+                    config.crossover_rate = rate;
+                }))
+            })
+            .collect();
+        assert_eq!(0, solvers[0].metaheuristic.clones);
+        assert_eq!(
+            solvers[1].metaheuristic.iterations,
+            solvers[1].metaheuristic.clones
+        );
+        for solver in solvers.iter() {
+            let best = solver.metaheuristic.best_solution.as_ref().unwrap();
+            assert!(best.is_feasible());
+            let mut customers: Vec<usize> = best.phenotype.iter().flatten().copied().collect();
+            customers.sort_unstable();
+            assert_eq!((1..solver.ctx.problem.dim()).collect::<Vec<_>>(), customers);
+        }
+    }
+
+    #[test]
+    fn convergence_diagnostics() {
+        let configure = |config: &mut Config| {
+            config.max_iterations = Some(100);
+            config.initial_individuals = 10;
+        };
+        let solver = run(instance_context(configure));
+        assert!(solver.metaheuristic.diagnostics.is_none());
+
+        let solver = run(instance_context(|config| {
+            configure(config);
+            config.collect_metrics = true;
+        }));
+        let ga = &solver.metaheuristic;
+        let diagnostics = ga.diagnostics.as_ref().unwrap();
+        assert_eq!(0, diagnostics.restarts);
+        assert!(diagnostics.generations_to_best <= ga.iterations);
+        let interval = solver.ctx.config.borrow().penalty_update_interval;
+        let updates = (ga.iterations + interval - 1) / interval;
+        assert_eq!(updates as usize, diagnostics.penalty_history.len());
+        assert_eq!(updates as usize, diagnostics.feasible_ratio_history.len());
+        assert!(diagnostics
+            .feasible_ratio_history
+            .iter()
+            .all(|&ratio| ratio >= 0.0 && ratio <= 1.0));
+    }
+
+    #[test]
+    fn construction_mix() {
+        let ctx = instance_context(|config| {
+            config.construction_mix = vec![(ConstructionKind::Savings, 1.0)];
+        });
+
+        let construct = |ga: &mut GeneticAlgorithm| {
+            let individual = ga.create_initial_individual(&ctx);
+            ga.num_initialized += 1;
+            let mut customers = individual.genotype.clone();
+            customers.sort_unstable();
+            assert_eq!((1..ctx.problem.dim()).collect::<Vec<_>>(), customers);
+            individual.construction.unwrap()
+        };
+        let mut ga = GeneticAlgorithm::new(&ctx);
+        for _ in 0..5 {
+            assert_eq!(ConstructionKind::Savings, construct(&mut ga));
+        }
+
+        // Only the first savings individual is built unperturbed
+        let mut ga = GeneticAlgorithm::new(&ctx);
+        let phenotypes: HashSet<Vec<Vec<usize>>> = (0..5)
+            .map(|_| ga.create_initial_individual(&ctx).phenotype)
+            .collect();
+        assert_eq!(5, phenotypes.len());
+        assert_eq!(vec![ConstructionKind::Savings], ga.constructed);
+
+        ctx.config.borrow_mut().construction_mix = vec![
+            (ConstructionKind::Savings, 1.0),
+            (ConstructionKind::Sweep, 1.0),
+            (ConstructionKind::NearestNeighbor, 1.0),
+            (ConstructionKind::KMeans, 1.0),
+            (ConstructionKind::Random, 1.0),
+        ];
+        let mut kinds: Vec<ConstructionKind> = Vec::new();
+        for _ in 0..30 {
+            let kind = construct(&mut ga);
+            if !kinds.contains(&kind) {
+                kinds.push(kind);
+            }
+        }
+        assert!(kinds.len() >= 3);
+    }
+
+    #[test]
+    fn polish_skipped_after_deadline() {
+        let ctx = instance_context(|config| config.exact_route_threshold = 100);
+        assert_eq!(MAX_EXACT_ROUTE, ctx.config.borrow().exact_route_threshold);
+
+        // Feasible sweep routes, each visited in a random order
+        let mut ga = GeneticAlgorithm::new(&ctx);
+        let mut routes = sweep_routes(&ctx.problem, 0.0);
+        for route in routes.iter_mut() {
+            ctx.random.shuffle(route);
+        }
+        let individual = Individual::from_routes(&ctx, &mut ga.split, routes, 0);
+        assert!(individual.is_feasible());
+        let cost = individual.penalized_cost();
+        ga.best_solution = Some(individual);
+
+        ctx.deadline
+            .store(true, std::sync::atomic::Ordering::Relaxed);
+        ga.polish(&ctx);
+        assert_eq!(cost, ga.best_solution.as_ref().unwrap().penalized_cost());
+
+        ctx.deadline
+            .store(false, std::sync::atomic::Ordering::Relaxed);
+        ga.polish(&ctx);
+        assert!(ga.best_solution.as_ref().unwrap().penalized_cost() < cost);
+    }
+}
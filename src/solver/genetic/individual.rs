@@ -1,8 +1,25 @@
 use std::cmp::Ordering;
+use std::ops::Range;
 
+use serde::Serialize;
+
+use crate::config::{ConstructionKind, DiagonalPolicy, Mutation, ObjectiveTerm};
+use crate::models::{Coordinate, Problem};
+use crate::solver::genetic::Split;
+use crate::solver::improvement::{
+    apply_3opt, held_karp_route, route_3opt_best_move, MAX_EXACT_ROUTE,
+};
 use crate::solver::Context;
-use crate::solver::SolutionEvaluation;
-use crate::utils::FloatCompare;
+use crate::solver::{ConstraintViolation, RouteEvaluation, SolutionEvaluation};
+use crate::utils::{FloatCompare, EPSILON};
+
+/// A leg of a route between two consecutive nodes
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Instruction {
+    pub from: usize,
+    pub to: usize,
+    pub distance: f64,
+}
 
 #[derive(Debug, Clone)]
 pub struct Individual {
@@ -16,6 +33,10 @@ pub struct Individual {
     pub fitness: f64,
     // Evaluation of the solution
     pub evaluation: SolutionEvaluation,
+    // Whether the genotype changed since the phenotype was split from it
+    split_pending: bool,
+    // Heuristic that constructed an initial individual
+    pub construction: Option<ConstructionKind>,
 }
 
 impl Individual {
@@ -26,6 +47,8 @@ impl Individual {
             phenotype: Vec::new(),
             fitness: f64::INFINITY,
             evaluation: SolutionEvaluation::new(),
+            split_pending: true,
+            construction: None,
         }
     }
 
@@ -36,6 +59,8 @@ impl Individual {
             phenotype: Vec::new(),
             fitness: f64::INFINITY,
             evaluation: SolutionEvaluation::new(),
+            split_pending: true,
+            construction: None,
         }
     }
 
@@ -51,7 +76,93 @@ impl Individual {
             phenotype: vec![Vec::new(); num_vehicles], // Vec::with_capacity(num_vehicles),
             fitness: f64::INFINITY,
             evaluation: SolutionEvaluation::new(),
+            split_pending: true,
+            construction: None,
+        }
+    }
+
+    /// Creates an individual from routes. If there are more routes than vehicles, the giant tour
+    /// of the routes is split instead
+    pub fn from_routes(
+        ctx: &Context,
+        split: &mut Split,
+        mut routes: Vec<Vec<usize>>,
+        number: u64,
+    ) -> Self {
+        let genotype: Vec<usize> = routes.iter().flatten().copied().collect();
+        let mut individual = Individual::new(genotype, number);
+        let num_vehicles = ctx.config.borrow().num_vehicles;
+        if routes.len() <= num_vehicles as usize {
+            routes.resize(num_vehicles as usize, Vec::new());
+            individual.set_routes(routes);
+            individual.sort_routes(ctx);
+            individual.evaluate(ctx);
+        } else {
+            individual.routes(ctx, split, num_vehicles);
         }
+        individual
+    }
+
+    /// Applies the configured mutation to the genotype with probability `mutation_rate`.
+    /// Returns true if the genotype was mutated
+    pub fn mutate(&mut self, ctx: &Context) -> bool {
+        let (mutation, rate) = {
+            let config = ctx.config.borrow();
+            match config.mutation {
+                Some(mutation) => (mutation, config.mutation_rate),
+                None => return false,
+            }
+        };
+        if self.genotype.len() < 2 || ctx.random.real() >= rate {
+            return false;
+        }
+        self.apply_mutation(ctx, mutation);
+        true
+    }
+
+    /// Applies the mutation to the genotype
+    pub fn apply_mutation(&mut self, ctx: &Context, mutation: Mutation) {
+        let length = self.genotype.len();
+        if length < 2 {
+            return;
+        }
+        self.split_pending = true;
+        match mutation {
+            Mutation::ReverseSegment => {
+                let start = ctx.random.range_usize(0, length - 1);
+                let end = ctx.random.range_usize(start + 1, length);
+                self.genotype[start..=end].reverse();
+            }
+            Mutation::InsertionShuffle(segment_length) => {
+                let segment_length = segment_length.max(2).min(length);
+                let start = ctx.random.range_usize(0, length - segment_length + 1);
+                ctx.random
+                    .shuffle(&mut self.genotype[start..start + segment_length]);
+            }
+            Mutation::RandomRelocate(number) => {
+                for _ in 0..number {
+                    let customer = self.genotype.remove(ctx.random.range_usize(0, length));
+                    self.genotype
+                        .insert(ctx.random.range_usize(0, length), customer);
+                }
+            }
+        }
+    }
+
+    /// Samples a random feasible individual by splitting random giant tours.
+    ///
+    /// Returns `None` if no feasible individual is found within `max_attempts` tours.
+    pub fn random_feasible(ctx: &Context, max_attempts: usize) -> Option<Self> {
+        let mut split = Split::new(ctx);
+        let max_vehicles = ctx.config.borrow().num_vehicles;
+        for number in 0..max_attempts {
+            let mut individual = Self::new_random(ctx, number as u64);
+            individual.routes(ctx, &mut split, max_vehicles);
+            if individual.is_feasible() {
+                return Some(individual);
+            }
+        }
+        None
     }
 
     // Returns the index of the node from the gene number in the genotype
@@ -93,6 +204,214 @@ impl Individual {
         self.evaluation.penalized_cost
     }
 
+    /// Routes of the individual with at most `max_vehicles` routes. The genotype is only split if
+    /// it changed since the last split, such that the routes and their cost are otherwise
+    /// returned as cached
+    pub fn routes(&mut self, ctx: &Context, split: &mut Split, max_vehicles: u64) -> &[Vec<usize>] {
+        if self.split_pending {
+            split.run(ctx, self, max_vehicles);
+        }
+        &self.phenotype
+    }
+
+    /// Whether the genotype changed since the routes were split from it
+    pub fn is_split_pending(&self) -> bool {
+        self.split_pending
+    }
+
+    /// Sets the routes and the genotype as the giant tour of them. The routes are not evaluated
+    pub fn set_routes(&mut self, routes: Vec<Vec<usize>>) {
+        self.genotype = routes.iter().flatten().copied().collect();
+        self.phenotype = routes;
+        self.split_pending = false;
+    }
+
+    /// Marks the routes as matching the genotype, after they were split from it or the genotype
+    /// was set from them
+    pub(crate) fn mark_split(&mut self) {
+        self.split_pending = false;
+    }
+
+    /// Penalized cost recomputed from the routes, rounded to an integer for rounded distances.
+    /// Used for reporting, as it does not depend on the stored evaluation
+    pub fn objective(&self, ctx: &Context) -> f64 {
+        let mut evaluation = SolutionEvaluation::new();
+        evaluation.evaluate(ctx, &self.phenotype);
+        if ctx.config.borrow().round_distances {
+            evaluation.penalized_cost.round()
+        } else {
+            evaluation.penalized_cost
+        }
+    }
+
+    /// Compares the solutions lexicographically by the terms in `order`, then by the penalized
+    /// cost. Values within `epsilon` of each other are ties. This is not a total order, as ties
+    /// are not transitive, so it decides between two solutions but must not sort them
+    pub fn compare_objectives(
+        &self,
+        other: &Self,
+        order: &[ObjectiveTerm],
+        epsilon: f64,
+    ) -> Ordering {
+        self.objective_ordering(other, order, |a, b| {
+            if a.approx_lt_eps(b, epsilon) {
+                Ordering::Less
+            } else if b.approx_lt_eps(a, epsilon) {
+                Ordering::Greater
+            } else {
+                Ordering::Equal
+            }
+        })
+    }
+
+    /// Total order of the solutions by the terms in `order`, then by the penalized cost, with
+    /// exact comparisons of the values. Used to sort and search the population
+    pub fn order_objectives(&self, other: &Self, order: &[ObjectiveTerm]) -> Ordering {
+        self.objective_ordering(other, order, |a, b| a.total_cmp(&b))
+    }
+
+    fn objective_ordering(
+        &self,
+        other: &Self,
+        order: &[ObjectiveTerm],
+        compare_values: impl Fn(f64, f64) -> Ordering,
+    ) -> Ordering {
+        order
+            .iter()
+            .map(|term| match term {
+                ObjectiveTerm::Feasibility => other.is_feasible().cmp(&self.is_feasible()),
+                ObjectiveTerm::Vehicles => {
+                    self.num_nonempty_routes().cmp(&other.num_nonempty_routes())
+                }
+                ObjectiveTerm::Distance => {
+                    compare_values(self.evaluation.distance(), other.evaluation.distance())
+                }
+                ObjectiveTerm::PenalizedCost => {
+                    compare_values(self.penalized_cost(), other.penalized_cost())
+                }
+            })
+            .find(|&ordering| ordering != Ordering::Equal)
+            .unwrap_or_else(|| compare_values(self.penalized_cost(), other.penalized_cost()))
+    }
+
+    /// Checks every constraint for moving the customer to the position of the route, removing
+    /// it from its current route first. Returns the change in distance if the move is feasible,
+    /// otherwise all violated constraints. The position is in the route without the customer
+    pub fn can_insert(
+        &self,
+        ctx: &Context,
+        customer: usize,
+        route: usize,
+        position: usize,
+    ) -> Result<f64, Vec<ConstraintViolation>> {
+        let distance_matrix = &ctx.matrix_provider.distance;
+        let leg = |from: usize, to: usize| distance_matrix.get(from, to);
+        let at = |route: &[usize], index: usize| route.get(index).copied().unwrap_or(0);
+
+        let current_position = self.position(customer);
+        let mut delta = 0.0;
+        if let Some((current, index)) = current_position {
+            let current = &self.phenotype[current];
+            let before = if index == 0 { 0 } else { current[index - 1] };
+            let after = at(current, index + 1);
+            delta += leg(before, after) - leg(before, customer) - leg(customer, after);
+        }
+        let mut new_route: Vec<usize> = self.phenotype[route]
+            .iter()
+            .copied()
+            .filter(|&node| node != customer)
+            .collect();
+        assert!(
+            position <= new_route.len(),
+            "Position {} is out of the route",
+            position
+        );
+        let before = if position == 0 {
+            0
+        } else {
+            new_route[position - 1]
+        };
+        let after = at(&new_route, position);
+        delta += leg(before, customer) + leg(customer, after) - leg(before, after);
+        new_route.insert(position, customer);
+        // The cost of visiting the customer only changes if it was not routed
+        if ctx.config.borrow().diagonal_policy == DiagonalPolicy::Honor
+            && current_position.is_none()
+        {
+            delta += leg(customer, customer);
+        }
+
+        let mut violations = Vec::new();
+        let capacity = ctx.problem.vehicle.cap;
+        let load: f64 = new_route
+            .iter()
+            .map(|&node| ctx.problem.nodes[node].demand)
+            .sum();
+        if load.approx_gt(capacity) {
+            violations.push(ConstraintViolation::Capacity(load, capacity));
+        }
+        if let Some(max_locations) = ctx.config.borrow().max_distinct_locations_per_route {
+            let locations = ctx.problem.num_locations(&new_route);
+            if locations > max_locations {
+                violations.push(ConstraintViolation::DistinctLocations(
+                    locations,
+                    max_locations,
+                ));
+            }
+        }
+        for cluster in ctx
+            .problem
+            .clusters
+            .iter()
+            .filter(|cluster| cluster.contains(&customer))
+        {
+            if let Some(&other) = cluster.iter().find(|node| !new_route.contains(node)) {
+                violations.push(ConstraintViolation::Cluster(other));
+            }
+        }
+
+        match violations.is_empty() {
+            true => Ok(delta),
+            false => Err(violations),
+        }
+    }
+
+    /// Customers in the same route and at the same index in the route as in the initial
+    /// individual. Routes are identified by their index, as both have their routes sorted
+    pub fn static_customers(&self, ctx: &Context, initial: &Individual) -> Vec<usize> {
+        let positions = |individual: &Individual| {
+            let mut positions = vec![None; ctx.problem.dim()];
+            for (route_index, route) in individual.phenotype.iter().enumerate() {
+                for (index, &customer) in route.iter().enumerate() {
+                    positions[customer] = Some((route_index, index));
+                }
+            }
+            positions
+        };
+        let initial_positions = positions(initial);
+        positions(self)
+            .iter()
+            .enumerate()
+            .filter(|&(customer, position)| {
+                position.is_some() && *position == initial_positions[customer]
+            })
+            .map(|(customer, _)| customer)
+            .collect()
+    }
+
+    /// Route and index in the route of the customer, if it is routed
+    fn position(&self, customer: usize) -> Option<(usize, usize)> {
+        self.phenotype
+            .iter()
+            .enumerate()
+            .find_map(|(route_index, route)| {
+                route
+                    .iter()
+                    .position(|&node| node == customer)
+                    .map(|index| (route_index, index))
+            })
+    }
+
     pub fn successor(&self, node: usize) -> usize {
         self.evaluation.successors[node]
     }
@@ -124,6 +443,351 @@ impl Individual {
         distance
     }
 
+    /// Returns the pairs of non-empty routes whose bounding boxes intersect.
+    /// The depot is not part of the bounding boxes
+    pub fn overlapping_route_pairs(&self, problem: &Problem) -> Vec<(usize, usize)> {
+        let boxes: Vec<(usize, (Coordinate, Coordinate))> = self
+            .phenotype
+            .iter()
+            .enumerate()
+            .filter(|(_, route)| !route.is_empty())
+            .map(|(index, route)| (index, problem.bounding_box(route)))
+            .collect();
+
+        let mut pairs = Vec::new();
+        for (i, &(route_one, (min_one, max_one))) in boxes.iter().enumerate() {
+            for &(route_two, (min_two, max_two)) in boxes.iter().skip(i + 1) {
+                if min_one.lng.approx_lte(max_two.lng)
+                    && min_two.lng.approx_lte(max_one.lng)
+                    && min_one.lat.approx_lte(max_two.lat)
+                    && min_two.lat.approx_lte(max_one.lat)
+                {
+                    pairs.push((route_one, route_two));
+                }
+            }
+        }
+        pairs
+    }
+
+    /// Returns the legs of the route, including the legs from and to the depot.
+    /// An empty route has no legs
+    pub fn route_instructions(&self, ctx: &Context, route_index: usize) -> Vec<Instruction> {
+        let route = &self.phenotype[route_index];
+        if route.is_empty() {
+            return Vec::new();
+        }
+        std::iter::once(&0)
+            .chain(route.iter())
+            .zip(route.iter().chain(std::iter::once(&0)))
+            .map(|(&from, &to)| Instruction {
+                from,
+                to,
+                distance: ctx.matrix_provider.distance.get(from, to),
+            })
+            .collect()
+    }
+
+    /// Returns the customer of the route with the least total distance to the other customers
+    /// of the route. Ties go to the first customer on the route. An empty route has no medoid
+    pub fn route_medoid(&self, ctx: &Context, route_index: usize) -> Option<usize> {
+        let distance_matrix = &ctx.matrix_provider.distance;
+        let route = &self.phenotype[route_index];
+        route
+            .iter()
+            .map(|&customer| {
+                let total: f64 = route
+                    .iter()
+                    .map(|&other| distance_matrix.get(customer, other))
+                    .sum();
+                (customer, total)
+            })
+            .fold(
+                None,
+                |medoid: Option<(usize, f64)>, (customer, total)| match medoid {
+                    Some((_, best_total)) if !total.approx_lt(best_total) => medoid,
+                    _ => Some((customer, total)),
+                },
+            )
+            .map(|(customer, _)| customer)
+    }
+
+    /// Returns for every node the distance saved by removing it from its route.
+    /// The depot has no saving
+    pub fn removal_savings(&self, ctx: &Context) -> Vec<f64> {
+        let distance_matrix = &ctx.matrix_provider.distance;
+        let mut savings = vec![0.0; ctx.problem.dim()];
+        for route in self.phenotype.iter() {
+            for (index, &node) in route.iter().enumerate() {
+                let predecessor = if index > 0 { route[index - 1] } else { 0 };
+                let successor = route.get(index + 1).copied().unwrap_or(0);
+                savings[node] = distance_matrix.get(predecessor, node)
+                    + distance_matrix.get(node, successor)
+                    - distance_matrix.get(predecessor, successor);
+            }
+        }
+        savings
+    }
+
+    /// Returns the routes with a remaining capacity of at least `min_slack`, together with the
+    /// remaining capacity. Uses the loads of the last evaluation and includes empty routes
+    pub fn routes_with_slack(&self, min_slack: f64) -> Vec<(usize, f64)> {
+        self.evaluation
+            .routes
+            .iter()
+            .enumerate()
+            .map(|(index, route)| (index, -route.overload))
+            .filter(|&(_, slack)| slack.approx_gte(min_slack))
+            .collect()
+    }
+
+    /// Loads of the non-empty routes from the last evaluation
+    pub fn route_loads(&self, ctx: &Context) -> Vec<f64> {
+        let capacity = ctx.problem.vehicle.cap;
+        self.phenotype
+            .iter()
+            .zip(self.evaluation.routes.iter())
+            .filter(|(route, _)| !route.is_empty())
+            .map(|(_, evaluation)| evaluation.overload + capacity)
+            .collect()
+    }
+
+    /// Number of non-empty routes with a load in each bucket [k * bucket_size, (k + 1) *
+    /// bucket_size), from the first bucket up to the one holding the largest load
+    pub fn load_histogram(&self, ctx: &Context, bucket_size: f64) -> Vec<(Range<f64>, usize)> {
+        let loads = self.route_loads(ctx);
+        let bucket = |load: f64| (load / bucket_size + EPSILON).floor() as usize;
+        let num_buckets = loads
+            .iter()
+            .map(|&load| bucket(load) + 1)
+            .max()
+            .unwrap_or(0);
+        let mut counts = vec![0; num_buckets];
+        for &load in loads.iter() {
+            counts[bucket(load)] += 1;
+        }
+        counts
+            .into_iter()
+            .enumerate()
+            .map(|(k, count)| (k as f64 * bucket_size..(k + 1) as f64 * bucket_size, count))
+            .collect()
+    }
+
+    /// Applies the best intra-route 3-opt move to every route until none improves it. Updates
+    /// the genotype and evaluation if any route improved
+    pub fn improve_three_opt(&mut self, ctx: &Context) -> bool {
+        let epsilon = ctx.config.borrow().float_epsilon;
+        let mut improved = false;
+        for route in self.phenotype.iter_mut() {
+            while let Some(three_opt) = route_3opt_best_move(
+                route,
+                &ctx.matrix_provider.distance,
+                &ctx.matrix_provider.correlation,
+                epsilon,
+            ) {
+                apply_3opt(route, &three_opt);
+                improved = true;
+            }
+        }
+        if improved {
+            self.genotype = self.phenotype.iter().flatten().copied().collect();
+            self.evaluate(ctx);
+        }
+        improved
+    }
+
+    /// Re-sequences every route with at most `threshold` customers optimally by Held-Karp. The
+    /// threshold is capped at `MAX_EXACT_ROUTE`. Updates the genotype and evaluation if any
+    /// route improved
+    pub fn improve_exact(&mut self, ctx: &Context, threshold: usize) -> bool {
+        let threshold = threshold.min(MAX_EXACT_ROUTE);
+        let epsilon = ctx.config.borrow().float_epsilon;
+        let distance_matrix = &ctx.matrix_provider.distance;
+        let mut improved = false;
+        for route in self.phenotype.iter_mut() {
+            if route.len() < 3 || route.len() > threshold {
+                continue;
+            }
+            let (order, distance) = held_karp_route(route, distance_matrix);
+            if distance.approx_lt_eps(distance_matrix.route_distance(route), epsilon) {
+                *route = order;
+                improved = true;
+            }
+        }
+        if improved {
+            self.genotype = self.phenotype.iter().flatten().copied().collect();
+            self.evaluate(ctx);
+        }
+        improved
+    }
+
+    /// Markdown summary of the last evaluation: the objective and its penalties, the vehicles
+    /// used, the distances, the capacity utilization, any infeasibilities and a table of the
+    /// routes. Routes are
+    /// numbered from 1 over the non-empty routes, as in the solution file
+    pub fn to_report(&self, ctx: &Context) -> String {
+        let capacity = ctx.problem.vehicle.cap;
+        let routes: Vec<(&Vec<usize>, &RouteEvaluation)> = self
+            .phenotype
+            .iter()
+            .zip(self.evaluation.routes.iter())
+            .filter(|(route, _)| !route.is_empty())
+            .collect();
+        let num_routes = routes.len();
+        let distance = self.evaluation.distance();
+        let load: f64 = routes
+            .iter()
+            .map(|(_, evaluation)| evaluation.overload + capacity)
+            .sum();
+        let percentage = |load: f64, capacity: f64| match capacity > 0.0 {
+            true => 100.0 * load / capacity,
+            false => 0.0,
+        };
+
+        let mut report = String::from("# Solution report\n\n");
+        report.push_str(&format!("- Objective: {:.2}\n", self.penalized_cost()));
+        if self.evaluation.short_route_penalty > 0.0 {
+            report.push_str(&format!(
+                "- Penalty for routes with too few customers: {:.2}\n",
+                self.evaluation.short_route_penalty
+            ));
+        }
+        report.push_str(&format!(
+            "- Feasible: {}\n",
+            if self.is_feasible() { "yes" } else { "no" }
+        ));
+        report.push_str(&format!("- Vehicles used: {}\n", num_routes));
+        report.push_str(&format!("- Total distance: {:.2}\n", distance));
+        report.push_str(&format!(
+            "- Average route distance: {:.2}\n",
+            distance / num_routes.max(1) as f64
+        ));
+        report.push_str(&format!(
+            "- Capacity utilization: {:.1}%\n",
+            percentage(load, num_routes as f64 * capacity)
+        ));
+
+        if !self.is_feasible() {
+            let max_locations = ctx.config.borrow().max_distinct_locations_per_route;
+            report.push_str("\n## Infeasibilities\n\n");
+            for (number, (route, evaluation)) in routes.iter().enumerate() {
+                if evaluation.overload.approx_gt(0.0) {
+                    report.push_str(&format!(
+                        "- Route {} exceeds the capacity by {:.2}\n",
+                        number + 1,
+                        evaluation.overload
+                    ));
+                }
+                if let Some(max_locations) = max_locations {
+                    let locations = ctx.problem.num_locations(route);
+                    if locations > max_locations {
+                        report.push_str(&format!(
+                            "- Route {} visits {} distinct locations, more than {}\n",
+                            number + 1,
+                            locations,
+                            max_locations
+                        ));
+                    }
+                }
+            }
+        }
+
+        report.push_str("\n## Routes\n\n");
+        report.push_str("| Route | Customers | Distance | Load | Utilization |\n");
+        report.push_str("|---|---|---|---|---|\n");
+        for (number, (route, evaluation)) in routes.iter().enumerate() {
+            let load = evaluation.overload + capacity;
+            report.push_str(&format!(
+                "| {} | {} | {:.2} | {:.2} | {:.1}% |\n",
+                number + 1,
+                route.len(),
+                evaluation.distance,
+                load,
+                percentage(load, capacity)
+            ));
+        }
+        report
+    }
+
+    /// Returns the index and duration of the longest route, which determines the makespan.
+    /// Durations are the route distances of the last evaluation, which include the visiting
+    /// costs on the diagonal when it is honored. Ties go to the lowest route index
+    pub fn critical_route(&self) -> (usize, f64) {
+        self.evaluation
+            .routes
+            .iter()
+            .enumerate()
+            .fold((0, 0.0), |longest, (index, route)| {
+                if route.distance.approx_gt(longest.1) {
+                    (index, route.distance)
+                } else {
+                    longest
+                }
+            })
+    }
+
+    /// Moves the customers of every cluster to one of the routes serving the cluster, each at
+    /// its cheapest position. The route with the least overload after the move is chosen, then
+    /// the one serving most of the cluster. The genotype and evaluation are not updated
+    pub fn consolidate_clusters(&mut self, ctx: &Context) {
+        let distance_matrix = &ctx.matrix_provider.distance;
+        let demand = |node: &usize| ctx.problem.nodes[*node].demand;
+        for cluster in ctx.problem.clusters.iter() {
+            let routes: Vec<usize> = cluster
+                .iter()
+                .map(|node| {
+                    self.phenotype
+                        .iter()
+                        .position(|route| route.contains(node))
+                        .expect("Cluster customer is not in a route")
+                })
+                .collect();
+
+            // Overload and negated number of cluster customers of every candidate route
+            let candidates: Vec<(f64, i64, usize)> = routes
+                .iter()
+                .map(|&route| {
+                    let load = self.phenotype[route].iter().map(demand).sum::<f64>()
+                        + cluster
+                            .iter()
+                            .zip(routes.iter())
+                            .filter(|&(_, &other)| other != route)
+                            .map(|(node, _)| demand(node))
+                            .sum::<f64>();
+                    let count = routes.iter().filter(|&&other| other == route).count();
+                    let overload = 0f64.max(load - ctx.problem.vehicle.cap);
+                    (overload, -(count as i64), route)
+                })
+                .collect();
+            let target = candidates
+                .iter()
+                .min_by(|a, b| a.partial_cmp(b).unwrap())
+                .expect("Empty cluster")
+                .2;
+
+            for (&node, &route) in cluster.iter().zip(routes.iter()) {
+                if route == target {
+                    continue;
+                }
+                self.phenotype[route].retain(|&other| other != node);
+                let target_route = &self.phenotype[target];
+                let insert_cost = |position: usize| {
+                    let prev = if position == 0 {
+                        0
+                    } else {
+                        target_route[position - 1]
+                    };
+                    let next = target_route.get(position).copied().unwrap_or(0);
+                    distance_matrix.get(prev, node) + distance_matrix.get(node, next)
+                        - distance_matrix.get(prev, next)
+                };
+                let position = (0..=target_route.len())
+                    .min_by(|&a, &b| insert_cost(a).partial_cmp(&insert_cost(b)).unwrap())
+                    .unwrap();
+                self.phenotype[target].insert(position, node);
+            }
+        }
+    }
+
     pub fn sort_routes(&mut self, ctx: &Context) {
         let mut sorted_angles: Vec<(f64, usize)> = Vec::new();
 
@@ -189,3 +853,414 @@ impl Ord for Individual {
 }
 
 impl Eq for Individual {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::utils::{coordinate_context, coordinate_problem, instance_context, problem_context};
+
+    #[test]
+    fn random_feasible_individual() {
+        let ctx = instance_context(|_| {});
+        let individual = Individual::random_feasible(&ctx, 100).expect("No feasible individual");
+        assert!(individual.is_feasible());
+
+        let mut customers: Vec<usize> = individual.phenotype.iter().flatten().copied().collect();
+        customers.sort_unstable();
+        assert_eq!((1..ctx.problem.dim()).collect::<Vec<_>>(), customers);
+        for route in individual.phenotype.iter() {
+            let load: f64 = route
+                .iter()
+                .map(|&node| ctx.problem.nodes[node].demand)
+                .sum();
+            assert!(load.approx_lte(ctx.problem.vehicle.cap));
+        }
+    }
+
+    #[test]
+    fn mutations_keep_permutation() {
+        let ctx = instance_context(|_| {});
+        let customers: Vec<usize> = (1..ctx.problem.dim()).collect();
+        let mutations = [
+            Mutation::ReverseSegment,
+            Mutation::InsertionShuffle(10),
+            Mutation::RandomRelocate(5),
+        ];
+        for &mutation in mutations.iter() {
+            ctx.config.borrow_mut().mutation = Some(mutation);
+            ctx.config.borrow_mut().mutation_rate = 1.0;
+            let mut individual = Individual::new(customers.clone(), 0);
+            for _ in 0..20 {
+                assert!(individual.mutate(&ctx));
+            }
+            assert_ne!(customers, individual.genotype, "{:?}", mutation);
+            let mut genotype = individual.genotype.clone();
+            genotype.sort_unstable();
+            assert_eq!(customers, genotype, "{:?}", mutation);
+
+            ctx.config.borrow_mut().mutation_rate = 0.0;
+            let genotype = individual.genotype.clone();
+            for _ in 0..100 {
+                assert!(!individual.mutate(&ctx));
+            }
+            assert_eq!(genotype, individual.genotype);
+        }
+    }
+
+    #[test]
+    fn random_feasible_individual_without_attempts() {
+        let ctx = instance_context(|_| {});
+        assert!(Individual::random_feasible(&ctx, 0).is_none());
+    }
+
+    #[test]
+    fn overlapping_routes() {
+        let coords = [
+            (0.0, 0.0),
+            (-10.0, 0.0),
+            (-8.0, 2.0),
+            (8.0, 0.0),
+            (10.0, 2.0),
+            (-9.0, 1.0),
+            (9.0, 1.0),
+        ];
+        let problem = coordinate_problem(&coords, 10.0);
+
+        let mut individual = Individual::empty();
+        // One route on each side of the depot
+        individual.phenotype = vec![vec![1, 2, 5], vec![], vec![3, 4, 6]];
+        let (min, max) = problem.bounding_box(&individual.phenotype[0]);
+        assert_eq!(
+            (-10.0, 0.0, -8.0, 2.0),
+            (min.lng, min.lat, max.lng, max.lat)
+        );
+        assert!(individual.overlapping_route_pairs(&problem).is_empty());
+
+        // Both routes visit both sides
+        individual.phenotype = vec![vec![1, 4, 5], vec![], vec![3, 2, 6]];
+        assert_eq!(vec![(0, 2)], individual.overlapping_route_pairs(&problem));
+    }
+
+    #[test]
+    fn instructions_of_route() {
+        let coords = [(0.0, 0.0), (3.0, 0.0), (3.0, 4.0), (0.0, 4.0)];
+        let ctx = coordinate_context(&coords);
+
+        let mut individual = Individual::empty();
+        individual.phenotype = vec![vec![1, 2, 3], vec![]];
+        let legs: Vec<(usize, usize, f64)> = individual
+            .route_instructions(&ctx, 0)
+            .iter()
+            .map(|leg| (leg.from, leg.to, leg.distance))
+            .collect();
+        assert_eq!(
+            vec![(0, 1, 3.0), (1, 2, 4.0), (2, 3, 3.0), (3, 0, 4.0)],
+            legs
+        );
+        assert!(individual.route_instructions(&ctx, 1).is_empty());
+    }
+
+    #[test]
+    fn central_customer_is_medoid() {
+        // Customer 4 is in the middle of the others
+        let coords = [
+            (0.0, 0.0),
+            (10.0, 0.0),
+            (14.0, 0.0),
+            (12.0, 2.0),
+            (12.0, 0.0),
+        ];
+        let ctx = coordinate_context(&coords);
+
+        let mut individual = Individual::empty();
+        individual.phenotype = vec![vec![1, 2, 3, 4], vec![], vec![2]];
+        assert_eq!(Some(4), individual.route_medoid(&ctx, 0));
+        assert_eq!(None, individual.route_medoid(&ctx, 1));
+        assert_eq!(Some(2), individual.route_medoid(&ctx, 2));
+    }
+
+    #[test]
+    fn outlier_has_largest_removal_saving() {
+        let coords = [(0.0, 0.0), (1.0, 0.0), (2.0, 0.0), (2.0, 20.0), (3.0, 0.0)];
+        let ctx = coordinate_context(&coords);
+
+        let mut individual = Individual::empty();
+        individual.phenotype = vec![vec![1, 2, 3, 4]];
+        let savings = individual.removal_savings(&ctx);
+        assert_eq!(0.0, savings[0]);
+        assert_eq!(39.0, savings[3]);
+        let largest = (1..5)
+            .max_by(|&a, &b| savings[a].partial_cmp(&savings[b]).unwrap())
+            .unwrap();
+        assert_eq!(3, largest);
+    }
+
+    #[test]
+    fn critical_route_is_longest() {
+        let coords = [(0.0, 0.0), (3.0, 0.0), (3.0, 4.0), (-6.0, 0.0), (0.0, -2.0)];
+        let ctx = coordinate_context(&coords);
+
+        let mut individual = Individual::empty();
+        // Routes 1 and 3 both take 12, the first of them is returned
+        individual.phenotype = vec![vec![4], vec![1, 2], vec![], vec![3]];
+        individual.evaluate(&ctx);
+        assert_eq!((1, 12.0), individual.critical_route());
+
+        individual.phenotype = vec![vec![4], vec![1, 2, 3]];
+        individual.evaluate(&ctx);
+        assert_eq!((1, 3.0 + 4.0 + 10.0 + 6.0), individual.critical_route());
+    }
+
+    #[test]
+    fn lexicographic_objectives() {
+        let coords = [(0.0, 0.0), (0.0, 3.0), (0.0, 4.0), (0.0, -5.0)];
+        let ctx = coordinate_context(&coords);
+        let individual = |phenotype: Vec<Vec<usize>>| {
+            let mut individual = Individual::empty();
+            individual.phenotype = phenotype;
+            individual.evaluate(&ctx);
+            individual
+        };
+        // Two routes each, with distances 18 and 24
+        let short = individual(vec![vec![1, 2], vec![3]]);
+        let long = individual(vec![vec![2], vec![1, 3]]);
+        // One route with distance 18
+        let single = individual(vec![vec![1, 2, 3]]);
+        assert_eq!(18.0, single.evaluation.distance());
+
+        // The vehicles tie, the distance decides
+        let order = [ObjectiveTerm::Vehicles, ObjectiveTerm::Distance];
+        assert_eq!(
+            Ordering::Less,
+            short.compare_objectives(&long, &order, EPSILON)
+        );
+        assert_eq!(
+            Ordering::Greater,
+            long.compare_objectives(&short, &order, EPSILON)
+        );
+        assert_eq!(
+            Ordering::Less,
+            single.compare_objectives(&short, &order, EPSILON)
+        );
+
+        // The distance ties, the vehicles decide
+        let order = [ObjectiveTerm::Distance, ObjectiveTerm::Vehicles];
+        assert_eq!(
+            Ordering::Greater,
+            short.compare_objectives(&single, &order, EPSILON)
+        );
+        let order = [ObjectiveTerm::Distance];
+        assert_eq!(
+            Ordering::Equal,
+            short.compare_objectives(&single, &order, EPSILON)
+        );
+    }
+
+    #[test]
+    fn objective_order_is_total() {
+        // Consecutive costs tie within epsilon, but the first and last do not
+        let epsilon = 1.0;
+        let individuals: Vec<Individual> = [0.0, 0.6, 1.2]
+            .iter()
+            .map(|&cost| {
+                let mut individual = Individual::empty();
+                individual.evaluation.penalized_cost = cost;
+                individual
+            })
+            .collect();
+        let order = [ObjectiveTerm::PenalizedCost];
+        let compare = |i: usize, j: usize| {
+            individuals[i].compare_objectives(&individuals[j], &order, epsilon)
+        };
+        assert_eq!(Ordering::Equal, compare(0, 1));
+        assert_eq!(Ordering::Equal, compare(1, 2));
+        assert_eq!(Ordering::Less, compare(0, 2));
+
+        for i in 0..individuals.len() {
+            for j in 0..individuals.len() {
+                assert_eq!(
+                    i.cmp(&j),
+                    individuals[i].order_objectives(&individuals[j], &order)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn load_histogram() {
+        let coords: Vec<(f64, f64)> = (0..12).map(|i| (i as f64, 0.0)).collect();
+        let ctx = coordinate_context(&coords);
+
+        let mut individual = Individual::empty();
+        // Loads 1, 3, 4, 0 and 3, the empty route is left out
+        individual.phenotype = vec![
+            vec![1],
+            vec![2, 3, 4],
+            vec![5, 6, 7, 8],
+            vec![],
+            vec![9, 10, 11],
+        ];
+        individual.evaluate(&ctx);
+        assert_eq!(vec![1.0, 3.0, 4.0, 3.0], individual.route_loads(&ctx));
+        assert_eq!(
+            vec![(0.0..2.0, 1), (2.0..4.0, 2), (4.0..6.0, 1)],
+            individual.load_histogram(&ctx, 2.0)
+        );
+        assert_eq!(vec![(0.0..5.0, 4)], individual.load_histogram(&ctx, 5.0));
+    }
+
+    #[test]
+    fn routes_with_slack() {
+        let coords: Vec<(f64, f64)> = (0..10).map(|i| (i as f64, 0.0)).collect();
+        let ctx = coordinate_context(&coords);
+
+        let mut individual = Individual::empty();
+        individual.phenotype = vec![(1..9).collect(), vec![9], Vec::new()];
+        individual.evaluate(&ctx);
+        assert_eq!(vec![(1, 9.0), (2, 10.0)], individual.routes_with_slack(5.0));
+        assert_eq!(3, individual.routes_with_slack(2.0).len());
+        assert!(individual.routes_with_slack(11.0).is_empty());
+    }
+
+    #[test]
+    fn routes_are_cached() {
+        let ctx = instance_context(|_| {});
+
+        let mut split = Split::new(&ctx);
+        let mut individual = Individual::new_random(&ctx, 0);
+        let mut recomputed = individual.clone();
+        let max_vehicles = ctx.config.borrow().num_vehicles;
+        let routes = individual.routes(&ctx, &mut split, max_vehicles).to_vec();
+        assert_eq!(routes, individual.routes(&ctx, &mut split, max_vehicles));
+        assert_eq!(1, split.runs);
+        assert!(!individual.is_split_pending());
+
+        Split::new(&ctx).run(&ctx, &mut recomputed, max_vehicles);
+        assert_eq!(recomputed.phenotype, routes);
+        assert_eq!(recomputed.penalized_cost(), individual.penalized_cost());
+
+        // Changing the genotype requires a new split
+        individual.apply_mutation(&ctx, Mutation::ReverseSegment);
+        assert!(individual.is_split_pending());
+        individual.routes(&ctx, &mut split, max_vehicles);
+        individual.routes(&ctx, &mut split, max_vehicles);
+        assert_eq!(2, split.runs);
+    }
+
+    #[test]
+    fn markdown_report() {
+        let ctx = instance_context(|_| {});
+        let mut individual = Individual::new_random(&ctx, 0);
+        let max_vehicles = ctx.config.borrow().num_vehicles;
+        Split::new(&ctx).run(&ctx, &mut individual, max_vehicles);
+
+        let report = individual.to_report(&ctx);
+        let num_routes = individual.num_nonempty_routes();
+        assert!(report.starts_with("# Solution report\n"));
+        assert!(report.contains(&format!(
+            "- Objective: {:.2}\n",
+            individual.penalized_cost()
+        )));
+        assert!(report.contains(&format!("- Vehicles used: {}\n", num_routes)));
+        let route_rows = report
+            .lines()
+            .skip_while(|line| !line.starts_with("|---"))
+            .skip(1)
+            .count();
+        assert_eq!(num_routes, route_rows);
+        assert_eq!(
+            !individual.is_feasible(),
+            report.contains("## Infeasibilities")
+        );
+    }
+
+    #[test]
+    fn report_lists_every_infeasibility() {
+        // Customers 1 and 2 share a location, customer 3 is elsewhere
+        let problem = coordinate_problem(&[(0.0, 0.0), (1.0, 0.0), (1.0, 0.0), (2.0, 0.0)], 2.0);
+        let mut config = Config::default();
+        config.max_distinct_locations_per_route = Some(1);
+        config.min_customers_per_route = Some(2);
+        let ctx = problem_context(problem, None, config);
+        let num_routes = ctx.config.borrow().num_vehicles as usize;
+        let mut individual = Individual::new(vec![1, 2, 3], 0);
+        individual.phenotype = vec![Vec::new(); num_routes];
+        individual.phenotype[0] = vec![1, 2];
+        individual.phenotype[1] = vec![3];
+        individual.evaluate(&ctx);
+        assert!(individual.is_feasible());
+
+        let report = individual.to_report(&ctx);
+        assert!(!report.contains("## Infeasibilities"));
+        let (costs, _) = report.split_once("## Routes").unwrap();
+        assert!(costs.contains("- Penalty for routes with too few customers: "));
+
+        individual.phenotype[0] = vec![1, 3];
+        individual.phenotype[1] = vec![2];
+        individual.evaluate(&ctx);
+        assert!(!individual.is_feasible());
+        let report = individual.to_report(&ctx);
+        let (_, infeasibilities) = report.split_once("## Infeasibilities").unwrap();
+        assert!(infeasibilities.contains("- Route 1 visits 2 distinct locations, more than 1\n"));
+    }
+
+    #[test]
+    fn insertion_constraints() {
+        // Customers 1 and 2 share a location
+        let problem = coordinate_problem(
+            &[(0.0, 0.0), (1.0, 0.0), (1.0, 0.0), (2.0, 0.0), (0.0, 2.0)],
+            3.0,
+        );
+        let mut config = Config::default();
+        config.max_distinct_locations_per_route = Some(2);
+        let ctx = problem_context(problem, None, config);
+        let mut individual = Individual::new(vec![1, 2, 4, 3], 0);
+        individual.phenotype = vec![vec![1, 2, 4], vec![3]];
+        individual.evaluate(&ctx);
+
+        let violations = individual.can_insert(&ctx, 3, 0, 3).unwrap_err();
+        assert_eq!(
+            vec![
+                ConstraintViolation::Capacity(4.0, 3.0),
+                ConstraintViolation::DistinctLocations(3, 2)
+            ],
+            violations
+        );
+
+        let delta = individual
+            .can_insert(&ctx, 4, 1, 1)
+            .expect("Feasible insertion");
+        let distance = individual.evaluation.distance();
+        individual.phenotype = vec![vec![1, 2], vec![3, 4]];
+        individual.evaluate(&ctx);
+        assert!(individual.is_feasible());
+        assert!(delta.approx_eq(individual.evaluation.distance() - distance));
+    }
+
+    #[test]
+    fn exact_resequencing() {
+        let ctx = coordinate_context(&[
+            (0.0, 0.0),
+            (-2.0, 2.0),
+            (-4.0, 6.0),
+            (-4.0, -1.0),
+            (5.0, -4.0),
+            (8.0, 2.0),
+            (1.0, 2.0),
+        ]);
+        let mut individual = Individual::empty();
+        individual.phenotype = vec![vec![1, 2, 3, 4, 5, 6], Vec::new()];
+        individual.evaluate(&ctx);
+        let cost = individual.penalized_cost();
+
+        // The route is above the threshold
+        assert!(!individual.improve_exact(&ctx, 5));
+        assert_eq!(vec![1, 2, 3, 4, 5, 6], individual.phenotype[0]);
+
+        assert!(individual.improve_exact(&ctx, 6));
+        assert!(individual.penalized_cost() < cost);
+        assert_eq!(individual.phenotype[0], individual.genotype);
+        assert!(!individual.improve_exact(&ctx, 6));
+    }
+}
@@ -1,10 +1,16 @@
+mod archive;
+pub use self::archive::*;
 mod decomposition;
 pub use self::decomposition::*;
 mod individual;
 pub use self::individual::*;
 mod genetic;
 pub use self::genetic::*;
+mod pareto;
+pub use self::pareto::*;
 mod population;
 pub use self::population::*;
 mod split;
 pub use self::split::*;
+mod two_stage;
+pub use self::two_stage::*;
@@ -0,0 +1,95 @@
+use std::time::Instant;
+
+use crate::config::Config;
+use crate::models::Problem;
+use crate::solver::genetic::{GeneticAlgorithm, Individual};
+use crate::solver::{Context, HistoricSolution, Solver};
+use crate::utils::{FloatCompare, ProblemParser};
+
+/// Runs the genetic algorithm with the Pareto archive and returns the non-dominated solutions
+/// over the number of vehicles and the total distance, sorted by the number of vehicles
+pub fn solve_pareto(problem: Problem, config: Config) -> Vec<HistoricSolution> {
+    let mut config = config;
+    config.pareto_front = true;
+    let mut parser = ProblemParser::new();
+    parser.problem = Some(problem);
+    let ctx = Context::new(parser, config, Instant::now());
+    let metaheuristic = GeneticAlgorithm::new(&ctx);
+    let mut solver = Solver::new(ctx, metaheuristic);
+    solver.run();
+    solver
+        .metaheuristic
+        .pareto_front
+        .individuals
+        .iter()
+        .map(HistoricSolution::from)
+        .collect()
+}
+
+/// Archive of feasible individuals that are non-dominated with respect to
+/// the number of vehicles and the total distance
+#[derive(Debug, Clone, Default)]
+pub struct ParetoFront {
+    pub individuals: Vec<Individual>,
+}
+
+impl ParetoFront {
+    pub fn new() -> Self {
+        Self {
+            individuals: Vec::new(),
+        }
+    }
+
+    // The objectives of an individual: (vehicles, distance)
+    fn objectives(individual: &Individual) -> (usize, f64) {
+        (
+            individual.num_nonempty_routes(),
+            individual.evaluation.distance(),
+        )
+    }
+
+    // Returns true if `a` is at least as good as `b` in both objectives
+    fn weakly_dominates(a: (usize, f64), b: (usize, f64)) -> bool {
+        a.0 <= b.0 && a.1.approx_lte(b.1)
+    }
+
+    /// Returns true if both individuals are feasible, and `a` is at least as good as `b` in
+    /// both objectives and better in one of them
+    pub fn dominates(a: &Individual, b: &Individual) -> bool {
+        if !a.is_feasible() || !b.is_feasible() {
+            return false;
+        }
+        let (a, b) = (Self::objectives(a), Self::objectives(b));
+        Self::weakly_dominates(a, b) && !Self::weakly_dominates(b, a)
+    }
+
+    /// Adds the individual if it is feasible and not dominated by the archive.
+    /// Individuals dominated by the new one are removed. Returns true if it was added
+    pub fn add(&mut self, individual: &Individual) -> bool {
+        if !individual.is_feasible() {
+            return false;
+        }
+        let objectives = Self::objectives(individual);
+        if self
+            .individuals
+            .iter()
+            .any(|other| Self::weakly_dominates(Self::objectives(other), objectives))
+        {
+            return false;
+        }
+        self.individuals
+            .retain(|other| !Self::weakly_dominates(objectives, Self::objectives(other)));
+        self.individuals.push(individual.clone());
+        self.individuals
+            .sort_by_key(|individual| individual.num_nonempty_routes());
+        true
+    }
+
+    pub fn len(&self) -> usize {
+        self.individuals.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.individuals.is_empty()
+    }
+}
@@ -3,7 +3,8 @@ use std::collections::HashMap;
 
 use ahash::RandomState;
 
-use crate::solver::genetic::Individual;
+use crate::config::{ParentSelection, Replacement};
+use crate::solver::genetic::{Individual, ParetoFront};
 use crate::solver::Context;
 
 #[derive(Debug)]
@@ -50,14 +51,15 @@ pub struct SubPopulation {
 
     pub focus_diversity: bool,
 
+    // Number of individuals left after natural selection
+    min_individuals: usize,
     // Maximum allowed individuals in the population
     max_individuals: usize,
 }
 
 impl SubPopulation {
-    pub fn new(ctx: &Context) -> Self {
-        let config = ctx.config.borrow();
-        let max_individuals = (config.min_population_size + config.population_lambda) as usize;
+    pub fn new(ctx: &Context, min_individuals: usize, lambda: usize) -> Self {
+        let max_individuals = min_individuals + lambda;
         Self {
             population: Vec::new(),
             diversity: HashMap::with_capacity_and_hasher(
@@ -65,6 +67,7 @@ impl SubPopulation {
                 ctx.random.random_state(),
             ),
             focus_diversity: false,
+            min_individuals,
             max_individuals,
         }
     }
@@ -75,7 +78,7 @@ impl SubPopulation {
 
     pub fn add_individual(&mut self, ctx: &Context, individual: Individual) {
         // To always keep the vec sorted we find the insert index with a binary search
-        let insert_index = match self.population.binary_search(&individual) {
+        let insert_index = match self.binary_search(ctx, &individual) {
             Ok(index) => index,
             Err(index) => index,
         };
@@ -94,13 +97,66 @@ impl SubPopulation {
             self.update_fitness(ctx);
         }
 
-        // If the population has reached the max size, perform natural selection until the population
-        // has min_population_size individuals left
-        if self.population.len() >= self.max_individuals {
-            let min_population_size = ctx.config.borrow().min_population_size as usize;
-            while self.population.len() > min_population_size {
-                self.natural_selection(ctx);
+        let replacement = ctx.config.borrow().replacement;
+        match replacement {
+            // If the population has reached the max size, perform natural selection until the
+            // population has min_individuals individuals left
+            Replacement::BiasedFitness => {
+                if self.population.len() >= self.max_individuals {
+                    while self.population.len() > self.min_individuals {
+                        self.natural_selection(ctx);
+                    }
+                }
+            }
+            _ => {
+                if self.population.len() > self.max_individuals {
+                    let index = self.replaced_index(ctx, replacement, insert_index);
+                    self.remove_individual(ctx, index);
+                }
+            }
+        }
+    }
+
+    // Individual replaced by the offspring at the index, never the best
+    fn replaced_index(&self, ctx: &Context, replacement: Replacement, offspring: usize) -> usize {
+        match replacement {
+            Replacement::ReplaceMostSimilar => {
+                let number = self.population[offspring].number;
+                let closest = self.diversity[&number][0].to_number;
+                let closest_index = self
+                    .population
+                    .iter()
+                    .position(|individual| individual.number == closest)
+                    .expect("Closest individual is not in the population");
+                // The population is sorted, so the later one is worse
+                closest_index.max(offspring)
             }
+            Replacement::SteadyState => ctx.random.range_usize(1, self.population.len()),
+            _ => self.population.len() - 1,
+        }
+    }
+
+    // Position of the individual in the population, ordered by penalized cost or by the
+    // configured objective order
+    fn binary_search(&self, ctx: &Context, individual: &Individual) -> Result<usize, usize> {
+        let config = ctx.config.borrow();
+        if config.objective_order.is_empty() {
+            self.population.binary_search(individual)
+        } else {
+            self.population.binary_search_by(|probe| {
+                probe.order_objectives(individual, &config.objective_order)
+            })
+        }
+    }
+
+    /// Sorts the population by penalized cost or by the configured objective order
+    pub fn sort(&mut self, ctx: &Context) {
+        let config = ctx.config.borrow();
+        if config.objective_order.is_empty() {
+            self.population.sort();
+        } else {
+            self.population
+                .sort_by(|a, b| a.order_objectives(b, &config.objective_order));
         }
     }
 
@@ -111,12 +167,9 @@ impl SubPopulation {
     }
 
     /// Calculates the average diversity in the population
-    pub fn get_diversity(&self, ctx: &Context) -> f64 {
-        // Only include the min_population_size best individuals
-        let size = min(
-            self.size(),
-            ctx.config.borrow().min_population_size as usize,
-        );
+    pub fn get_diversity(&self) -> f64 {
+        // Only include the min_individuals best individuals
+        let size = min(self.size(), self.min_individuals);
 
         let mut total = 0.0;
         for individual in &self.population[..size] {
@@ -130,12 +183,9 @@ impl SubPopulation {
         }
     }
 
-    pub fn get_average_cost(&self, ctx: &Context) -> f64 {
-        // Only include the min_population_size best individuals
-        let size = min(
-            self.size(),
-            ctx.config.borrow().min_population_size as usize,
-        );
+    pub fn get_average_cost(&self) -> f64 {
+        // Only include the min_individuals best individuals
+        let size = min(self.size(), self.min_individuals);
 
         let mut total = 0.0;
         for individual in &self.population[..size] {
@@ -254,7 +304,7 @@ impl SubPopulation {
             return;
         }
 
-        // if self.get_diversity(ctx) < 0.1 {
+        // if self.get_diversity() < 0.1 {
         //     self.focus_diversity = true;
         // } else {
         //     self.focus_diversity = false;
@@ -380,10 +430,23 @@ pub struct Population {
 
 impl Population {
     pub fn new(ctx: &Context) -> Self {
+        let config = ctx.config.borrow();
         Self {
             total_individuals_count: 0,
-            feasible: SubPopulation::new(ctx),
-            infeasible: SubPopulation::new(ctx),
+            feasible: SubPopulation::new(
+                ctx,
+                config.min_population_size as usize,
+                config.population_lambda as usize,
+            ),
+            infeasible: SubPopulation::new(
+                ctx,
+                config
+                    .infeasible_min_population_size
+                    .unwrap_or(config.min_population_size) as usize,
+                config
+                    .infeasible_population_lambda
+                    .unwrap_or(config.population_lambda) as usize,
+            ),
             feasible_history: vec![true; 100],
         }
     }
@@ -414,7 +477,26 @@ impl Population {
     }
 
     pub fn get_parent(&self, ctx: &Context) -> &Individual {
-        self.tournament(ctx, ctx.config.borrow().tournament_size as usize)
+        let (parent_selection, tournament_size) = {
+            let config = ctx.config.borrow();
+            (config.parent_selection, config.tournament_size as usize)
+        };
+        match parent_selection {
+            ParentSelection::Tournament => self.tournament(ctx, tournament_size),
+            ParentSelection::DistinctTournament => self.distinct_tournament(ctx, tournament_size),
+            ParentSelection::RankRoulette => self.rank_roulette(ctx),
+            ParentSelection::Random => self.get(ctx.random.range_usize(0, self.size())),
+        }
+    }
+
+    /// Returns copies of all individuals, the feasible ones first
+    pub fn snapshot(&self) -> Vec<Individual> {
+        self.feasible
+            .population
+            .iter()
+            .chain(self.infeasible.population.iter())
+            .cloned()
+            .collect()
     }
 
     pub fn history_fraction(&self) -> f64 {
@@ -422,29 +504,54 @@ impl Population {
             / self.feasible_history.len() as f64
     }
 
+    // Individual at the index of the two subpopulations chained together
+    fn get(&self, index: usize) -> &Individual {
+        if index < self.feasible.size() {
+            &self.feasible.population[index]
+        } else {
+            &self.infeasible.population[index - self.feasible.size()]
+        }
+    }
+
     fn tournament(&self, ctx: &Context, num_contestants: usize) -> &Individual {
         // let lower = if ctx.random.real() < 0.1 { 0 } else { 1 };
         let lower = 0;
         // Sample `k` individuals from the two subpopulations
-        let indicies: Vec<usize> = (0..num_contestants)
-            .into_iter()
-            .map(|_| ctx.random.range_usize(lower, self.size()))
-            .collect();
+        let indicies = (0..num_contestants).map(|_| ctx.random.range_usize(lower, self.size()));
+        self.winner(ctx, indicies)
+    }
 
-        // Select the winner which has the lowest cost
-        let mut winner: Option<&Individual> = None;
-        for index in indicies {
-            // Select the correct subpopulation and offset the index
-            let (sub_population, population_index) = if index < self.feasible.size() {
-                (&self.feasible, index)
+    fn distinct_tournament(&self, ctx: &Context, num_contestants: usize) -> &Individual {
+        // Sample `k` distinct individuals from the two subpopulations by Floyd's algorithm
+        let size = self.size();
+        let num_contestants = num_contestants.min(size);
+        let mut indicies: Vec<usize> = Vec::with_capacity(num_contestants);
+        for upper in (size - num_contestants)..size {
+            let index = ctx.random.range_usize(0, upper + 1);
+            if indicies.contains(&index) {
+                indicies.push(upper);
             } else {
-                (&self.infeasible, index - self.feasible.size())
-            };
+                indicies.push(index);
+            }
+        }
+        self.winner(ctx, indicies.into_iter())
+    }
 
+    // Individual with the lowest biased fitness among the indices, the first one on ties.
+    // With the Pareto archive, an individual dominating the current winner always wins, and
+    // one dominated by it never does
+    fn winner(&self, ctx: &Context, indicies: impl Iterator<Item = usize>) -> &Individual {
+        let pareto_front = ctx.config.borrow().pareto_front;
+        let mut winner: Option<&Individual> = None;
+        for index in indicies {
             // Update the winner if the individual is better
-            let individual = &sub_population.population[population_index];
+            let individual = self.get(index);
             if let Some(current_winner) = winner {
-                if individual.fitness < current_winner.fitness {
+                if pareto_front && ParetoFront::dominates(individual, current_winner) {
+                    winner = Some(individual);
+                } else if pareto_front && ParetoFront::dominates(current_winner, individual) {
+                    continue;
+                } else if individual.fitness < current_winner.fitness {
                     winner = Some(individual);
                 }
             } else {
@@ -454,4 +561,100 @@ impl Population {
 
         winner.expect("No winner found")
     }
+
+    fn rank_roulette(&self, ctx: &Context) -> &Individual {
+        // The individual with the lowest biased fitness has the highest weight
+        let size = self.size();
+        let mut ranked: Vec<&Individual> = (0..size).map(|index| self.get(index)).collect();
+        ranked.sort_by(|a, b| a.fitness.partial_cmp(&b.fitness).unwrap());
+        let mut draw = ctx.random.range_usize(0, size * (size + 1) / 2);
+        for (rank, individual) in ranked.into_iter().enumerate() {
+            let weight = size - rank;
+            if draw < weight {
+                return individual;
+            }
+            draw -= weight;
+        }
+        unreachable!("Draw exceeds the total weight")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solver::genetic::Split;
+    use crate::utils::instance_context;
+
+    // Smallest broken pairs distance between two individuals of the subpopulation
+    fn min_distance(subpopulation: &SubPopulation) -> i64 {
+        subpopulation
+            .diversity
+            .values()
+            .map(|diversity| diversity[0].distance)
+            .min()
+            .unwrap()
+    }
+
+    // Adds five random individuals and a near-clone of the best one
+    fn add_near_clone(replacement: Replacement) -> SubPopulation {
+        let ctx = instance_context(|config| config.replacement = replacement);
+        let mut split = Split::new(&ctx);
+        let max_vehicles = ctx.config.borrow().num_vehicles;
+
+        let mut subpopulation = SubPopulation::new(&ctx, 4, 1);
+        let mut individuals: Vec<Individual> = Vec::new();
+        for number in 0..5 {
+            let mut individual = Individual::new_random(&ctx, number);
+            split.run(&ctx, &mut individual, max_vehicles);
+            individuals.push(individual.clone());
+            subpopulation.add_individual(&ctx, individual);
+        }
+        assert_eq!(5, subpopulation.size());
+
+        let mut near_clone = individuals
+            .into_iter()
+            .min_by(|a, b| a.penalized_cost().partial_cmp(&b.penalized_cost()).unwrap())
+            .unwrap();
+        near_clone.number = 5;
+        near_clone.genotype.swap(0, 1);
+        split.run(&ctx, &mut near_clone, max_vehicles);
+        subpopulation.add_individual(&ctx, near_clone);
+        assert_eq!(5, subpopulation.size());
+        subpopulation
+    }
+
+    #[test]
+    fn dominating_parent_wins() {
+        let ctx = instance_context(|_| {});
+        let mut split = Split::new(&ctx);
+        let mut individual = Individual::new_random(&ctx, 0);
+        split.run(&ctx, &mut individual, ctx.config.borrow().num_vehicles);
+        individual.evaluation.feasible = true;
+
+        // The same routes but longer, with a better biased fitness
+        let mut dominated = individual.clone();
+        dominated.number = 1;
+        dominated.evaluation.routes[0].distance += 100.0;
+        individual.fitness = 1.0;
+        dominated.fitness = 0.0;
+
+        let mut population = Population::new(&ctx);
+        population.feasible.population = vec![dominated, individual];
+        assert_eq!(1, population.winner(&ctx, 0..2).number);
+        ctx.config.borrow_mut().pareto_front = true;
+        assert_eq!(0, population.winner(&ctx, 0..2).number);
+        assert_eq!(0, population.winner(&ctx, (0..2).rev()).number);
+    }
+
+    #[test]
+    fn replace_most_similar() {
+        let worst = add_near_clone(Replacement::ReplaceWorst);
+        let most_similar = add_near_clone(Replacement::ReplaceMostSimilar);
+        assert!(min_distance(&worst) < min_distance(&most_similar));
+        // The worst individual survives when a near-clone is replaced instead
+        let worst_cost = |subpopulation: &SubPopulation| {
+            subpopulation.population.last().unwrap().penalized_cost()
+        };
+        assert!(worst_cost(&worst) < worst_cost(&most_similar));
+    }
 }
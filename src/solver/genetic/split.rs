@@ -1,6 +1,7 @@
-use std::collections::VecDeque;
+use std::collections::{HashSet, VecDeque};
 
 use crate::models::Matrix;
+use crate::solver::evaluate::{capacity_penalty, location_penalty};
 use crate::solver::genetic::Individual;
 use crate::solver::Context;
 use crate::utils::FloatCompare;
@@ -94,9 +95,20 @@ pub struct Split {
     pub cum_distance: Vec<f64>,
     // Cumulative load for each node
     pub cum_load: Vec<f64>,
+    // Number of routes of the shortest path from node 0 to index
+    pub route_counts: Vec<usize>,
 
     pub vehicle_cap: f64,
     pub penalty_capacity: f64,
+    pub capacity_penalty_tiers: Vec<(f64, f64)>,
+    pub tiebreak: bool,
+    pub balance_weight: f64,
+    pub max_locations: Option<usize>,
+    // Costs within epsilon are equal for the tiebreak
+    pub epsilon: f64,
+
+    // Number of individuals split
+    pub runs: u64,
 }
 
 impl Split {
@@ -108,13 +120,25 @@ impl Split {
             nodes: vec![NodeSplit::new(); ctx.problem.dim()],
             cum_distance: vec![f64::default(); ctx.problem.dim()],
             cum_load: vec![f64::default(); ctx.problem.dim()],
+            route_counts: vec![0; ctx.problem.dim()],
             vehicle_cap: ctx.problem.vehicle.cap,
             penalty_capacity: ctx.config.borrow().penalty_capacity,
+            capacity_penalty_tiers: ctx.config.borrow().capacity_penalty_tiers.clone(),
+            tiebreak: ctx.config.borrow().split_tiebreak,
+            balance_weight: ctx.config.borrow().split_balance_weight,
+            max_locations: ctx.config.borrow().max_distinct_locations_per_route,
+            epsilon: ctx.config.borrow().float_epsilon,
+            runs: 0,
         }
     }
 
     fn load(&mut self, ctx: &Context, individual: &Individual) {
         self.penalty_capacity = ctx.config.borrow().penalty_capacity;
+        self.capacity_penalty_tiers = ctx.config.borrow().capacity_penalty_tiers.clone();
+        self.tiebreak = ctx.config.borrow().split_tiebreak;
+        self.balance_weight = ctx.config.borrow().split_balance_weight;
+        self.max_locations = ctx.config.borrow().max_distinct_locations_per_route;
+        self.epsilon = ctx.config.borrow().float_epsilon;
         let num_nodes = ctx.problem.dim();
         for i in 1..num_nodes {
             let mut node = self.nodes.get_mut(i).expect("No node");
@@ -146,6 +170,7 @@ impl Split {
             }
         } else {
             self.path_cost.set(0, 0, 0.0);
+            self.route_counts[0] = 0;
             for col in 1..self.path_cost.cols {
                 self.path_cost.set(0, col, 1e30);
             }
@@ -161,6 +186,55 @@ impl Split {
                 * (0f64.max(self.cum_load[j] - self.cum_load[i] - self.vehicle_cap) as f64)
     }
 
+    /// Penalty for a route with the given load
+    #[inline]
+    fn overload_penalty(&self, load: f64) -> f64 {
+        capacity_penalty(
+            load - self.vehicle_cap,
+            self.vehicle_cap,
+            self.penalty_capacity,
+            &self.capacity_penalty_tiers,
+        )
+    }
+
+    /// Penalty for the unused capacity of a route with the given load. For a fixed number of
+    /// routes, the sum is the smallest when the loads are even. Overloaded routes have no unused
+    /// capacity, as the overload is priced by the capacity penalty
+    #[inline]
+    fn balance_penalty(&self, load: f64) -> f64 {
+        self.balance_weight * (self.vehicle_cap - load).max(0.0).powi(2) / self.vehicle_cap
+    }
+
+    /// Penalty for a route visiting the given number of distinct locations
+    #[inline]
+    fn location_penalty(&self, locations: usize) -> f64 {
+        location_penalty(locations, self.max_locations, self.penalty_capacity)
+    }
+
+    // The linear split relies on the penalty being linear in the overload, and keeps the latest
+    // of equal-cost predecessors. It does not count the locations of a route
+    fn use_linear_split(&self, ctx: &Context) -> bool {
+        ctx.config.borrow().linear_split
+            && self.capacity_penalty_tiers.is_empty()
+            && !self.tiebreak
+            && self.balance_weight == 0.0
+            && self.max_locations.is_none()
+    }
+
+    /// Whether a path replaces the current path to a node. The predecessors are tried in
+    /// increasing order, so the earliest of equal-cost predecessors is kept. With the tiebreak,
+    /// costs within epsilon are equal and fewer routes are preferred
+    #[inline]
+    fn improves(&self, cost: f64, routes: usize, current_cost: f64, current_routes: usize) -> bool {
+        match self.tiebreak {
+            true => {
+                cost.approx_lt_eps(current_cost, self.epsilon)
+                    || (cost.approx_eq_eps(current_cost, self.epsilon) && routes < current_routes)
+            }
+            false => cost < current_cost,
+        }
+    }
+
     #[inline]
     fn dominates(&self, i: usize, j: usize, k: usize) -> bool {
         self.path_cost.get(k, j) + self.nodes[j + 1].distance_depot
@@ -184,8 +258,11 @@ impl Split {
         if !self.split(ctx, individual, max_vehicles) {
             self.split_limited_fleet(ctx, individual, max_vehicles);
         }
+        individual.consolidate_clusters(ctx);
         individual.sort_routes(ctx);
         individual.evaluate(ctx);
+        individual.mark_split();
+        self.runs += 1;
     }
 
     // Split of the individual's genotype to create its phenotype
@@ -200,7 +277,7 @@ impl Split {
         let cap = ctx.problem.vehicle.cap;
         let capacity_factor = ctx.config.borrow().split_capacity_factor;
 
-        if ctx.config.borrow().linear_split {
+        if self.use_linear_split(ctx) {
             let mut queue: MyVecDeque<usize> = MyVecDeque::new(ctx.problem.dim());
             queue.push_back(0);
 
@@ -231,6 +308,7 @@ impl Split {
             // Bellman-based split algorithm in O(nB) where B is the average route length
             for from_index in 0..(dim - 1) {
                 let mut load = 0.0;
+                let mut locations = HashSet::new();
                 let mut to_index = from_index + 1;
                 let mut cost = 0.0;
                 while to_index < dim
@@ -257,12 +335,26 @@ impl Split {
                             .get(individual.genotype_node(to_index), 0);
 
                     if (load - cap).approx_gt(0.0) {
-                        new_path_cost += (load - cap) * self.penalty_capacity;
+                        new_path_cost += self.overload_penalty(load);
+                    }
+                    if self.balance_weight > 0.0 {
+                        new_path_cost += self.balance_penalty(load);
+                    }
+                    if self.max_locations.is_some() {
+                        locations.insert(ctx.problem.location(individual.genotype_node(to_index)));
+                        new_path_cost += self.location_penalty(locations.len());
                     }
 
-                    if new_path_cost < self.path_cost.get(0, to_index) {
+                    let routes = self.route_counts[from_index] + 1;
+                    if self.improves(
+                        new_path_cost,
+                        routes,
+                        self.path_cost.get(0, to_index),
+                        self.route_counts[to_index],
+                    ) {
                         self.path_cost.set(0, to_index, new_path_cost);
                         self.predecessors.set(0, to_index, from_index);
+                        self.route_counts[to_index] = routes;
                     }
                     to_index += 1;
                 }
@@ -303,7 +395,7 @@ impl Split {
         let cap = ctx.problem.vehicle.cap;
         let capacity_factor = ctx.config.borrow().split_capacity_factor;
 
-        if ctx.config.borrow().linear_split {
+        if self.use_linear_split(ctx) {
             let mut queue: MyVecDeque<usize> = MyVecDeque::new(ctx.problem.dim());
 
             for k in 0..max_vehicles {
@@ -341,6 +433,7 @@ impl Split {
                         break;
                     }
                     let mut load = 0.0;
+                    let mut locations = HashSet::new();
                     let mut to_index = from_index + 1;
                     let mut cost = 0.0;
                     while to_index < dim
@@ -367,10 +460,24 @@ impl Split {
                                 .get(individual.genotype_node(to_index), 0);
 
                         if (load - cap).approx_gt(0.0) {
-                            new_path_cost += (load - cap) * self.penalty_capacity;
+                            new_path_cost += self.overload_penalty(load);
+                        }
+                        if self.balance_weight > 0.0 {
+                            new_path_cost += self.balance_penalty(load);
+                        }
+                        if self.max_locations.is_some() {
+                            locations
+                                .insert(ctx.problem.location(individual.genotype_node(to_index)));
+                            new_path_cost += self.location_penalty(locations.len());
                         }
 
-                        if new_path_cost < self.path_cost.get(vehicle_index + 1, to_index) {
+                        // Every path in a row has the same number of routes
+                        if self.improves(
+                            new_path_cost,
+                            0,
+                            self.path_cost.get(vehicle_index + 1, to_index),
+                            0,
+                        ) {
                             self.path_cost
                                 .set(vehicle_index + 1, to_index, new_path_cost);
                             self.predecessors
@@ -388,8 +495,9 @@ impl Split {
         let mut num_routes = max_vehicles;
 
         for vehicle_number in 1..max_vehicles {
-            if self.path_cost.get(vehicle_number, last_customer_index) < min_cost {
-                min_cost = self.path_cost.get(vehicle_number, last_customer_index);
+            let cost = self.path_cost.get(vehicle_number, last_customer_index);
+            if self.improves(cost, vehicle_number, min_cost, num_routes) {
+                min_cost = cost;
                 num_routes = vehicle_number;
             }
         }
@@ -418,3 +526,153 @@ impl Split {
         end == 0
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::utils::{coordinate_problem, demand_problem, problem_context, EPSILON};
+
+    // Customers 1 to 4 are at the depot, so every split of them costs the same
+    fn split_routes(tiebreak: bool) -> (Vec<Vec<usize>>, f64) {
+        let mut coords = [(0.0, 0.0); 6];
+        coords[5].0 = 10.0;
+        let mut config = Config::default();
+        config.split_tiebreak = tiebreak;
+        let ctx = problem_context(coordinate_problem(&coords, 10.0), None, config);
+
+        let mut individual = Individual::new(vec![1, 2, 5, 3, 4], 0);
+        let max_vehicles = ctx.config.borrow().num_vehicles;
+        Split::new(&ctx).run(&ctx, &mut individual, max_vehicles);
+        let routes = individual
+            .phenotype
+            .iter()
+            .filter(|route| !route.is_empty())
+            .cloned()
+            .collect();
+        (routes, individual.penalized_cost())
+    }
+
+    #[test]
+    fn tiebreak_prefers_fewer_routes() {
+        let (routes, cost) = split_routes(false);
+        assert!(routes.len() > 1);
+
+        let (tiebreak_routes, tiebreak_cost) = split_routes(true);
+        assert_eq!(vec![vec![1, 2, 5, 3, 4]], tiebreak_routes);
+        assert_eq!(cost, tiebreak_cost);
+        assert_eq!((tiebreak_routes, tiebreak_cost), split_routes(true));
+    }
+
+    #[test]
+    fn tiebreak_within_float_epsilon() {
+        // Customers 1 to 4 are at the depot, and visiting customer 5 between them costs 0.1
+        // more than a separate route
+        let split = |epsilon: f64| {
+            let matrix = vec![
+                vec![0.0],
+                vec![0.0, 0.0],
+                vec![0.0, 0.0, 0.0],
+                vec![0.0, 0.0, 0.0, 0.0],
+                vec![10.0, 10.05, 10.05, 10.05, 10.05],
+            ];
+            let mut config = Config::default();
+            config.round_distances = false;
+            config.split_tiebreak = true;
+            config.float_epsilon = epsilon;
+            let problem = coordinate_problem(&[(0.0, 0.0); 6], 10.0);
+            let ctx = problem_context(problem, Some(matrix), config);
+
+            let mut individual = Individual::new(vec![1, 2, 5, 3, 4], 0);
+            let max_vehicles = ctx.config.borrow().num_vehicles;
+            Split::new(&ctx).run(&ctx, &mut individual, max_vehicles);
+            individual.num_nonempty_routes()
+        };
+        assert!(split(EPSILON) > 1);
+        assert_eq!(1, split(0.5));
+    }
+
+    // Loads and distance of the split of a line of five customers followed by one customer
+    // between the line and the depot
+    fn balanced_split(weight: f64) -> (Vec<f64>, f64) {
+        let coords = [
+            (0.0, 0.0),
+            (10.0, 0.0),
+            (10.0, 2.0),
+            (10.0, 4.0),
+            (10.0, 6.0),
+            (10.0, 8.0),
+            (5.0, 4.0),
+        ];
+        let mut config = Config::default();
+        config.split_balance_weight = weight;
+        let ctx = problem_context(demand_problem(&coords, &[2.0; 6], 10.0), None, config);
+        // Rules out serving every customer on one overloaded route
+        ctx.config.borrow_mut().penalty_capacity = 100.0;
+
+        let mut individual = Individual::new(vec![1, 2, 3, 4, 5, 6], 0);
+        let max_vehicles = ctx.config.borrow().num_vehicles;
+        Split::new(&ctx).run(&ctx, &mut individual, max_vehicles);
+        let routes: Vec<&Vec<usize>> = individual
+            .phenotype
+            .iter()
+            .filter(|route| !route.is_empty())
+            .collect();
+        let loads = routes
+            .iter()
+            .map(|route| route.iter().map(|&c| ctx.problem.nodes[c].demand).sum())
+            .collect();
+        let distance = routes
+            .iter()
+            .map(|route| ctx.matrix_provider.distance.route_distance(route))
+            .sum();
+        (loads, distance)
+    }
+
+    #[test]
+    fn balance_weight_evens_loads() {
+        let spread = |loads: &[f64]| {
+            let max = loads.iter().cloned().fold(f64::MIN, f64::max);
+            let min = loads.iter().cloned().fold(f64::MAX, f64::min);
+            max - min
+        };
+        let (loads, distance) = balanced_split(0.0);
+        let (balanced_loads, balanced_distance) = balanced_split(5.0);
+        assert_eq!(loads.len(), balanced_loads.len());
+        assert!(spread(&balanced_loads) < spread(&loads));
+        assert!(balanced_distance > distance);
+        assert!(balanced_distance < 1.3 * distance);
+    }
+
+    #[test]
+    fn balance_ignores_overload() {
+        let mut config = Config::default();
+        config.split_balance_weight = 1.0;
+        let ctx = problem_context(coordinate_problem(&[(0.0, 0.0); 3], 10.0), None, config);
+        let split = Split::new(&ctx);
+        assert!(split.balance_penalty(8.0) > 0.0);
+        assert_eq!(0.0, split.balance_penalty(10.0));
+        assert_eq!(0.0, split.balance_penalty(12.0));
+    }
+
+    #[test]
+    fn max_locations_prices_routes() {
+        // Customers 1 to 3 are orders at the same location, customer 4 is elsewhere
+        let split = |max_locations: Option<usize>| {
+            let coords = [(0.0, 0.0), (1.0, 0.0), (1.0, 0.0), (1.0, 0.0), (2.0, 0.0)];
+            let mut config = Config::default();
+            config.max_distinct_locations_per_route = max_locations;
+            let ctx = problem_context(coordinate_problem(&coords, 10.0), None, config);
+            // The second location of a route costs more than a route of its own
+            ctx.config.borrow_mut().penalty_capacity = 100.0;
+
+            let mut individual = Individual::new(vec![1, 2, 3, 4], 0);
+            let max_vehicles = ctx.config.borrow().num_vehicles;
+            Split::new(&ctx).run(&ctx, &mut individual, max_vehicles);
+            assert!(individual.is_feasible());
+            individual.num_nonempty_routes()
+        };
+        assert_eq!(1, split(None));
+        assert_eq!(2, split(Some(1)));
+    }
+}
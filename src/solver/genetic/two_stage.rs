@@ -0,0 +1,159 @@
+use crate::models::Coordinate;
+use crate::solver::genetic::{GeneticAlgorithm, Individual, Split};
+use crate::solver::{Context, Metaheuristic};
+use crate::utils::ProblemParser;
+
+// Share of the time limit for the coarse stage, and the least time it gets in seconds
+const COARSE_SHARE: f64 = 0.5;
+const MIN_COARSE_SECONDS: f64 = 0.1;
+
+#[derive(PartialEq)]
+enum State {
+    Coarse,
+    Refine,
+}
+
+/// Solves a coarse problem, where the customers are snapped to a grid and merged per cell, and
+/// uses its best solution as the seed of the search on the original customers
+pub struct TwoStageGeneticAlgorithm {
+    state: State,
+    pub ga: GeneticAlgorithm,
+}
+
+impl TwoStageGeneticAlgorithm {
+    pub fn new(ctx: &Context) -> Self {
+        Self {
+            state: State::Coarse,
+            ga: GeneticAlgorithm::new(ctx),
+        }
+    }
+
+    // Cell size giving about `two_stage_cluster_size` customers per cell when they are spread
+    // uniformly over their bounding box
+    fn cell_size(ctx: &Context) -> f64 {
+        let customers: Vec<usize> = (1..ctx.problem.dim()).collect();
+        let (min, max): (Coordinate, Coordinate) = ctx.problem.bounding_box(&customers);
+        let area = (max.lng - min.lng) * (max.lat - min.lat);
+        let cluster_size = ctx.config.borrow().two_stage_cluster_size as f64;
+        (area * cluster_size / customers.len() as f64)
+            .sqrt()
+            .max(f64::EPSILON)
+    }
+
+    // Solves the coarse problem and expands its best solution to the original customers.
+    // Distances in the coarse problem are euclidian between the snapped coordinates
+    fn solve_coarse(ctx: &Context) -> Individual {
+        let (problem, merged) = ctx.problem.snap_to_grid(Self::cell_size(ctx), true);
+        log::info!(
+            "Coarse problem with {} of {} customers",
+            problem.num_customers(),
+            ctx.problem.num_customers()
+        );
+
+        // The coarse stage gets a share of the time limit, which is in whole seconds
+        let config = ctx.config.borrow().clone();
+        let budget = (config.time_limit as f64 * COARSE_SHARE).max(MIN_COARSE_SECONDS);
+        let mut parser = ProblemParser::new();
+        parser.problem = Some(problem);
+        let start_time = ctx.search_history.borrow().start_time;
+        let mut coarse_ctx = Context::new(parser, config, start_time);
+        coarse_ctx.deadline = ctx.deadline.clone();
+
+        let mut coarse_ga = GeneticAlgorithm::new(&coarse_ctx);
+        while !coarse_ga.terminated() && coarse_ctx.elapsed_as_secs_f64() < budget {
+            coarse_ga.iterate(&coarse_ctx);
+        }
+        // Merged cells can exceed the capacity, then only infeasible coarse solutions exist.
+        // If the stage stopped before any individual was created, a random one is split
+        let coarse_solution = coarse_ga
+            .best_solution
+            .take()
+            .or_else(|| coarse_ga.population.snapshot().into_iter().min())
+            .unwrap_or_else(|| {
+                let mut individual = Individual::new_random(&coarse_ctx, 0);
+                let max_vehicles = coarse_ctx.config.borrow().num_vehicles;
+                individual.routes(&coarse_ctx, &mut Split::new(&coarse_ctx), max_vehicles);
+                individual
+            });
+
+        let mut solution = Individual::empty();
+        let mut routes: Vec<Vec<usize>> = coarse_solution
+            .phenotype
+            .iter()
+            .map(|route| {
+                route
+                    .iter()
+                    .flat_map(|&cell| merged[cell].iter().copied())
+                    .collect()
+            })
+            .collect();
+        let num_vehicles = ctx.config.borrow().num_vehicles as usize;
+        if routes.len() < num_vehicles {
+            routes.resize(num_vehicles, Vec::new());
+        }
+        solution.set_routes(routes);
+        solution.evaluate(ctx);
+        solution
+    }
+}
+
+impl Metaheuristic for TwoStageGeneticAlgorithm {
+    fn iterate(&mut self, ctx: &Context) {
+        match self.state {
+            State::Coarse => {
+                let solution = Self::solve_coarse(ctx);
+                log::info!("Coarse solution cost: {}", solution.penalized_cost());
+                self.ga.add_initial(ctx, solution);
+                self.state = State::Refine;
+            }
+            State::Refine => self.ga.iterate(ctx),
+        }
+    }
+
+    fn terminated(&self) -> bool {
+        self.ga.terminated()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solver::Solver;
+    use crate::utils::instance_context;
+
+    #[test]
+    fn two_stage_covers_all_customers() {
+        let ctx = instance_context(|config| {
+            config.max_iterations = Some(200);
+            config.initial_individuals = 10;
+            config.two_stage = true;
+        });
+        let metaheuristic = TwoStageGeneticAlgorithm::new(&ctx);
+        let mut solver = Solver::new(ctx, metaheuristic);
+        solver.run();
+
+        let best = solver.metaheuristic.ga.best_solution.expect("No solution");
+        assert!(best.is_feasible());
+        let mut customers: Vec<usize> = best.phenotype.iter().flatten().copied().collect();
+        customers.sort();
+        let expected: Vec<usize> = (1..solver.ctx.problem.dim()).collect();
+        assert_eq!(expected, customers);
+    }
+
+    #[test]
+    fn two_stage_within_one_second() {
+        let ctx = instance_context(|config| {
+            config.time_limit = 1;
+            config.two_stage = true;
+        });
+        let metaheuristic = TwoStageGeneticAlgorithm::new(&ctx);
+        let mut solver = Solver::new(ctx, metaheuristic);
+        solver.run();
+
+        let best = solver.metaheuristic.ga.best_solution.expect("No solution");
+        let mut customers: Vec<usize> = best.phenotype.iter().flatten().copied().collect();
+        customers.sort();
+        let expected: Vec<usize> = (1..solver.ctx.problem.dim()).collect();
+        assert_eq!(expected, customers);
+    }
+}
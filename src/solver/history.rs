@@ -1,12 +1,38 @@
 use instant::Instant;
 use std::fmt;
+use std::io::{self, Write};
 use std::time::Duration;
 
 use serde::{Deserialize, Serialize};
 
+use crate::models::Problem;
 use crate::solver::genetic::Individual;
 use crate::solver::Context;
 
+/// Reason a solution does not belong to a problem
+#[derive(Debug, PartialEq)]
+pub enum MismatchError {
+    // Node number beyond the nodes of the problem
+    NodeOutOfRange(usize),
+    // The depot is visited within the route with the given index
+    DepotInRoute(usize),
+    MissingCustomer(usize),
+    DuplicateCustomer(usize),
+}
+
+impl fmt::Display for MismatchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::NodeOutOfRange(node) => write!(f, "Node {} is out of range", node),
+            Self::DepotInRoute(route) => write!(f, "Route {} visits the depot", route),
+            Self::MissingCustomer(customer) => write!(f, "Customer {} is not visited", customer),
+            Self::DuplicateCustomer(customer) => {
+                write!(f, "Customer {} is visited more than once", customer)
+            }
+        }
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct HistoricSolution {
     // Routes in the solution
@@ -16,12 +42,77 @@ pub struct HistoricSolution {
     pub cost: f64,
 }
 
+impl HistoricSolution {
+    /// Drops the empty routes, such that the routes are numbered 0..k without gaps
+    pub fn compact(&mut self) {
+        self.routes.retain(|route| !route.is_empty());
+    }
+
+    /// Checks that the solution was computed for the problem: every node is in range, the
+    /// depot only starts and ends the routes, and every customer is visited exactly once
+    pub fn validate_against(&self, problem: &Problem) -> Result<(), MismatchError> {
+        let mut visited = vec![false; problem.dim()];
+        for (route_index, route) in self.routes.iter().enumerate() {
+            for &node in route.iter() {
+                if node >= problem.dim() {
+                    return Err(MismatchError::NodeOutOfRange(node));
+                }
+                if node == 0 {
+                    return Err(MismatchError::DepotInRoute(route_index));
+                }
+                if visited[node] {
+                    return Err(MismatchError::DuplicateCustomer(node));
+                }
+                visited[node] = true;
+            }
+        }
+        match (1..problem.dim()).find(|&customer| !visited[customer]) {
+            Some(customer) => Err(MismatchError::MissingCustomer(customer)),
+            None => Ok(()),
+        }
+    }
+
+    /// Writes one row per stop, with the depot at the start and end of every non-empty route.
+    /// Routes are numbered from 1 as in the solution file, and nodes by their instance ids
+    pub fn to_csv<W: Write>(&self, writer: &mut W, problem: &Problem) -> io::Result<()> {
+        writeln!(
+            writer,
+            "route_id,sequence,node_id,lng,lat,demand,cumulative_load"
+        )?;
+        let routes = self.routes.iter().filter(|route| !route.is_empty());
+        for (route_index, route) in routes.enumerate() {
+            let mut load = 0.0;
+            let stops = std::iter::once(&0)
+                .chain(route.iter())
+                .chain(std::iter::once(&0));
+            for (sequence, &stop) in stops.enumerate() {
+                let node = &problem.nodes[stop];
+                load += node.demand;
+                writeln!(
+                    writer,
+                    "{},{},{},{},{},{},{}",
+                    route_index + 1,
+                    sequence,
+                    node.id,
+                    node.coord.lng,
+                    node.coord.lat,
+                    node.demand,
+                    load
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
 impl From<&Individual> for HistoricSolution {
     fn from(individual: &Individual) -> Self {
-        Self {
+        let mut solution = Self {
             routes: individual.phenotype.clone(),
             cost: individual.penalized_cost(),
-        }
+        };
+        solution.compact();
+        solution
     }
 }
 
@@ -95,11 +186,17 @@ impl SearchHistory {
         }
     }
 
-    pub fn add(&mut self, _ctx: &Context, individual: &Individual) {
+    pub fn add(&mut self, ctx: &Context, individual: &Individual) {
         self.best_cost = individual.penalized_cost();
         let timestamp = self.start_time.elapsed();
+        // The reported cost is recomputed, free of drift in the stored evaluation
+        let mut solution = HistoricSolution {
+            routes: individual.phenotype.clone(),
+            cost: individual.objective(ctx),
+        };
+        solution.compact();
         let history_entry = HistoryEntry {
-            solution: HistoricSolution::from(individual),
+            solution,
             timestamp,
         };
 
@@ -142,3 +239,80 @@ impl SearchHistory {
         self.log_new_best = log_new_best;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::{coordinate_problem, demand_problem};
+
+    #[test]
+    fn compact_routes() {
+        let mut individual = Individual::empty();
+        // The second route was emptied during the search
+        individual.phenotype = vec![vec![3, 1], vec![], vec![2], vec![], vec![4]];
+        let solution = HistoricSolution::from(&individual);
+        assert_eq!(vec![vec![3, 1], vec![2], vec![4]], solution.routes);
+        assert!(format!("{}", solution).starts_with("Route #1: 3 1\nRoute #2: 2\nRoute #3: 4\n"));
+    }
+
+    #[test]
+    fn csv_manifest() {
+        let coords: Vec<(f64, f64)> = (0..5).map(|id| (id as f64, 0.5)).collect();
+        let problem = demand_problem(&coords, &[1.0, 2.0, 3.0, 4.0], 10.0);
+        let solution = HistoricSolution {
+            routes: vec![vec![3, 1], vec![], vec![2, 4]],
+            cost: 0.0,
+        };
+
+        let mut csv = Vec::new();
+        solution.to_csv(&mut csv, &problem).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let mut lines = csv.lines();
+        assert_eq!(
+            Some("route_id,sequence,node_id,lng,lat,demand,cumulative_load"),
+            lines.next()
+        );
+        let rows: Vec<Vec<&str>> = lines.map(|line| line.split(',').collect()).collect();
+        // Four stops and the depot at both ends of the two non-empty routes
+        assert_eq!(4 + 2 * 2, rows.len());
+        assert_eq!(vec!["1", "1", "4", "3", "0.5", "3", "3"], rows[1]);
+        assert_eq!(vec!["2", "3", "1", "0", "0.5", "0", "6"], rows[7]);
+
+        for route in ["1", "2"].iter() {
+            let loads: Vec<f64> = rows
+                .iter()
+                .filter(|row| row[0] == *route)
+                .map(|row| row[6].parse().unwrap())
+                .collect();
+            assert_eq!(0.0, loads[0]);
+            assert!(loads.windows(2).all(|pair| pair[0] <= pair[1]));
+        }
+    }
+
+    #[test]
+    fn validate_against_problem() {
+        let coords: Vec<(f64, f64)> = (0..5).map(|id| (id as f64, 0.0)).collect();
+        let problem = coordinate_problem(&coords, 10.0);
+        let validate = |routes: Vec<Vec<usize>>| {
+            HistoricSolution { routes, cost: 0.0 }.validate_against(&problem)
+        };
+
+        assert_eq!(Ok(()), validate(vec![vec![3, 1], vec![], vec![2, 4]]));
+        assert_eq!(
+            Err(MismatchError::NodeOutOfRange(5)),
+            validate(vec![vec![3, 1], vec![2, 5, 4]])
+        );
+        assert_eq!(
+            Err(MismatchError::MissingCustomer(2)),
+            validate(vec![vec![3, 1], vec![4]])
+        );
+        assert_eq!(
+            Err(MismatchError::DepotInRoute(1)),
+            validate(vec![vec![3, 1], vec![2, 0, 4]])
+        );
+        assert_eq!(
+            Err(MismatchError::DuplicateCustomer(3)),
+            validate(vec![vec![3, 1], vec![2, 3, 4]])
+        );
+    }
+}
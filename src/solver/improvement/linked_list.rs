@@ -13,6 +13,8 @@ pub struct LinkNode {
     pub position: usize,
     pub last_tested: i32,
     pub cum_distance: f64,
+    // Distance from the node back to the start depot. Only updated for asymmetric distances
+    pub cum_reverse_distance: f64,
     pub cum_load: f64,
     // Change in distance when removing the node
     pub delta_removal: f64,
@@ -29,6 +31,7 @@ impl LinkNode {
             position: 0,
             last_tested: 0,
             cum_distance: f64::default(),
+            cum_reverse_distance: f64::default(),
             cum_load: f64::default(),
             delta_removal: f64::default(),
         }
@@ -1,17 +1,27 @@
 use std::cmp::max;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::ptr;
 
 use ahash::RandomState;
 
 use crate::models::{CorrelationMatrix, Matrix};
-use crate::solver::evaluate::route_cost;
+use crate::solver::evaluate::{
+    capacity_penalty, location_penalty, route_cost, short_route_penalty,
+};
 use crate::solver::genetic::Individual;
-use crate::solver::improvement::moves::{Moves, SwapStar};
+use crate::solver::improvement::moves::{Move, Moves, NeighborList, SwapStar};
 use crate::solver::improvement::{InsertLocation, LinkNode, LinkRoute, ThreeBestInserts};
 use crate::solver::Context;
 use crate::utils::FloatCompare;
 
+/// The routes changed by a move as they were before it, with their overlap area and emissions
+pub struct RouteSnapshot {
+    routes: [*mut LinkRoute; 2],
+    customers: [Vec<usize>; 2],
+    overlap: f64,
+    emissions: f64,
+}
+
 pub struct LocalSearch {
     pub ctx: &'static Context,
 
@@ -24,6 +34,13 @@ pub struct LocalSearch {
     pub move_count: i32,
     pub moves: Moves,
 
+    // Number of times each move was performed in the last search, by move name
+    pub move_counts: HashMap<&'static str, u64, RandomState>,
+
+    // Number of passes over all customers in the last search
+    pub pass_count: u64,
+    pub max_passes: u64,
+
     // Store the three best insert locations for each node and route. Used by swap start move.
     pub best_inserts: Matrix<ThreeBestInserts>,
 
@@ -37,6 +54,26 @@ pub struct LocalSearch {
 
     // Penalty for capacity during the search
     pub penalty_capacity: f64,
+    pub capacity_penalty_tiers: Vec<(f64, f64)>,
+
+    // Reversal moves are costed with the distances in the reverse direction
+    pub asymmetric: bool,
+
+    // Moves are only performed if they improve the cost by more than epsilon
+    pub epsilon: f64,
+
+    // Penalty for non-empty routes with fewer than `min_customers_per_route` customers
+    pub min_customers_per_route: Option<usize>,
+    pub short_route_penalty: f64,
+
+    // Cluster of every customer that must share its route with the rest of the cluster
+    pub cluster_index: Vec<Option<usize>>,
+    // Moves between routes may not take a route beyond this many distinct locations
+    pub max_locations: Option<usize>,
+    // Penalty per unit of area in which the bounding boxes of two routes intersect
+    pub overlap_penalty: f64,
+    // Weight of the emissions from the leg cost of the context
+    pub emissions_weight: f64,
 }
 
 impl LocalSearch {
@@ -84,6 +121,9 @@ impl LocalSearch {
                 correlation: ctx.matrix_provider.correlation.clone(),
                 routes: routes,
                 move_count: 0,
+                move_counts: HashMap::with_hasher(RandomState::with_seeds(0, 0, 0, 0)),
+                pass_count: 0,
+                max_passes: ctx.config.borrow().max_local_search_passes,
                 empty_routes: HashSet::with_capacity_and_hasher(
                     num_vehicles,
                     ctx.random.random_state(),
@@ -91,12 +131,62 @@ impl LocalSearch {
                 start_depots: start_depots,
                 end_depots: end_depots,
                 penalty_capacity: ctx.config.borrow().penalty_capacity * penalty_multiplier,
+                capacity_penalty_tiers: ctx.config.borrow().capacity_penalty_tiers.clone(),
+                asymmetric: ctx.config.borrow().asymmetric,
+                epsilon: ctx.config.borrow().float_epsilon,
+                min_customers_per_route: ctx.config.borrow().min_customers_per_route,
+                short_route_penalty: ctx.config.borrow().short_route_penalty,
+                cluster_index: ctx.problem.cluster_index(),
+                max_locations: ctx.config.borrow().max_distinct_locations_per_route,
+                overlap_penalty: ctx.config.borrow().overlap_penalty,
+                emissions_weight: ctx.config.borrow().emissions_weight,
             }
         }
     }
 
     pub fn update_penalty(&mut self, penalty_multiplier: f64) {
-        self.penalty_capacity = self.ctx.config.borrow().penalty_capacity * penalty_multiplier;
+        let config = self.ctx.config.borrow();
+        self.penalty_capacity = config.penalty_capacity * penalty_multiplier;
+        self.capacity_penalty_tiers = config.capacity_penalty_tiers.clone();
+        self.min_customers_per_route = config.min_customers_per_route;
+        self.short_route_penalty = config.short_route_penalty;
+        self.max_locations = config.max_distinct_locations_per_route;
+        self.overlap_penalty = config.overlap_penalty;
+        self.emissions_weight = config.emissions_weight;
+        self.max_passes = config.max_local_search_passes;
+    }
+
+    /// Penalized cost of a route with the given distance, overload and number of customers
+    #[inline]
+    pub fn route_cost(&self, distance: f64, overload: f64, num_customers: usize) -> f64 {
+        route_cost(
+            distance,
+            overload,
+            self.ctx.problem.vehicle.cap,
+            self.penalty_capacity,
+            &self.capacity_penalty_tiers,
+        ) + self.short_route_penalty(num_customers)
+    }
+
+    /// Penalty for a route with the given overload
+    #[inline]
+    pub fn capacity_penalty(&self, overload: f64) -> f64 {
+        capacity_penalty(
+            overload,
+            self.ctx.problem.vehicle.cap,
+            self.penalty_capacity,
+            &self.capacity_penalty_tiers,
+        )
+    }
+
+    /// Penalty for a route with `num_customers` customers being too short
+    #[inline]
+    pub fn short_route_penalty(&self, num_customers: usize) -> f64 {
+        short_route_penalty(
+            num_customers,
+            self.min_customers_per_route,
+            self.short_route_penalty,
+        )
     }
 
     pub fn load_individual(&mut self, individual: &Individual) {
@@ -130,6 +220,8 @@ impl LocalSearch {
 
     pub fn reset(&mut self) {
         self.move_count = 0;
+        self.move_counts.clear();
+        self.pass_count = 0;
         for node in self.nodes.iter_mut() {
             node.last_tested = -1;
         }
@@ -150,20 +242,21 @@ impl LocalSearch {
         let mut loop_count = 0;
         let mut improvement = true;
         let moves = &*{ &self.moves as *const Moves };
-        while improvement {
+        let asymmetric = self.asymmetric;
+        let neighbor_lists: &[NeighborList] = match asymmetric {
+            true => &[NeighborList::Predecessors, NeighborList::Successors],
+            false => &[NeighborList::Successors],
+        };
+        // Stop at a local optimum, when a full pass does not improve the solution
+        while improvement && self.pass_count < self.max_passes {
             improvement = false;
             // Loop over all customers in random order
             self.ctx.random.shuffle(self.customers.as_mut_slice());
             let customers = &*{ &self.customers as *const Vec<usize> };
             for u_index in customers {
-                // Get all correlated customers in random order
-                let cor = &mut *{
-                    self.correlation.top_slice_mut(*u_index, self.granularity) as *mut [usize]
-                };
-                if self.ctx.random.range_usize(0, self.granularity) == 0 {
-                    self.ctx
-                        .random
-                        .shuffle(self.correlation.top_slice_mut(*u_index, self.granularity));
+                // Stop between moves such that the routes stay consistent
+                if self.ctx.deadline_reached() {
+                    return;
                 }
 
                 let u = &mut self.nodes[*u_index] as *mut LinkNode;
@@ -173,41 +266,60 @@ impl LocalSearch {
                 let last_test_u = (*u).last_tested;
                 (*u).last_tested = self.move_count;
 
-                // Iterate over correlated nodes
-                'v_loop: for &v_index in cor.iter() {
-                    let v = &mut self.nodes[v_index] as *mut LinkNode;
-                    let route_v = (*v).route;
-
-                    // Only try moves if one of the routes is modified since last time
-                    if loop_count == 0
-                        || max((*route_u).last_modified, (*route_v).last_modified) > last_test_u
-                    {
-                        // First, all the moves for the pair of customers are attempted
-                        for m in moves.neighbor.iter() {
-                            let delta = m.delta(&self, u, v);
-                            if delta.approx_lt(0.0) {
-                                self.move_count += 1;
-                                m.perform(self, u, v);
-                                route_u = (*u).route;
-                                improvement = true;
-                                continue 'v_loop;
-                            }
-                        }
-
-                        // If none of the moves above are successful, we attempt moves where
-                        // `u` is located directly after a depot
-                        let v_pred = (*v).predecessor;
-                        if (*v_pred).is_depot() {
-                            for m in moves.depot.iter() {
+                // With asymmetric distances, the moves are split by the correlated customers
+                // they are evaluated with
+                for &list in neighbor_lists {
+                    // Get all correlated customers in random order
+                    let cor = &mut *{ self.neighbors_mut(*u_index, list) as *mut [usize] };
+                    if self.ctx.random.range_usize(0, self.granularity) == 0 {
+                        self.ctx.random.shuffle(self.neighbors_mut(*u_index, list));
+                    }
+                    // Iterate over correlated nodes
+                    'v_loop: for &v_index in cor.iter() {
+                        let v = &mut self.nodes[v_index] as *mut LinkNode;
+                        let route_v = (*v).route;
+
+                        // Only try moves if one of the routes is modified since last time
+                        if loop_count == 0
+                            || max((*route_u).last_modified, (*route_v).last_modified) > last_test_u
+                        {
+                            // First, all the moves for the pair of customers are attempted
+                            for m in moves
+                                .neighbor
+                                .iter()
+                                .filter(|m| !asymmetric || m.neighbor_list() == list)
+                            {
                                 let delta = m.delta(&self, u, v);
-                                if delta.approx_lt(0.0) {
-                                    self.move_count += 1;
-                                    m.perform(self, u, v);
+                                if delta.approx_lt_eps(0.0, self.epsilon)
+                                    && self.perform_checked(m.as_ref(), u, v, delta)
+                                {
+                                    *self.move_counts.entry(m.move_name()).or_insert(0) += 1;
                                     route_u = (*u).route;
                                     improvement = true;
                                     continue 'v_loop;
                                 }
                             }
+
+                            // If none of the moves above are successful, we attempt moves where
+                            // `u` is located directly after a depot
+                            let v_pred = (*v).predecessor;
+                            if (*v_pred).is_depot() {
+                                for m in moves
+                                    .depot
+                                    .iter()
+                                    .filter(|m| !asymmetric || m.neighbor_list() == list)
+                                {
+                                    let delta = m.delta(&self, u, v);
+                                    if delta.approx_lt_eps(0.0, self.epsilon)
+                                        && self.perform_checked(m.as_ref(), u, v, delta)
+                                    {
+                                        *self.move_counts.entry(m.move_name()).or_insert(0) += 1;
+                                        route_u = (*u).route;
+                                        improvement = true;
+                                        continue 'v_loop;
+                                    }
+                                }
+                            }
                         }
                     }
                 }
@@ -223,9 +335,10 @@ impl LocalSearch {
                     let v = (*route_v).start_depot;
                     for m in moves.empty_route.iter() {
                         let delta = m.delta(&self, u, v);
-                        if delta.approx_lt(0.0) {
-                            self.move_count += 1;
-                            m.perform(self, u, v);
+                        if delta.approx_lt_eps(0.0, self.epsilon)
+                            && self.perform_checked(m.as_ref(), u, v, delta)
+                        {
+                            *self.move_counts.entry(m.move_name()).or_insert(0) += 1;
                             improvement = true;
                             break;
                         }
@@ -234,7 +347,7 @@ impl LocalSearch {
             }
 
             // Finally the SWAP* move is performed for all pairs of routes with overlapping circle sectors
-            if self.ctx.config.borrow().swap_star {
+            if moves.swap_star {
                 for r1_num in 0..self.routes.len() {
                     let r1_ptr = &mut self.routes[r1_num] as *mut LinkRoute;
                     let last_tested_u = (*r1_ptr).last_tested_swap_star;
@@ -253,6 +366,8 @@ impl LocalSearch {
                         {
                             if (*r1_ptr).sector.overlaps(&(*r2_ptr).sector) {
                                 if SwapStar::run(self, r1_ptr, r2_ptr) {
+                                    *self.move_counts.entry(SwapStar::move_name()).or_insert(0) +=
+                                        1;
                                     improvement = true;
                                 }
                             }
@@ -261,6 +376,208 @@ impl LocalSearch {
                 }
                 loop_count += 1;
             }
+            self.pass_count += 1;
+        }
+    }
+
+    // Performs the move with the given delta, unless it breaks a route constraint or the change
+    // in route overlap and emissions outweighs the delta. Then the routes of `u` and `v` are
+    // restored and false is returned
+    unsafe fn perform_checked(
+        &mut self,
+        m: &dyn Move,
+        u: *mut LinkNode,
+        v: *mut LinkNode,
+        delta: f64,
+    ) -> bool {
+        let checked = match (*u).route == (*v).route {
+            true => self.prices_emissions(),
+            false => self.checks_moves_between_routes(),
+        };
+        if !checked {
+            self.move_count += 1;
+            m.perform(self, u, v);
+            return true;
+        }
+        let snapshot = self.snapshot_routes([(*u).route, (*v).route]);
+        self.move_count += 1;
+        m.perform(self, u, v);
+        self.keep_move(snapshot, delta)
+    }
+
+    /// Whether moves between routes must keep clusters together, cap the locations of a route or
+    /// pay for the route overlap or emissions, which the move deltas do not include. Moves within
+    /// a route only change the emissions
+    #[inline]
+    pub fn checks_moves_between_routes(&self) -> bool {
+        !self.ctx.problem.clusters.is_empty()
+            || self.max_locations.is_some()
+            || self.overlap_penalty > 0.0
+            || self.prices_emissions()
+    }
+
+    #[inline]
+    fn prices_emissions(&self) -> bool {
+        self.emissions_weight > 0.0 && self.ctx.leg_cost.is_some()
+    }
+
+    /// Takes a snapshot of the two routes, which may be the same, before a move is performed on
+    /// them
+    pub(crate) unsafe fn snapshot_routes(&self, routes: [*mut LinkRoute; 2]) -> RouteSnapshot {
+        let customers = routes.map(|route| self.route_customers(route));
+        RouteSnapshot {
+            routes,
+            overlap: self.overlap_area(),
+            emissions: self.emissions(routes[0] == routes[1], &customers),
+            customers,
+        }
+    }
+
+    /// Restores the routes of the snapshot if the move just performed on them split a cluster,
+    /// took a route beyond the distinct locations or does not improve the cost once the changes
+    /// in location penalty, overlap area and emissions are added to its delta. Returns whether the move was kept.
+    /// The move count timestamps the route changes, so it is not taken back for a restored move
+    pub(crate) unsafe fn keep_move(&mut self, snapshot: RouteSnapshot, delta: f64) -> bool {
+        let RouteSnapshot {
+            routes,
+            customers,
+            overlap,
+            emissions,
+        } = snapshot;
+        let broken = routes.iter().zip(customers.iter()).any(|(&route, before)| {
+            self.splits_cluster(route) || self.adds_locations(route, before)
+        });
+        let same_route = routes[0] == routes[1];
+        let after = routes.map(|route| self.route_customers(route));
+        let num_routes = if same_route { 1 } else { 2 };
+        let locations: f64 = (0..num_routes)
+            .map(|i| self.location_penalty(&after[i]) - self.location_penalty(&customers[i]))
+            .sum();
+        let delta = delta
+            + locations
+            + self.overlap_penalty * (self.overlap_area() - overlap)
+            + self.emissions_weight * (self.emissions(same_route, &after) - emissions);
+        if broken || delta.approx_gte_eps(0.0, self.epsilon) {
+            for (&route, customers) in routes.iter().zip(customers.iter()).take(num_routes) {
+                self.relink_route(route, customers);
+            }
+            return false;
+        }
+        true
+    }
+
+    /// Area in which the bounding boxes of the routes intersect, or zero without an overlap
+    /// penalty
+    pub(crate) unsafe fn overlap_area(&self) -> f64 {
+        if self.overlap_penalty <= 0.0 {
+            return 0.0;
+        }
+        let routes: Vec<Vec<usize>> = self
+            .routes
+            .iter()
+            .map(|route| self.route_customers(route as *const LinkRoute as *mut LinkRoute))
+            .collect();
+        self.ctx
+            .problem
+            .route_overlap_area(routes.iter().map(|route| route.as_slice()))
+    }
+
+    // Penalty for the distinct locations of the customers beyond the cap
+    fn location_penalty(&self, customers: &[usize]) -> f64 {
+        match self.max_locations {
+            Some(_) => location_penalty(
+                self.ctx.problem.num_locations(customers),
+                self.max_locations,
+                self.penalty_capacity,
+            ),
+            None => 0.0,
+        }
+    }
+
+    // Emissions of the routes with the given customers, counting the same route once, or zero
+    // if they are not priced
+    fn emissions(&self, same_route: bool, customers: &[Vec<usize>; 2]) -> f64 {
+        match &self.ctx.leg_cost {
+            Some(leg_cost) if self.prices_emissions() => {
+                let num_routes = if same_route { 1 } else { 2 };
+                customers
+                    .iter()
+                    .take(num_routes)
+                    .filter(|route| !route.is_empty())
+                    .map(|route| leg_cost.route_cost(self.ctx, route))
+                    .sum()
+            }
+            _ => 0.0,
+        }
+    }
+
+    /// Customers of the route in order
+    pub(crate) unsafe fn route_customers(&self, route: *mut LinkRoute) -> Vec<usize> {
+        let mut customers = Vec::with_capacity((*route).num_customers);
+        let mut node = (*(*route).start_depot).successor;
+        while !(*node).is_depot() {
+            customers.push((*node).number);
+            node = (*node).successor;
+        }
+        customers
+    }
+
+    // Links the customers into the route between its depots
+    unsafe fn relink_route(&mut self, route: *mut LinkRoute, customers: &[usize]) {
+        let mut prev_node = (*route).start_depot;
+        for &customer in customers.iter() {
+            let node = &mut self.nodes[customer] as *mut LinkNode;
+            LinkNode::link_nodes(prev_node, node);
+            prev_node = node;
+        }
+        LinkNode::link_nodes(prev_node, (*route).end_depot);
+        self.update_route(route);
+    }
+
+    // Whether a customer of the route has a customer of its cluster on another route
+    unsafe fn splits_cluster(&self, route: *mut LinkRoute) -> bool {
+        let mut node = (*(*route).start_depot).successor;
+        while !(*node).is_depot() {
+            if let Some(cluster) = self.cluster_index[(*node).number] {
+                let split = self.ctx.problem.clusters[cluster]
+                    .iter()
+                    .any(|&customer| self.nodes[customer].route != route);
+                if split {
+                    return true;
+                }
+            }
+            node = (*node).successor;
+        }
+        false
+    }
+
+    // Whether the route visits more distinct locations than allowed, and more than it did with
+    // the customers before the move. Routes already beyond the cap may still lose locations
+    unsafe fn adds_locations(&self, route: *mut LinkRoute, before: &[usize]) -> bool {
+        match self.max_locations {
+            Some(max_locations) => {
+                let locations = self.ctx.problem.num_locations(&self.route_customers(route));
+                locations > max_locations && locations > self.ctx.problem.num_locations(before)
+            }
+            None => false,
+        }
+    }
+
+    /// Whether the customer must share its route with other customers of a cluster
+    #[inline]
+    pub fn in_cluster(&self, customer: usize) -> bool {
+        self.cluster_index[customer].is_some()
+    }
+
+    /// Correlated customers `v` of `u` in the list. Without asymmetric distances both lists are
+    /// the nearest customers
+    fn neighbors_mut(&self, u_index: usize, list: NeighborList) -> &mut [usize] {
+        let granularity = self.granularity.min(self.correlation.node_width(u_index));
+        match (self.asymmetric, list) {
+            (true, NeighborList::Predecessors) => {
+                self.correlation.top_predecessors_mut(u_index, granularity)
+            }
+            _ => self.correlation.top_slice_mut(u_index, granularity),
         }
     }
 
@@ -297,6 +614,7 @@ impl LocalSearch {
         unsafe {
             // Variables to be calculated for the route
             let mut distance = 0.0;
+            let mut reverse_distance = 0.0;
             let mut load = 0.0;
             let mut num_customers = 0;
 
@@ -318,6 +636,10 @@ impl LocalSearch {
             while !node_ptr.is_null() {
                 // Add distance and load for the node
                 distance += distance_matrix.get((*prev_node_ptr).number, (*node_ptr).number);
+                if self.asymmetric {
+                    reverse_distance +=
+                        distance_matrix.get((*node_ptr).number, (*prev_node_ptr).number);
+                }
                 load += problem.nodes[(*node_ptr).number].demand;
 
                 // Update circle sector for customers
@@ -328,6 +650,7 @@ impl LocalSearch {
 
                 // Update information on the node
                 (*node_ptr).cum_distance = distance;
+                (*node_ptr).cum_reverse_distance = reverse_distance;
                 (*node_ptr).cum_load = load;
                 (*node_ptr).route = route_ptr;
                 (*node_ptr).position = position;
@@ -350,11 +673,8 @@ impl LocalSearch {
             self.end_depots[(*route_ptr).index].successor = ptr::null_mut();
 
             // Update route cost
-            (*route_ptr).cost = route_cost(
-                (*route_ptr).distance,
-                (*route_ptr).overload,
-                self.penalty_capacity,
-            );
+            (*route_ptr).cost =
+                self.route_cost((*route_ptr).distance, (*route_ptr).overload, num_customers);
 
             // Update set of empty routes
             if (*route_ptr).is_empty() {
@@ -502,3 +822,180 @@ impl Drop for LocalSearch {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::MoveKind;
+    use crate::solver::genetic::Split;
+    use crate::solver::LegCost;
+    use crate::utils::instance_context;
+
+    // Number of moves performed on the same random individual of a fractional instance
+    fn moves_with_epsilon(epsilon: f64) -> i32 {
+        let ctx = instance_context(|config| {
+            config.round_distances = false;
+            config.float_epsilon = epsilon;
+        });
+
+        let mut individual = Individual::new_random(&ctx, 0);
+        let max_vehicles = ctx.config.borrow().num_vehicles;
+        Split::new(&ctx).run(&ctx, &mut individual, max_vehicles);
+        let mut ls = LocalSearch::new(&ctx, 1.0);
+        ls.run(&ctx, &mut individual, 1.0);
+        ls.move_count
+    }
+
+    #[test]
+    fn float_epsilon_move_acceptance() {
+        assert!(moves_with_epsilon(1e-9) > moves_with_epsilon(5.0));
+    }
+
+    #[test]
+    fn maintained_loads_match_routes() {
+        let ctx = instance_context(|_| {});
+
+        let mut individual = Individual::new_random(&ctx, 0);
+        let max_vehicles = ctx.config.borrow().num_vehicles;
+        Split::new(&ctx).run(&ctx, &mut individual, max_vehicles);
+        let mut ls = LocalSearch::new(&ctx, 1.0);
+        ls.run(&ctx, &mut individual, 1.0);
+        assert!(ls.move_count > 0);
+
+        for route in ls.routes.iter() {
+            let mut load = 0.0;
+            unsafe {
+                let mut node_ptr = (*route.start_depot).successor;
+                while !(*node_ptr).is_depot() {
+                    load += ctx.problem.nodes[(*node_ptr).number].demand;
+                    assert!((*node_ptr).cum_load.approx_eq(load));
+                    node_ptr = (*node_ptr).successor;
+                }
+            }
+            assert!(route.load.approx_eq(load));
+            assert!(route.overload.approx_eq(load - ctx.problem.vehicle.cap));
+        }
+    }
+
+    #[test]
+    fn local_search_keeps_clusters() {
+        let mut ctx = instance_context(|_| {});
+        // Customers with small demands far away from each other
+        let clusters = vec![vec![7, 29], vec![34, 64, 87]];
+        ctx.problem.clusters = clusters.clone();
+
+        let mut individual = Individual::new_random(&ctx, 0);
+        let max_vehicles = ctx.config.borrow().num_vehicles;
+        Split::new(&ctx).run(&ctx, &mut individual, max_vehicles);
+        let mut ls = LocalSearch::new(&ctx, 1.0);
+        ls.run(&ctx, &mut individual, 1.0);
+        assert!(ls.move_count > 0);
+
+        for cluster in clusters.iter() {
+            let route = individual
+                .phenotype
+                .iter()
+                .find(|route| route.contains(&cluster[0]))
+                .unwrap();
+            assert!(cluster.iter().all(|node| route.contains(node)));
+        }
+    }
+
+    // Routes of an individual after the local search from routes of four customers
+    fn routes_after_local_search(ctx: &Context) -> Vec<Vec<usize>> {
+        let customers: Vec<usize> = (1..ctx.problem.dim()).collect();
+        let routes = customers.chunks(4).map(|route| route.to_vec()).collect();
+        let mut individual = Individual::from_routes(ctx, &mut Split::new(ctx), routes, 0);
+        let mut ls = LocalSearch::new(ctx, 1.0);
+        ls.run(ctx, &mut individual, 1.0);
+        assert!(ls.move_count > 0);
+        individual.phenotype
+    }
+
+    #[test]
+    fn local_search_keeps_max_locations() {
+        let longest = |ctx: Context| {
+            let routes = routes_after_local_search(&ctx);
+            routes.iter().map(|route| route.len()).max().unwrap()
+        };
+        assert!(longest(instance_context(|_| {})) > 5);
+        let ctx = instance_context(|config| config.max_distinct_locations_per_route = Some(5));
+        assert!(longest(ctx) <= 5);
+    }
+
+    #[test]
+    fn local_search_prices_overlap() {
+        let overlap_area = |ctx: Context| {
+            let routes = routes_after_local_search(&ctx);
+            ctx.problem
+                .route_overlap_area(routes.iter().map(|route| route.as_slice()))
+        };
+        let overlap = overlap_area(instance_context(|_| {}));
+        let penalized_overlap =
+            overlap_area(instance_context(|config| config.overlap_penalty = 1.0));
+        assert!(penalized_overlap < 0.5 * overlap);
+    }
+
+    #[test]
+    fn local_search_prices_emissions() {
+        let emissions = |weight: f64| {
+            let mut ctx = instance_context(|config| config.emissions_weight = weight);
+            ctx.leg_cost = Some(LegCost::new(|_, _, load, distance| load * distance / 100.0));
+            let routes = routes_after_local_search(&ctx);
+            let leg_cost = ctx.leg_cost.as_ref().unwrap();
+            routes
+                .iter()
+                .map(|route| leg_cost.route_cost(&ctx, route))
+                .sum::<f64>()
+        };
+        assert!(emissions(10.0) < 0.9 * emissions(0.0));
+    }
+
+    #[test]
+    fn local_optimum_needs_one_pass() {
+        let ctx = instance_context(|_| {});
+
+        let mut individual = Individual::new_random(&ctx, 0);
+        let max_vehicles = ctx.config.borrow().num_vehicles;
+        Split::new(&ctx).run(&ctx, &mut individual, max_vehicles);
+        let mut ls = LocalSearch::new(&ctx, 1.0);
+        ls.run(&ctx, &mut individual, 1.0);
+        assert!(ls.pass_count > 1);
+
+        // The search stops after a pass without any moves
+        ls.run(&ctx, &mut individual, 1.0);
+        assert_eq!(0, ls.move_count);
+        assert_eq!(1, ls.pass_count);
+
+        // The number of passes is capped
+        let mut individual = Individual::new_random(&ctx, 0);
+        Split::new(&ctx).run(&ctx, &mut individual, max_vehicles);
+        ctx.config.borrow_mut().max_local_search_passes = 2;
+        ls.run(&ctx, &mut individual, 1.0);
+        assert_eq!(2, ls.pass_count);
+    }
+
+    #[test]
+    fn local_search_order() {
+        let ctx = instance_context(|_| {});
+
+        let mut individual = Individual::new_random(&ctx, 0);
+        let max_vehicles = ctx.config.borrow().num_vehicles;
+        Split::new(&ctx).run(&ctx, &mut individual, max_vehicles);
+        let mut all_moves = individual.clone();
+        let mut ls = LocalSearch::new(&ctx, 1.0);
+        ls.run(&ctx, &mut all_moves, 1.0);
+        assert!(ls.move_counts.keys().any(|name| name.starts_with("Swap")));
+
+        ctx.config.borrow_mut().local_search_order = vec![MoveKind::Relocate];
+        let mut ls = LocalSearch::new(&ctx, 1.0);
+        assert!(!ls.moves.swap_star);
+        ls.run(&ctx, &mut individual, 1.0);
+        assert!(ls.move_count > 0);
+        assert_eq!(
+            vec![&"RelocateSingle"],
+            ls.move_counts.keys().collect::<Vec<_>>()
+        );
+        assert_eq!(ls.move_count as u64, ls.move_counts["RelocateSingle"]);
+    }
+}
@@ -0,0 +1,135 @@
+use crate::models::DistanceMatrix;
+
+/// Longest route solved exactly, at about 8 MB for the dynamic program
+pub const MAX_EXACT_ROUTE: usize = 16;
+
+/// Optimal order of the customers of a route starting and ending at the depot, with its
+/// distance. Solves the TSP over the route exactly by the dynamic program of Held and Karp,
+/// taking time and memory exponential in the route length. The distances are read in the
+/// direction of travel, so the order is optimal for asymmetric distances too.
+///
+/// Panics if the route has more than `MAX_EXACT_ROUTE` customers
+pub fn held_karp_route(route: &[usize], distance_matrix: &DistanceMatrix) -> (Vec<usize>, f64) {
+    let n = route.len();
+    assert!(
+        n <= MAX_EXACT_ROUTE,
+        "Route of {} customers is too long to solve exactly",
+        n
+    );
+    if n <= 1 {
+        return (route.to_vec(), distance_matrix.route_distance(route));
+    }
+
+    // Shortest path from the depot through the customers in the subset, ending at the last
+    // customer, and the customer visited before the last one
+    let subsets = 1 << n;
+    let mut cost = vec![f64::INFINITY; subsets * n];
+    let mut previous = vec![usize::MAX; subsets * n];
+    for last in 0..n {
+        cost[(1 << last) * n + last] = distance_matrix.get(0, route[last]);
+    }
+    for subset in 1..subsets {
+        for last in 0..n {
+            let path_cost = cost[subset * n + last];
+            if subset & (1 << last) == 0 || path_cost == f64::INFINITY {
+                continue;
+            }
+            for next in 0..n {
+                if subset & (1 << next) != 0 {
+                    continue;
+                }
+                let extended = subset | (1 << next);
+                let next_cost = path_cost + distance_matrix.get(route[last], route[next]);
+                if next_cost < cost[extended * n + next] {
+                    cost[extended * n + next] = next_cost;
+                    previous[extended * n + next] = last;
+                }
+            }
+        }
+    }
+
+    // Close the tour at the depot and follow the predecessors back
+    let full = subsets - 1;
+    let (mut last, distance) = (0..n)
+        .map(|last| {
+            (
+                last,
+                cost[full * n + last] + distance_matrix.get(route[last], 0),
+            )
+        })
+        .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
+        .unwrap();
+    let mut order = Vec::with_capacity(n);
+    let mut subset = full;
+    loop {
+        order.push(route[last]);
+        let before = previous[subset * n + last];
+        subset &= !(1 << last);
+        if subset == 0 {
+            break;
+        }
+        last = before;
+    }
+    order.reverse();
+    (order, distance)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Coordinate, Matrix};
+    use crate::utils::{instance_context, FloatCompare};
+
+    // Shortest distance over all orders of the route
+    fn brute_force(route: &mut Vec<usize>, k: usize, distance_matrix: &DistanceMatrix) -> f64 {
+        if k == route.len() {
+            return distance_matrix.route_distance(route);
+        }
+        let mut best = f64::INFINITY;
+        for i in k..route.len() {
+            route.swap(k, i);
+            best = best.min(brute_force(route, k + 1, distance_matrix));
+            route.swap(k, i);
+        }
+        best
+    }
+
+    #[test]
+    fn held_karp_matches_brute_force() {
+        let ctx = instance_context(|_| {});
+        let distance_matrix = &ctx.matrix_provider.distance;
+
+        let route: Vec<usize> = (1..=8).collect();
+        let (order, optimal) = held_karp_route(&route, distance_matrix);
+        assert!(optimal < distance_matrix.route_distance(&route));
+        assert!(optimal.approx_eq(distance_matrix.route_distance(&order)));
+        assert!(optimal.approx_eq(brute_force(&mut route.clone(), 0, distance_matrix)));
+        let mut customers = order.clone();
+        customers.sort_unstable();
+        assert_eq!(route, customers);
+    }
+
+    #[test]
+    fn held_karp_asymmetric() {
+        // Customers on a line, where travelling towards the depot is expensive
+        let size = 5;
+        let mut storage = Matrix::new(size, size);
+        for i in 0..size {
+            for j in 0..size {
+                let distance = match i < j || j == 0 {
+                    true => (i as f64 - j as f64).abs(),
+                    false => 10.0 * (i - j) as f64,
+                };
+                storage.set(i, j, distance);
+            }
+        }
+        let locations = vec![Coordinate { lng: 0.0, lat: 0.0 }; size];
+        let distance_matrix = DistanceMatrix::new(locations, storage, true, false, Some(30.0));
+
+        let route = vec![4, 3, 2, 1];
+        let (order, distance) = held_karp_route(&route, &distance_matrix);
+        assert_eq!(vec![1, 2, 3, 4], order);
+        assert!(distance.approx_eq(8.0));
+        assert!(distance.approx_eq(brute_force(&mut route.clone(), 0, &distance_matrix)));
+    }
+}
@@ -1,3 +1,6 @@
+mod held_karp;
+pub use self::held_karp::*;
+
 mod relocation;
 pub use self::relocation::*;
 
@@ -7,34 +10,107 @@ pub use self::swap::*;
 mod swap_star;
 pub use self::swap_star::*;
 
+mod three_opt;
+pub use self::three_opt::*;
+
 mod two_opt;
 pub use self::two_opt::*;
 
+use crate::config::MoveKind;
+use crate::models::DistanceMatrix;
 use crate::solver::improvement::linked_list::LinkNode;
 use crate::solver::improvement::LocalSearch;
 use crate::solver::Context;
 
 pub trait Move {
     fn move_name(&self) -> &'static str;
+    fn move_kind(&self) -> MoveKind;
+    /// Correlated customers of `u` the move is evaluated with, when they differ for asymmetric
+    /// distances
+    fn neighbor_list(&self) -> NeighborList {
+        NeighborList::Successors
+    }
     unsafe fn delta(&self, ls: &LocalSearch, node_u: *mut LinkNode, node_v: *mut LinkNode) -> f64;
     unsafe fn perform(&self, ls: &mut LocalSearch, node_u: *mut LinkNode, node_v: *mut LinkNode);
 }
 
+/// Correlated customers `v` of a customer `u`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NeighborList {
+    // Nearest by the distance from `u`, for moves joining `u` to `v` or placing `u` near `v`
+    Successors,
+    // Nearest by the distance to `u`, for moves inserting `u` after `v`
+    Predecessors,
+}
+
+/// Direction in which two joined segments are traversed
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Orientation {
+    // The end of segment a is connected to the start of segment b
+    Forward,
+    // Both segments are reversed and the start of segment b is connected to the end of segment a
+    Reversed,
+}
+
+/// Cheapest edge joining segment `a` to segment `b`, where the segments are given by their first
+/// and last node. Only the connecting edge is costed, the caller accounts for reversing the
+/// segments themselves. Without asymmetric distances both orientations cost the same.
+pub fn cheapest_connection(
+    a_ends: (usize, usize),
+    b_ends: (usize, usize),
+    distance_matrix: &DistanceMatrix,
+    asymmetric: bool,
+) -> (Orientation, f64) {
+    let forward = distance_matrix.get(a_ends.1, b_ends.0);
+    if !asymmetric {
+        return (Orientation::Forward, forward);
+    }
+    let reversed = distance_matrix.get(b_ends.0, a_ends.1);
+    if reversed < forward {
+        (Orientation::Reversed, reversed)
+    } else {
+        (Orientation::Forward, forward)
+    }
+}
+
 pub struct Moves {
     pub neighbor: Vec<Box<dyn Move>>,
     pub depot: Vec<Box<dyn Move>>,
     pub empty_route: Vec<Box<dyn Move>>,
+    pub swap_star: bool,
 }
 
 impl Moves {
     pub fn new(ctx: &Context) -> Self {
+        let order = ctx.config.borrow().local_search_order.clone();
         Self {
-            neighbor: Self::neighborhood_moves(ctx),
-            depot: Self::depot_moves(ctx),
-            empty_route: Self::empty_route_moves(ctx),
+            neighbor: Self::ordered(Self::neighborhood_moves(ctx), &order),
+            depot: Self::ordered(Self::depot_moves(ctx), &order),
+            empty_route: Self::ordered(Self::empty_route_moves(ctx), &order),
+            swap_star: ctx.config.borrow().swap_star
+                && (order.is_empty() || order.contains(&MoveKind::SwapStar)),
         }
     }
 
+    /// Keeps the moves of the kinds in the order, sorted by the position of their kind. Moves of
+    /// the same kind keep their relative order. An empty order keeps all moves
+    fn ordered(moves: Vec<Box<dyn Move>>, order: &[MoveKind]) -> Vec<Box<dyn Move>> {
+        if order.is_empty() {
+            return moves;
+        }
+        let mut moves: Vec<(usize, Box<dyn Move>)> = moves
+            .into_iter()
+            .filter_map(|m| {
+                order
+                    .iter()
+                    .position(|&kind| kind == m.move_kind())
+                    .map(|position| (position, m))
+            })
+            .collect();
+        moves.sort_by_key(|&(position, _)| position);
+        moves.into_iter().map(|(_, m)| m).collect()
+    }
+
     fn neighborhood_moves(ctx: &Context) -> Vec<Box<dyn Move>> {
         let mut moves: Vec<Box<dyn Move>> = Vec::new();
         if ctx.config.borrow().relocate_single {
@@ -104,3 +180,91 @@ impl Moves {
         moves
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Matrix;
+
+    // Distances on a line, where driving from a higher to a lower index costs `backward` per unit
+    fn line_matrix(backward: f64) -> DistanceMatrix {
+        let size = 5;
+        let mut storage = Matrix::new(size, size);
+        for i in 0..size {
+            for j in 0..size {
+                let distance = if i < j {
+                    (j - i) as f64
+                } else {
+                    backward * (i - j) as f64
+                };
+                storage.set(i, j, distance);
+            }
+        }
+        DistanceMatrix::from_input(storage, false, None)
+    }
+
+    #[test]
+    fn neighbor_lists() {
+        // Only the relocations insert `u` after `v`, so they look for the nearest predecessors
+        let predecessors: Vec<Box<dyn Move>> = vec![
+            Box::new(RelocateSingle),
+            Box::new(RelocateDouble),
+            Box::new(RelocateDoubleReverse),
+        ];
+        for m in predecessors.iter() {
+            assert_eq!(
+                NeighborList::Predecessors,
+                m.neighbor_list(),
+                "{}",
+                m.move_name()
+            );
+        }
+        let successors: Vec<Box<dyn Move>> = vec![
+            Box::new(SwapOneWithOne),
+            Box::new(SwapTwoWithOne),
+            Box::new(SwapTwoWithTwo),
+            Box::new(TwoOptIntraReverse),
+            Box::new(TwoOptInterReverse),
+            Box::new(TwoOptInter),
+        ];
+        for m in successors.iter() {
+            assert_eq!(
+                NeighborList::Successors,
+                m.neighbor_list(),
+                "{}",
+                m.move_name()
+            );
+        }
+    }
+
+    #[test]
+    fn symmetric_connection() {
+        // Both orientations cost the same, so the forward one is kept
+        let distance_matrix = line_matrix(1.0);
+        for &asymmetric in &[false, true] {
+            let (orientation, cost) =
+                cheapest_connection((1, 2), (4, 3), &distance_matrix, asymmetric);
+            assert_eq!(Orientation::Forward, orientation);
+            assert_eq!(2.0, cost);
+        }
+    }
+
+    #[test]
+    fn asymmetric_connection() {
+        // Joining segment (3, 4) to (2, 1) forward drives backward from 4 to 2
+        let distance_matrix = line_matrix(3.0);
+        let (orientation, cost) = cheapest_connection((3, 4), (2, 1), &distance_matrix, true);
+        assert_eq!(Orientation::Reversed, orientation);
+        assert_eq!(2.0, cost);
+
+        // Driving forward from 2 to 4 is the cheapest
+        let (orientation, cost) = cheapest_connection((1, 2), (4, 3), &distance_matrix, true);
+        assert_eq!(Orientation::Forward, orientation);
+        assert_eq!(2.0, cost);
+
+        // Without the flag only the forward orientation is considered
+        let (orientation, cost) = cheapest_connection((3, 4), (2, 1), &distance_matrix, false);
+        assert_eq!(Orientation::Forward, orientation);
+        assert_eq!(6.0, cost);
+    }
+}
@@ -1,5 +1,5 @@
-use crate::solver::evaluate::route_cost;
-use crate::solver::improvement::{LinkNode, LocalSearch, Move};
+use crate::config::MoveKind;
+use crate::solver::improvement::{LinkNode, LocalSearch, Move, NeighborList};
 
 pub struct RelocateSingle;
 
@@ -7,6 +7,12 @@ impl Move for RelocateSingle {
     fn move_name(&self) -> &'static str {
         "RelocateSingle"
     }
+    fn move_kind(&self) -> MoveKind {
+        MoveKind::Relocate
+    }
+    fn neighbor_list(&self) -> NeighborList {
+        NeighborList::Predecessors
+    }
     unsafe fn delta(&self, ls: &LocalSearch, u_rc: *mut LinkNode, v_rc: *mut LinkNode) -> f64 {
         let distance_matrix = &ls.ctx.matrix_provider.distance;
         let nodes = &ls.ctx.problem.nodes;
@@ -36,16 +42,20 @@ impl Move for RelocateSingle {
 
         let mut overload_one = r1.overload;
         let mut overload_two = r2.overload;
+        let mut customers_one = r1.num_customers;
+        let mut customers_two = r2.num_customers;
 
         if r1.index != r2.index {
             let u_demand = nodes[u.number].demand;
             overload_one += -u_demand;
             overload_two += u_demand;
+            customers_one -= 1;
+            customers_two += 1;
         }
 
         let old_cost = r1.cost + r2.cost;
-        let new_cost = route_cost(distance_one, overload_one, ls.penalty_capacity)
-            + route_cost(distance_two, overload_two, ls.penalty_capacity);
+        let new_cost = ls.route_cost(distance_one, overload_one, customers_one)
+            + ls.route_cost(distance_two, overload_two, customers_two);
 
         // Return delta cost
         new_cost - old_cost
@@ -80,6 +90,12 @@ impl Move for RelocateDouble {
     fn move_name(&self) -> &'static str {
         "RelocateDouble"
     }
+    fn move_kind(&self) -> MoveKind {
+        MoveKind::OrOpt
+    }
+    fn neighbor_list(&self) -> NeighborList {
+        NeighborList::Predecessors
+    }
     unsafe fn delta(&self, ls: &LocalSearch, u_rc: *mut LinkNode, v_rc: *mut LinkNode) -> f64 {
         let distance_matrix = &ls.ctx.matrix_provider.distance;
         let nodes = &ls.ctx.problem.nodes;
@@ -118,17 +134,21 @@ impl Move for RelocateDouble {
 
         let mut overload_one = r1.overload;
         let mut overload_two = r2.overload;
+        let mut customers_one = r1.num_customers;
+        let mut customers_two = r2.num_customers;
 
         if r1.index != r2.index {
             let u_demand = nodes[u.number].demand;
             let x_demand = nodes[x.number].demand;
             overload_one += -u_demand - x_demand;
             overload_two += u_demand + x_demand;
+            customers_one -= 2;
+            customers_two += 2;
         }
 
         let old_cost = r1.cost + r2.cost;
-        let new_cost = route_cost(distance_one, overload_one, ls.penalty_capacity)
-            + route_cost(distance_two, overload_two, ls.penalty_capacity);
+        let new_cost = ls.route_cost(distance_one, overload_one, customers_one)
+            + ls.route_cost(distance_two, overload_two, customers_two);
 
         // Return delta cost
         new_cost - old_cost
@@ -164,6 +184,12 @@ impl Move for RelocateDoubleReverse {
     fn move_name(&self) -> &'static str {
         "RelocateDoubleReverse"
     }
+    fn move_kind(&self) -> MoveKind {
+        MoveKind::OrOpt
+    }
+    fn neighbor_list(&self) -> NeighborList {
+        NeighborList::Predecessors
+    }
     unsafe fn delta(&self, ls: &LocalSearch, u_rc: *mut LinkNode, v_rc: *mut LinkNode) -> f64 {
         let distance_matrix = &ls.ctx.matrix_provider.distance;
         let nodes = &ls.ctx.problem.nodes;
@@ -202,17 +228,21 @@ impl Move for RelocateDoubleReverse {
 
         let mut overload_one = r1.overload;
         let mut overload_two = r2.overload;
+        let mut customers_one = r1.num_customers;
+        let mut customers_two = r2.num_customers;
 
         if r1.index != r2.index {
             let u_demand = nodes[u.number].demand;
             let x_demand = nodes[x.number].demand;
             overload_one += -u_demand - x_demand;
             overload_two += u_demand + x_demand;
+            customers_one -= 2;
+            customers_two += 2;
         }
 
         let old_cost = r1.cost + r2.cost;
-        let new_cost = route_cost(distance_one, overload_one, ls.penalty_capacity)
-            + route_cost(distance_two, overload_two, ls.penalty_capacity);
+        let new_cost = ls.route_cost(distance_one, overload_one, customers_one)
+            + ls.route_cost(distance_two, overload_two, customers_two);
 
         // Return delta cost
         new_cost - old_cost
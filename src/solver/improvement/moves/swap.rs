@@ -1,4 +1,4 @@
-use crate::solver::evaluate::route_cost;
+use crate::config::MoveKind;
 use crate::solver::improvement::{LinkNode, LocalSearch, Move};
 
 pub struct SwapOneWithOne;
@@ -7,6 +7,9 @@ impl Move for SwapOneWithOne {
     fn move_name(&self) -> &'static str {
         "SwapOneWithOne"
     }
+    fn move_kind(&self) -> MoveKind {
+        MoveKind::Swap
+    }
     unsafe fn delta(&self, ls: &LocalSearch, u_rc: *mut LinkNode, v_rc: *mut LinkNode) -> f64 {
         let distance_matrix = &ls.ctx.matrix_provider.distance;
         let nodes = &ls.ctx.problem.nodes;
@@ -50,8 +53,8 @@ impl Move for SwapOneWithOne {
         }
 
         let old_cost = r1.cost + r2.cost;
-        let new_cost = route_cost(distance_one, overload_one, ls.penalty_capacity)
-            + route_cost(distance_two, overload_two, ls.penalty_capacity);
+        let new_cost = ls.route_cost(distance_one, overload_one, r1.num_customers)
+            + ls.route_cost(distance_two, overload_two, r2.num_customers);
 
         // Return delta cost
         new_cost - old_cost
@@ -92,6 +95,9 @@ impl Move for SwapTwoWithOne {
     fn move_name(&self) -> &'static str {
         "SwapTwoWithOne"
     }
+    fn move_kind(&self) -> MoveKind {
+        MoveKind::Swap
+    }
     unsafe fn delta(&self, ls: &LocalSearch, u_rc: *mut LinkNode, v_rc: *mut LinkNode) -> f64 {
         let distance_matrix = &ls.ctx.matrix_provider.distance;
         let nodes = &ls.ctx.problem.nodes;
@@ -133,6 +139,8 @@ impl Move for SwapTwoWithOne {
 
         let mut overload_one = r1.overload;
         let mut overload_two = r2.overload;
+        let mut customers_one = r1.num_customers;
+        let mut customers_two = r2.num_customers;
 
         if r1.index != r2.index {
             let u_demand = nodes[u.number].demand;
@@ -140,11 +148,13 @@ impl Move for SwapTwoWithOne {
             let x_demand = nodes[x.number].demand;
             overload_one += -u_demand - x_demand + v_demand;
             overload_two += u_demand + x_demand - v_demand;
+            customers_one -= 1;
+            customers_two += 1;
         }
 
         let old_cost = r1.cost + r2.cost;
-        let new_cost = route_cost(distance_one, overload_one, ls.penalty_capacity)
-            + route_cost(distance_two, overload_two, ls.penalty_capacity);
+        let new_cost = ls.route_cost(distance_one, overload_one, customers_one)
+            + ls.route_cost(distance_two, overload_two, customers_two);
 
         // Return delta cost
         new_cost - old_cost
@@ -186,6 +196,9 @@ impl Move for SwapTwoWithTwo {
     fn move_name(&self) -> &'static str {
         "SwapTwoWithTwo"
     }
+    fn move_kind(&self) -> MoveKind {
+        MoveKind::Swap
+    }
     unsafe fn delta(&self, ls: &LocalSearch, u_rc: *mut LinkNode, v_rc: *mut LinkNode) -> f64 {
         let distance_matrix = &ls.ctx.matrix_provider.distance;
         let nodes = &ls.ctx.problem.nodes;
@@ -248,8 +261,8 @@ impl Move for SwapTwoWithTwo {
         }
 
         let old_cost = r1.cost + r2.cost;
-        let new_cost = route_cost(distance_one, overload_one, ls.penalty_capacity)
-            + route_cost(distance_two, overload_two, ls.penalty_capacity);
+        let new_cost = ls.route_cost(distance_one, overload_one, r1.num_customers)
+            + ls.route_cost(distance_two, overload_two, r2.num_customers);
 
         // Return delta cost
         new_cost - old_cost
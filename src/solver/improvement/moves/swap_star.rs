@@ -48,7 +48,8 @@ impl SwapStar {
         ls.preprocess_insertions(r1_ptr, r2_ptr);
         ls.preprocess_insertions(r2_ptr, r1_ptr);
 
-        // Loop over pairs of nodes in the two routes
+        // Loop over pairs of nodes in the two routes. SWAP* moves single customers between the
+        // routes, so it never moves a customer of a cluster
         while !(*u_ptr).is_depot() {
             let u = &*u_ptr;
             let mut v_ptr = (*r2.start_depot).successor;
@@ -56,18 +57,18 @@ impl SwapStar {
                 let v = &*v_ptr;
 
                 // Calculate the change in penalty when u and v swap routes
-                let delta_penalty_r1 = 0f64.max(
+                let delta_penalty_r1 = ls.capacity_penalty(
                     r1.overload - problem.nodes[u.number].demand + problem.nodes[v.number].demand,
-                ) * ls.penalty_capacity
-                    - 0f64.max(r1.overload) * ls.penalty_capacity;
-                let delta_penalty_r2 = 0f64.max(
+                ) - ls.capacity_penalty(r1.overload);
+                let delta_penalty_r2 = ls.capacity_penalty(
                     r2.overload + problem.nodes[u.number].demand - problem.nodes[v.number].demand,
-                ) * ls.penalty_capacity
-                    - 0f64.max(r2.overload) * ls.penalty_capacity;
+                ) - ls.capacity_penalty(r2.overload);
 
                 // Filter to avoid moves with huge penalties due to violation of capacity constraints
                 if (u.delta_removal + v.delta_removal + delta_penalty_r1 + delta_penalty_r2)
                     .approx_lte(0.0)
+                    && !ls.in_cluster(u.number)
+                    && !ls.in_cluster(v.number)
                 {
                     let mut m = BestSwapStar::new();
                     m.u = u_ptr;
@@ -107,15 +108,23 @@ impl SwapStar {
             m.u = u_ptr;
             let best_insert = &ls.best_inserts.get(r2.index, u.number).locations[0];
             m.pos_u = best_insert.node;
-            let delta_penalty_r1 = 0f64.max(r1.overload - problem.nodes[u.number].demand)
-                * ls.penalty_capacity
-                - 0f64.max(r1.overload) * ls.penalty_capacity;
-            let delta_penalty_r2 = 0f64.max(r2.overload + problem.nodes[u.number].demand)
-                * ls.penalty_capacity
-                - 0f64.max(r2.overload) * ls.penalty_capacity;
-            m.cost = u.delta_removal + best_insert.cost + delta_penalty_r1 + delta_penalty_r2;
-
-            if m.cost.approx_lt(best_move.cost) {
+            let delta_penalty_r1 = ls
+                .capacity_penalty(r1.overload - problem.nodes[u.number].demand)
+                - ls.capacity_penalty(r1.overload);
+            let delta_penalty_r2 = ls
+                .capacity_penalty(r2.overload + problem.nodes[u.number].demand)
+                - ls.capacity_penalty(r2.overload);
+            let delta_short_route = ls.short_route_penalty(r1.num_customers - 1)
+                - ls.short_route_penalty(r1.num_customers)
+                + ls.short_route_penalty(r2.num_customers + 1)
+                - ls.short_route_penalty(r2.num_customers);
+            m.cost = u.delta_removal
+                + best_insert.cost
+                + delta_penalty_r1
+                + delta_penalty_r2
+                + delta_short_route;
+
+            if m.cost.approx_lt(best_move.cost) && !ls.in_cluster(u.number) {
                 best_move = m;
             }
 
@@ -131,15 +140,23 @@ impl SwapStar {
             m.v = v_ptr;
             let best_insert = &ls.best_inserts.get(r1.index, v.number).locations[0];
             m.pos_v = best_insert.node;
-            let delta_penalty_r1 = 0f64.max(r1.overload + problem.nodes[v.number].demand)
-                * ls.penalty_capacity
-                - 0f64.max(r1.overload) * ls.penalty_capacity;
-            let delta_penalty_r2 = 0f64.max(r2.overload - problem.nodes[v.number].demand)
-                * ls.penalty_capacity
-                - 0f64.max(r2.overload) * ls.penalty_capacity;
-            m.cost = v.delta_removal + best_insert.cost + delta_penalty_r1 + delta_penalty_r2;
-
-            if m.cost.approx_lt(best_move.cost) {
+            let delta_penalty_r1 = ls
+                .capacity_penalty(r1.overload + problem.nodes[v.number].demand)
+                - ls.capacity_penalty(r1.overload);
+            let delta_penalty_r2 = ls
+                .capacity_penalty(r2.overload - problem.nodes[v.number].demand)
+                - ls.capacity_penalty(r2.overload);
+            let delta_short_route = ls.short_route_penalty(r1.num_customers + 1)
+                - ls.short_route_penalty(r1.num_customers)
+                + ls.short_route_penalty(r2.num_customers - 1)
+                - ls.short_route_penalty(r2.num_customers);
+            m.cost = v.delta_removal
+                + best_insert.cost
+                + delta_penalty_r1
+                + delta_penalty_r2
+                + delta_short_route;
+
+            if m.cost.approx_lt(best_move.cost) && !ls.in_cluster(v.number) {
                 best_move = m;
             }
 
@@ -147,10 +164,14 @@ impl SwapStar {
         }
 
         // Return false if the move does not reduce the objective function
-        if best_move.cost.approx_gte(0.0) {
+        if best_move.cost.approx_gte_eps(0.0, ls.epsilon) {
             return false;
         }
 
+        let snapshot = match ls.checks_moves_between_routes() {
+            true => Some(ls.snapshot_routes([r1_ptr, r2_ptr])),
+            false => None,
+        };
         ls.move_count += 1;
 
         // Relocate u into route of v
@@ -166,6 +187,9 @@ impl SwapStar {
         ls.update_route(r1_ptr);
         ls.update_route(r2_ptr);
 
-        true
+        match snapshot {
+            Some(snapshot) => ls.keep_move(snapshot, best_move.cost),
+            None => true,
+        }
     }
 }
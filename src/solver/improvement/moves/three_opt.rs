@@ -0,0 +1,189 @@
+use crate::models::{CorrelationMatrix, DistanceMatrix};
+
+/// Reconnection of the segments `b` and `c` between the three removed edges of a route
+/// `a b c d`. A prime marks a reversed segment
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Reconnection {
+    // a b' c d
+    ReverseFirst,
+    // a b c' d
+    ReverseSecond,
+    // a c' b' d
+    ReverseBoth,
+    // a b' c' d
+    ReverseEach,
+    // a c b d
+    Exchange,
+    // a c b' d
+    ExchangeReverseFirst,
+    // a c' b d
+    ExchangeReverseSecond,
+}
+
+impl Reconnection {
+    pub const ALL: [Reconnection; 7] = [
+        Reconnection::ReverseFirst,
+        Reconnection::ReverseSecond,
+        Reconnection::ReverseBoth,
+        Reconnection::ReverseEach,
+        Reconnection::Exchange,
+        Reconnection::ExchangeReverseFirst,
+        Reconnection::ExchangeReverseSecond,
+    ];
+
+    // Whether the segments are exchanged, and whether b and c are reversed
+    fn parts(self) -> (bool, bool, bool) {
+        match self {
+            Reconnection::ReverseFirst => (false, true, false),
+            Reconnection::ReverseSecond => (false, false, true),
+            Reconnection::ReverseBoth => (true, true, true),
+            Reconnection::ReverseEach => (false, true, true),
+            Reconnection::Exchange => (true, false, false),
+            Reconnection::ExchangeReverseFirst => (true, true, false),
+            Reconnection::ExchangeReverseSecond => (true, false, true),
+        }
+    }
+}
+
+/// Intra-route 3-opt move. The removed edges start at the positions (i, j, k) of the route
+/// with the depot at both ends, so b is `i + 1..=j` and c is `j + 1..=k`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ThreeOptMove {
+    pub positions: (usize, usize, usize),
+    pub reconnection: Reconnection,
+    pub delta: f64,
+}
+
+/// Finds the 3-opt move improving the route by the most, at least by `epsilon`. The first new
+/// edge must join the end of `a` to a correlated customer. Reversed segments are costed in the
+/// reverse direction, so the delta is exact for asymmetric distances
+pub fn route_3opt_best_move(
+    route: &[usize],
+    distance_matrix: &DistanceMatrix,
+    correlation: &CorrelationMatrix,
+    epsilon: f64,
+) -> Option<ThreeOptMove> {
+    let tour: Vec<usize> = std::iter::once(0)
+        .chain(route.iter().copied())
+        .chain(std::iter::once(0))
+        .collect();
+    let m = route.len();
+    // Cumulative distances up to each position, forward and backward
+    let mut forward = vec![0.0; tour.len()];
+    let mut backward = vec![0.0; tour.len()];
+    for p in 1..tour.len() {
+        forward[p] = forward[p - 1] + distance_matrix.get(tour[p - 1], tour[p]);
+        backward[p] = backward[p - 1] + distance_matrix.get(tour[p], tour[p - 1]);
+    }
+    // Ends and internal cost of the segment from `start` to `end`, possibly reversed
+    let segment = |start: usize, end: usize, reversed: bool| match reversed {
+        false => (tour[start], tour[end], forward[end] - forward[start]),
+        true => (tour[end], tour[start], backward[end] - backward[start]),
+    };
+
+    let mut best: Option<ThreeOptMove> = None;
+    for i in 0..m.saturating_sub(1) {
+        let neighbors = correlation.get(tour[i]);
+        for j in (i + 1)..m {
+            for k in (j + 1)..=m {
+                let removed = forward[k + 1] - forward[i];
+                for &reconnection in Reconnection::ALL.iter() {
+                    let (exchanged, reverse_b, reverse_c) = reconnection.parts();
+                    let b = segment(i + 1, j, reverse_b);
+                    let c = segment(j + 1, k, reverse_c);
+                    let (x, y) = if exchanged { (c, b) } else { (b, c) };
+                    if !neighbors.contains(&x.0) {
+                        continue;
+                    }
+                    let added = distance_matrix.get(tour[i], x.0)
+                        + x.2
+                        + distance_matrix.get(x.1, y.0)
+                        + y.2
+                        + distance_matrix.get(y.1, tour[k + 1]);
+                    let delta = added - removed;
+                    let threshold = best.map_or(-epsilon, |best| best.delta.min(-epsilon));
+                    if delta < threshold {
+                        best = Some(ThreeOptMove {
+                            positions: (i, j, k),
+                            reconnection,
+                            delta,
+                        });
+                    }
+                }
+            }
+        }
+    }
+    best
+}
+
+/// Applies a 3-opt move to the route
+pub fn apply_3opt(route: &mut Vec<usize>, three_opt: &ThreeOptMove) {
+    let (i, j, k) = three_opt.positions;
+    let (exchanged, reverse_b, reverse_c) = three_opt.reconnection.parts();
+    // Positions in the tour are one ahead of the positions in the route
+    let mut b = route[i..j].to_vec();
+    let mut c = route[j..k].to_vec();
+    if reverse_b {
+        b.reverse();
+    }
+    if reverse_c {
+        c.reverse();
+    }
+    let (x, y) = if exchanged { (c, b) } else { (b, c) };
+    route.splice(i..k, x.into_iter().chain(y));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::models::MatrixProvider;
+    use crate::utils::{coordinate_problem, FloatCompare};
+
+    #[test]
+    fn three_opt_beyond_two_opt() {
+        let coords = [
+            (0.0, 0.0),
+            (-2.0, 2.0),
+            (-4.0, 6.0),
+            (-4.0, -1.0),
+            (5.0, -4.0),
+            (8.0, 2.0),
+            (1.0, 2.0),
+        ];
+        let problem = coordinate_problem(&coords, 10.0);
+        let mut config = Config::default();
+        config.round_distances = false;
+        let matrix_provider = MatrixProvider::new(&problem, &config, None);
+        let distance_matrix = &matrix_provider.distance;
+
+        let mut route = vec![1, 2, 3, 4, 5, 6];
+        let distance = distance_matrix.route_distance(&route);
+        // No reversal of a single segment improves the route
+        for start in 0..route.len() {
+            for end in (start + 2)..=route.len() {
+                let mut reversed = route.clone();
+                reversed[start..end].reverse();
+                assert!(distance_matrix
+                    .route_distance(&reversed)
+                    .approx_gte(distance));
+            }
+        }
+
+        let three_opt = route_3opt_best_move(
+            &route,
+            distance_matrix,
+            &matrix_provider.correlation,
+            config.float_epsilon,
+        )
+        .expect("No improving move");
+        assert!(three_opt.delta < -1.0);
+        apply_3opt(&mut route, &three_opt);
+        let mut customers = route.clone();
+        customers.sort_unstable();
+        assert_eq!(vec![1, 2, 3, 4, 5, 6], customers);
+        assert!(distance_matrix
+            .route_distance(&route)
+            .approx_eq(distance + three_opt.delta));
+    }
+}
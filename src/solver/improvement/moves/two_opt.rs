@@ -1,4 +1,4 @@
-use crate::solver::evaluate::route_cost;
+use crate::config::MoveKind;
 use crate::solver::improvement::{LinkNode, LocalSearch, Move};
 
 pub struct TwoOptIntraReverse;
@@ -7,6 +7,9 @@ impl Move for TwoOptIntraReverse {
     fn move_name(&self) -> &'static str {
         "TwoOptIntraReverse"
     }
+    fn move_kind(&self) -> MoveKind {
+        MoveKind::TwoOpt
+    }
     unsafe fn delta(&self, ls: &LocalSearch, u_rc: *mut LinkNode, v_rc: *mut LinkNode) -> f64 {
         let distance_matrix = &ls.ctx.matrix_provider.distance;
 
@@ -27,11 +30,17 @@ impl Move for TwoOptIntraReverse {
             return 0.0;
         }
 
-        let delta_distance = -distance_matrix.get(u.number, x.number)
+        let mut delta_distance = -distance_matrix.get(u.number, x.number)
             - distance_matrix.get(v.number, y.number)
             + distance_matrix.get(u.number, v.number)
             + distance_matrix.get(x.number, y.number);
 
+        // The segment from x to v is traversed in the opposite direction
+        if ls.asymmetric {
+            delta_distance +=
+                v.cum_reverse_distance - x.cum_reverse_distance - (v.cum_distance - x.cum_distance);
+        }
+
         // Return delta cost
         delta_distance as f64
     }
@@ -53,6 +62,9 @@ impl Move for TwoOptInterReverse {
     fn move_name(&self) -> &'static str {
         "TwoOptInterReverse"
     }
+    fn move_kind(&self) -> MoveKind {
+        MoveKind::TwoOpt
+    }
     unsafe fn delta(&self, ls: &LocalSearch, u_rc: *mut LinkNode, v_rc: *mut LinkNode) -> f64 {
         let distance_matrix = &ls.ctx.matrix_provider.distance;
 
@@ -70,16 +82,26 @@ impl Move for TwoOptInterReverse {
 
         let cap = ls.ctx.problem.vehicle.cap;
 
-        let distance_one =
-            u.cum_distance + v.cum_distance + distance_matrix.get(u.number, v.number);
-        let distance_two = r1.distance - x.cum_distance + r2.distance - y.cum_distance
-            + distance_matrix.get(x.number, y.number);
+        // The start of route two and the end of route one are traversed in the opposite direction
+        let (v_distance, x_distance) = if ls.asymmetric {
+            (
+                v.cum_reverse_distance,
+                (*r1.end_depot).cum_reverse_distance - x.cum_reverse_distance,
+            )
+        } else {
+            (v.cum_distance, r1.distance - x.cum_distance)
+        };
+        let distance_one = u.cum_distance + v_distance + distance_matrix.get(u.number, v.number);
+        let distance_two =
+            x_distance + r2.distance - y.cum_distance + distance_matrix.get(x.number, y.number);
         let overload_one = u.cum_load + v.cum_load - cap;
         let overload_two = r1.load - u.cum_load + r2.load - v.cum_load - cap;
+        let customers_one = u.position + v.position;
+        let customers_two = r1.num_customers - u.position + r2.num_customers - v.position;
 
         let old_cost = r1.cost + r2.cost;
-        let new_cost = route_cost(distance_one, overload_one, ls.penalty_capacity)
-            + route_cost(distance_two, overload_two, ls.penalty_capacity);
+        let new_cost = ls.route_cost(distance_one, overload_one, customers_one)
+            + ls.route_cost(distance_two, overload_two, customers_two);
 
         // Return delta cost
         new_cost - old_cost
@@ -114,6 +136,9 @@ impl Move for TwoOptInter {
     fn move_name(&self) -> &'static str {
         "TwoOptInter"
     }
+    fn move_kind(&self) -> MoveKind {
+        MoveKind::TwoOpt
+    }
     unsafe fn delta(&self, ls: &LocalSearch, u_rc: *mut LinkNode, v_rc: *mut LinkNode) -> f64 {
         let distance_matrix = &ls.ctx.matrix_provider.distance;
 
@@ -137,10 +162,12 @@ impl Move for TwoOptInter {
             v.cum_distance + r1.distance - x.cum_distance + distance_matrix.get(v.number, x.number);
         let overload_one = u.cum_load + r2.load - v.cum_load - cap;
         let overload_two = v.cum_load + r1.load - u.cum_load - cap;
+        let customers_one = u.position + r2.num_customers - v.position;
+        let customers_two = v.position + r1.num_customers - u.position;
 
         let old_cost = r1.cost + r2.cost;
-        let new_cost = route_cost(distance_one, overload_one, ls.penalty_capacity)
-            + route_cost(distance_two, overload_two, ls.penalty_capacity);
+        let new_cost = ls.route_cost(distance_one, overload_one, customers_one)
+            + ls.route_cost(distance_two, overload_two, customers_two);
 
         // Return delta cost
         new_cost - old_cost
@@ -159,3 +186,90 @@ impl Move for TwoOptInter {
         ls.update_route(r2);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::models::{DistanceMatrix, Matrix};
+    use crate::solver::genetic::Individual;
+    use crate::solver::Context;
+    use crate::utils::{coordinate_problem, problem_context, FloatCompare};
+
+    // Customers on a line where driving from 2 to 1 or from 3 to 2 is a detour
+    fn asymmetric_context(asymmetric: bool) -> Context {
+        let coords: Vec<(f64, f64)> = (0..5).map(|id| (id as f64, 0.0)).collect();
+        let mut config = Config::default();
+        config.local_search_granularity = 2;
+        config.asymmetric = asymmetric;
+        let mut ctx = problem_context(coordinate_problem(&coords, 10.0), None, config);
+
+        let size = ctx.problem.dim();
+        let mut storage = Matrix::new(size, size);
+        for i in 0..size {
+            for j in 0..size {
+                let distance = match (i, j) {
+                    (2, 1) | (3, 2) => 5.0,
+                    _ => (i as f64 - j as f64).abs(),
+                };
+                storage.set(i, j, distance);
+            }
+        }
+        let locations = ctx.problem.nodes.iter().map(|node| node.coord).collect();
+        ctx.matrix_provider.distance =
+            DistanceMatrix::new(locations, storage, true, false, Some(5.0));
+        ctx
+    }
+
+    // Returns the delta of the move and the actual change in cost after performing it
+    fn delta_and_change(
+        ctx: &Context,
+        phenotype: Vec<Vec<usize>>,
+        m: &dyn Move,
+        u: usize,
+        v: usize,
+    ) -> (f64, f64) {
+        let mut individual = Individual::empty();
+        individual.phenotype = phenotype;
+        individual
+            .phenotype
+            .resize(ctx.config.borrow().num_vehicles as usize, Vec::new());
+        let mut ls = LocalSearch::new(ctx, 1.0);
+        ls.load_individual(&individual);
+        let cost = |ls: &LocalSearch| ls.routes.iter().map(|route| route.cost).sum::<f64>();
+        let before = cost(&ls);
+        unsafe {
+            let u = ls.nodes.as_mut_ptr().add(u);
+            let v = ls.nodes.as_mut_ptr().add(v);
+            let delta = m.delta(&ls, u, v);
+            m.perform(&mut ls, u, v);
+            (delta, cost(&ls) - before)
+        }
+    }
+
+    #[test]
+    fn asymmetric_reversal_deltas() {
+        let ctx = asymmetric_context(true);
+        let distance = &ctx.matrix_provider.distance;
+        assert!(!distance.is_reversible(&[1, 2]));
+        assert!(distance.is_reversible(&[1]));
+
+        let (delta, change) =
+            delta_and_change(&ctx, vec![vec![1, 2, 3, 4]], &TwoOptIntraReverse, 1, 3);
+        assert!(delta.approx_eq(change));
+        let (delta, change) = delta_and_change(
+            &ctx,
+            vec![vec![1, 2], vec![3, 4]],
+            &TwoOptInterReverse,
+            1,
+            3,
+        );
+        assert!(delta.approx_eq(change));
+
+        // Without the flag the reversed segments are costed as if the distances were symmetric
+        let ctx = asymmetric_context(false);
+        let (delta, change) =
+            delta_and_change(&ctx, vec![vec![1, 2, 3, 4]], &TwoOptIntraReverse, 1, 3);
+        assert!(!delta.approx_eq(change));
+    }
+}
@@ -15,6 +15,7 @@ use std::collections::HashSet;
 use ahash::RandomState;
 use instant::Duration;
 
+use crate::solver::evaluate::{location_penalty, route_cost, short_route_penalty};
 use crate::solver::genetic::Individual;
 use crate::solver::Context;
 use crate::utils::FloatCompare;
@@ -40,7 +41,7 @@ impl RuinRecreateSolution {
         Self {
             routes,
             unassigned,
-            ruined_routes: HashSet::with_hasher(RandomState::new()),
+            ruined_routes: HashSet::with_hasher(ctx.random.random_state()),
             locations,
             cost: f64::INFINITY,
         }
@@ -92,12 +93,7 @@ impl RuinRecreateSolution {
     where
         I: Iterator<Item = &'a usize>,
     {
-        let penalty_capacity = ctx.config.borrow().penalty_capacity;
-        self.cost = self
-            .routes
-            .iter()
-            .map(|route| route.distance + 0f64.max(route.overload) * penalty_capacity)
-            .sum();
+        self.cost = self.calculate_cost(ctx);
 
         for &route_index in updated_routes {
             for node_index in 0..self.routes[route_index].nodes.len() {
@@ -108,12 +104,56 @@ impl RuinRecreateSolution {
     }
 
     pub fn reevaluate(&mut self, ctx: &Context) {
-        let penalty_capacity = ctx.config.borrow().penalty_capacity;
-        self.cost = self
+        self.cost = self.calculate_cost(ctx);
+    }
+
+    fn calculate_cost(&self, ctx: &Context) -> f64 {
+        let config = ctx.config.borrow();
+        let cost: f64 = self
             .routes
             .iter()
-            .map(|route| route.distance + 0f64.max(route.overload) * penalty_capacity)
+            .map(|route| {
+                route_cost(
+                    route.distance,
+                    route.overload,
+                    ctx.problem.vehicle.cap,
+                    config.penalty_capacity,
+                    &config.capacity_penalty_tiers,
+                ) + short_route_penalty(
+                    route.nodes.len(),
+                    config.min_customers_per_route,
+                    config.short_route_penalty,
+                ) + match config.max_distinct_locations_per_route {
+                    Some(_) => location_penalty(
+                        ctx.problem.num_locations(&route.nodes),
+                        config.max_distinct_locations_per_route,
+                        config.penalty_capacity,
+                    ),
+                    None => 0.0,
+                }
+            })
             .sum();
+        let overlap_cost = if config.overlap_penalty > 0.0 {
+            let area = ctx
+                .problem
+                .route_overlap_area(self.routes.iter().map(|route| route.nodes.as_slice()));
+            config.overlap_penalty * area
+        } else {
+            0.0
+        };
+        let emissions_cost = match &ctx.leg_cost {
+            Some(leg_cost) if config.emissions_weight > 0.0 => {
+                let emissions: f64 = self
+                    .routes
+                    .iter()
+                    .filter(|route| !route.nodes.is_empty())
+                    .map(|route| leg_cost.route_cost(ctx, &route.nodes))
+                    .sum();
+                config.emissions_weight * emissions
+            }
+            _ => 0.0,
+        };
+        cost + overlap_cost + emissions_cost
     }
 }
 
@@ -186,17 +226,18 @@ impl RuinRecreate {
     }
 
     pub fn complete(&self) -> bool {
-        self.acceptance.completed()
+        self.acceptance.completed() || self.ctx.deadline_reached()
     }
 
     fn update_best(&mut self) {
         self.best_solution = Some(self.current_solution.clone());
         let mut search_history = self.ctx.search_history.borrow_mut();
+        let epsilon = self.ctx.config.borrow().float_epsilon;
         if self.current_solution.is_feasible()
             && self
                 .current_solution
                 .cost
-                .approx_lt(search_history.best_cost)
+                .approx_lt_eps(search_history.best_cost, epsilon)
         {
             let mut best_individual = Individual::new_random(self.ctx, 0);
             self.update_individual(&self.current_solution, &mut best_individual);
@@ -209,6 +250,11 @@ impl RuinRecreate {
     pub fn search(&mut self) {
         let update_interval = 1000;
         for i in 1..=update_interval {
+            // Stop between R&R moves such that the solutions stay consistent
+            if self.ctx.deadline_reached() {
+                break;
+            }
+
             // Check for possible update of penalty
             if i == update_interval {
                 // Update penalty if enabled in search
@@ -240,7 +286,12 @@ impl RuinRecreate {
                 .accept(self.current_solution.cost, cost_before, &self.ctx.random)
             {
                 if let Some(best_solution) = self.best_solution.as_ref() {
-                    if self.current_solution.cost.approx_lt(best_solution.cost) {
+                    let epsilon = self.ctx.config.borrow().float_epsilon;
+                    if self
+                        .current_solution
+                        .cost
+                        .approx_lt_eps(best_solution.cost, epsilon)
+                    {
                         self.update_best();
                     }
                 } else {
@@ -284,6 +335,7 @@ impl RuinRecreate {
             individual.phenotype[route_number] = route.nodes.clone();
             individual.genotype.extend(route.nodes.clone());
         }
+        individual.mark_split();
 
         // Reevaluate the individual
         individual.sort_routes(self.ctx);
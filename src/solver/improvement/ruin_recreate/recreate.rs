@@ -1,3 +1,4 @@
+use crate::solver::evaluate::capacity_penalty;
 use crate::solver::improvement::RuinRecreateSolution;
 use crate::solver::Context;
 use crate::utils::FloatCompare;
@@ -32,6 +33,48 @@ fn sort_closest_to_depot(ctx: &Context, solution: &mut RuinRecreateSolution) {
     })
 }
 
+// Route of the customers already assigned from the cluster of the customer, if any
+fn cluster_route(ctx: &Context, solution: &RuinRecreateSolution, customer: usize) -> Option<usize> {
+    let cluster = ctx
+        .problem
+        .clusters
+        .iter()
+        .find(|cluster| cluster.contains(&customer))?;
+    solution
+        .routes
+        .iter()
+        .position(|route| route.nodes.iter().any(|node| cluster.contains(node)))
+}
+
+// Demand that joins a route with the customer. The first customer of a cluster to be inserted
+// brings the rest of its cluster along, since they may only join the same route
+fn joining_demand(ctx: &Context, customer: usize, cluster_route: Option<usize>) -> f64 {
+    let cluster = ctx
+        .problem
+        .clusters
+        .iter()
+        .find(|cluster| cluster.contains(&customer));
+    match (cluster, cluster_route) {
+        (Some(cluster), None) => cluster
+            .iter()
+            .map(|&node| ctx.problem.nodes[node].demand)
+            .sum(),
+        _ => ctx.problem.nodes[customer].demand,
+    }
+}
+
+// Whether inserting the customer keeps the route within the distinct locations per route
+fn fits_locations(ctx: &Context, nodes: &[usize], customer: usize) -> bool {
+    match ctx.config.borrow().max_distinct_locations_per_route {
+        Some(max_locations) => {
+            let mut nodes = nodes.to_vec();
+            nodes.push(customer);
+            ctx.problem.num_locations(&nodes) <= max_locations
+        }
+        None => true,
+    }
+}
+
 pub trait Recreate {
     fn run(&self, ctx: &Context, solution: &mut RuinRecreateSolution);
 }
@@ -66,12 +109,16 @@ impl Default for GreedyBlink {
 impl Recreate for GreedyBlink {
     fn run(&self, ctx: &Context, solution: &mut RuinRecreateSolution) {
         self.sort_unassigned(ctx, solution);
-        let problem = &ctx.problem;
 
         let mut updated_routes = solution.ruined_routes.clone();
         while !solution.unassigned.is_empty() {
             let customer = solution.unassigned.remove(0);
-            let demand = problem.nodes[customer].demand;
+            // A customer of a cluster may only join the route of its cluster
+            let cluster_route = cluster_route(ctx, solution, customer);
+            let demand = joining_demand(ctx, customer, cluster_route);
+            let allowed = |route_number: usize| {
+                cluster_route.is_none() || cluster_route == Some(route_number)
+            };
 
             let mut best_route: Option<usize> = None;
             let mut best_distance = f64::MAX;
@@ -79,7 +126,10 @@ impl Recreate for GreedyBlink {
 
             for &route_number in updated_routes.iter() {
                 let route = solution.routes.get_mut(route_number).unwrap();
-                if (route.overload + demand).approx_lte(0.0) {
+                if allowed(route_number)
+                    && (route.overload + demand).approx_lte(0.0)
+                    && fits_locations(ctx, &route.nodes, customer)
+                {
                     for index in 0..=route.nodes.len() {
                         let delta_distance = route.delta_distance(index, customer, ctx);
                         if delta_distance.approx_lt(best_distance) {
@@ -103,22 +153,41 @@ impl Recreate for GreedyBlink {
                 let mut best_cost = f64::MAX;
                 let mut best_node_index = 0;
 
-                for (route_number, route) in solution.routes.iter_mut().enumerate() {
-                    let overload = route.overload + demand;
-                    let overload_cost = 0f64.max(overload) * ctx.config.borrow().penalty_capacity;
-                    for index in 0..=route.nodes.len() {
-                        let delta_distance = route.delta_distance(index, customer, ctx);
-                        let delta_cost = delta_distance + overload_cost;
-                        if delta_cost.approx_lt(best_cost) {
-                            best_cost = delta_cost;
-                            best_node_index = index;
-                            if let Some(best_route_number) = best_route.as_mut() {
-                                *best_route_number = route_number;
-                            } else {
-                                best_route = Some(route_number);
+                // Routes beyond the distinct locations are only used if no other route is allowed
+                for &keep_locations in [true, false].iter() {
+                    for (route_number, route) in solution.routes.iter_mut().enumerate() {
+                        if !allowed(route_number)
+                            || (keep_locations && !fits_locations(ctx, &route.nodes, customer))
+                        {
+                            continue;
+                        }
+                        let overload = route.overload + demand;
+                        let overload_cost = {
+                            let config = ctx.config.borrow();
+                            capacity_penalty(
+                                overload,
+                                ctx.problem.vehicle.cap,
+                                config.penalty_capacity,
+                                &config.capacity_penalty_tiers,
+                            )
+                        };
+                        for index in 0..=route.nodes.len() {
+                            let delta_distance = route.delta_distance(index, customer, ctx);
+                            let delta_cost = delta_distance + overload_cost;
+                            if delta_cost.approx_lt(best_cost) {
+                                best_cost = delta_cost;
+                                best_node_index = index;
+                                if let Some(best_route_number) = best_route.as_mut() {
+                                    *best_route_number = route_number;
+                                } else {
+                                    best_route = Some(route_number);
+                                }
                             }
                         }
                     }
+                    if best_route.is_some() {
+                        break;
+                    }
                 }
                 let best_route_number = best_route.expect("No best route found");
                 solution.routes[best_route_number].add(best_node_index, customer, ctx);
@@ -129,3 +198,38 @@ impl Recreate for GreedyBlink {
         solution.ruined_routes.clear();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::solver::genetic::Individual;
+    use crate::utils::{demand_problem, problem_context};
+
+    #[test]
+    fn cluster_joins_route_with_room_for_all() {
+        // Customer 1 is routed, customers 2 and 3 form a cluster next to it. One of them fits
+        // the route of customer 1, but not both
+        let coords = [(0.0, 0.0), (10.0, 0.0), (11.0, 0.0), (11.0, 1.0)];
+        let mut ctx = problem_context(
+            demand_problem(&coords, &[6.0, 3.0, 3.0], 10.0),
+            None,
+            Config::default(),
+        );
+        ctx.problem.clusters = vec![vec![2, 3]];
+        let num_routes = ctx.config.borrow().num_vehicles as usize;
+
+        let mut individual = Individual::new(vec![1], 0);
+        individual.phenotype = vec![Vec::new(); num_routes];
+        individual.phenotype[0] = vec![1];
+        individual.evaluate(&ctx);
+        let mut solution = RuinRecreateSolution::new(&ctx);
+        solution.load(&individual);
+        solution.unassigned = vec![2, 3];
+        solution.ruined_routes.extend(0..num_routes);
+
+        GreedyBlink::default().run(&ctx, &mut solution);
+        assert!(solution.is_feasible());
+        assert_eq!(vec![1], solution.routes[0].nodes);
+    }
+}
@@ -41,6 +41,7 @@ impl AdjacentStringRemoval {
         lt: usize,
     ) {
         let node_index = solution.locations[node].node_index;
+        let first_removed = solution.unassigned.len();
 
         let route_length = solution.routes[route_number].nodes.len();
 
@@ -102,6 +103,38 @@ impl AdjacentStringRemoval {
             }
         }
         solution.ruined_routes.insert(route_number);
+        self.ruin_clusters(ctx, solution, first_removed);
+    }
+
+    // Removes the rest of the clusters of the customers removed since the given index of the
+    // unassigned customers, such that recreate inserts every cluster as a whole
+    fn ruin_clusters(&self, ctx: &Context, solution: &mut RuinRecreateSolution, first: usize) {
+        if ctx.problem.clusters.is_empty() {
+            return;
+        }
+        let mut index = first;
+        while index < solution.unassigned.len() {
+            let customer = solution.unassigned[index];
+            let cluster = ctx
+                .problem
+                .clusters
+                .iter()
+                .find(|cluster| cluster.contains(&customer));
+            for &other in cluster.into_iter().flatten() {
+                // Customers already removed are no longer on their route
+                let route_number = solution.locations[other].route_index;
+                let position = solution.routes[route_number]
+                    .nodes
+                    .iter()
+                    .position(|&node| node == other);
+                if let Some(position) = position {
+                    let removed = solution.routes[route_number].remove(position, ctx);
+                    solution.unassigned.push(removed);
+                    solution.ruined_routes.insert(route_number);
+                }
+            }
+            index += 1;
+        }
     }
 }
 
@@ -144,3 +177,40 @@ impl Ruin for AdjacentStringRemoval {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solver::genetic::{Individual, Split};
+    use crate::utils::instance_context;
+
+    #[test]
+    fn ruin_removes_whole_clusters() {
+        let mut ctx = instance_context(|_| {});
+        let clusters = vec![vec![7, 29], vec![34, 64, 87]];
+        ctx.problem.clusters = clusters.clone();
+        let mut individual = Individual::new((1..ctx.problem.dim()).collect(), 0);
+        let max_vehicles = ctx.config.borrow().num_vehicles;
+        Split::new(&ctx).run(&ctx, &mut individual, max_vehicles);
+        let mut solution = RuinRecreateSolution::new(&ctx);
+        solution.load(&individual);
+
+        let ruin = AdjacentStringRemoval::new(&ctx);
+        let mut ruined_clusters = 0;
+        for _ in 0..100 {
+            let mut ruined = solution.clone();
+            ruin.run(&ctx, &mut ruined);
+            for cluster in clusters.iter() {
+                let removed = cluster
+                    .iter()
+                    .filter(|customer| ruined.unassigned.contains(customer))
+                    .count();
+                assert!(removed == 0 || removed == cluster.len());
+                if removed > 0 {
+                    ruined_clusters += 1;
+                }
+            }
+        }
+        assert!(ruined_clusters > 0);
+    }
+}
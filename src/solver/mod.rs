@@ -7,5 +7,6 @@ pub use self::solver::*;
 mod history;
 pub use self::history::*;
 
+pub mod construction;
 pub mod genetic;
 pub mod improvement;
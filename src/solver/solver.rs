@@ -1,3 +1,12 @@
+#[cfg(not(feature = "wasm"))]
+use std::sync::atomic::Ordering;
+#[cfg(not(feature = "wasm"))]
+use std::sync::mpsc::{self, RecvTimeoutError};
+#[cfg(not(feature = "wasm"))]
+use std::thread;
+#[cfg(not(feature = "wasm"))]
+use std::time::Duration;
+
 use crate::solver::Context;
 
 pub trait Metaheuristic {
@@ -22,9 +31,37 @@ where
     }
 
     pub fn run(&mut self) {
+        let _watchdog = self.start_watchdog();
         while !self.metaheuristic.terminated() {
             self.metaheuristic.iterate(&self.ctx);
         }
         log::info!("Time: {:?}, Completed", self.ctx.elapsed());
     }
+
+    /// Starts a thread signalling the deadline at the time limit if `hard_deadline` is set.
+    /// The thread stops without signalling when the returned sender is dropped
+    #[cfg(not(feature = "wasm"))]
+    fn start_watchdog(&self) -> Option<mpsc::Sender<()>> {
+        let time_limit = Duration::from_secs(self.ctx.config.borrow().time_limit);
+        if !self.ctx.config.borrow().hard_deadline {
+            return None;
+        }
+        let remaining = time_limit
+            .checked_sub(self.ctx.elapsed())
+            .unwrap_or_default();
+        let deadline = self.ctx.deadline.clone();
+        let (sender, receiver) = mpsc::channel::<()>();
+        thread::spawn(move || {
+            if let Err(RecvTimeoutError::Timeout) = receiver.recv_timeout(remaining) {
+                deadline.store(true, Ordering::Relaxed);
+            }
+        });
+        Some(sender)
+    }
+
+    // Threads are not available in the browser
+    #[cfg(feature = "wasm")]
+    fn start_watchdog(&self) -> Option<()> {
+        None
+    }
 }
@@ -1,6 +1,7 @@
 use std::borrow::Borrow;
 
-const EPSILON: f64 = 0.000001;
+/// Default epsilon used in the comparisons
+pub const EPSILON: f64 = 0.000001;
 
 /// Extension trait enabling float comparison using an epsilon value
 pub trait FloatCompare<T> {
@@ -9,6 +10,11 @@ pub trait FloatCompare<T> {
     fn approx_gt(&self, other: T) -> bool;
     fn approx_gte(&self, other: T) -> bool;
     fn approx_eq(&self, other: T) -> bool;
+
+    // Same as `approx_lt`, `approx_gte` and `approx_eq`, but with the given epsilon
+    fn approx_lt_eps(&self, other: T, epsilon: f64) -> bool;
+    fn approx_gte_eps(&self, other: T, epsilon: f64) -> bool;
+    fn approx_eq_eps(&self, other: T, epsilon: f64) -> bool;
 }
 
 impl<T: Borrow<f64>> FloatCompare<T> for f64 {
@@ -31,6 +37,19 @@ impl<T: Borrow<f64>> FloatCompare<T> for f64 {
     fn approx_eq(&self, other: T) -> bool {
         (self - other.borrow()).abs() < EPSILON
     }
+
+    fn approx_lt_eps(&self, other: T, epsilon: f64) -> bool {
+        *self < other.borrow() - epsilon
+    }
+
+    fn approx_gte_eps(&self, other: T, epsilon: f64) -> bool {
+        let other = *other.borrow();
+        *self > other || (*self - other).abs() < epsilon
+    }
+
+    fn approx_eq_eps(&self, other: T, epsilon: f64) -> bool {
+        (self - other.borrow()).abs() < epsilon
+    }
 }
 
 #[cfg(test)]
@@ -81,6 +100,16 @@ mod tests {
         assert_eq!(false, 10.0.approx_gte(11.0));
     }
 
+    #[test]
+    fn f64_approx_eps() {
+        assert_eq!(true, 10.0.approx_lt_eps(10.1, 0.01));
+        assert_eq!(false, 10.0.approx_lt_eps(10.1, 0.5));
+        assert_eq!(false, 10.0.approx_gte_eps(10.1, 0.01));
+        assert_eq!(true, 10.0.approx_gte_eps(10.1, 0.5));
+        assert_eq!(false, 10.0.approx_eq_eps(10.1, 0.01));
+        assert_eq!(true, 10.0.approx_eq_eps(10.1, 0.5));
+    }
+
     #[test]
     fn f64_approx_eq() {
         assert_eq!(false, 10.0.approx_eq(9.0));
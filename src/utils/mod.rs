@@ -9,3 +9,8 @@ pub use self::random::*;
 
 mod serializer;
 pub use self::serializer::*;
+
+#[cfg(test)]
+mod testing;
+#[cfg(test)]
+pub use self::testing::*;
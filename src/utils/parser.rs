@@ -27,6 +27,7 @@ impl FromStr for EdgeWeightType {
 
 enum EdgeWeightFormat {
     LowerRow,
+    LowerDiagRow,
 }
 
 impl FromStr for EdgeWeightFormat {
@@ -35,6 +36,7 @@ impl FromStr for EdgeWeightFormat {
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s {
             "LOWER_ROW" => Ok(Self::LowerRow),
+            "LOWER_DIAG_ROW" => Ok(Self::LowerDiagRow),
             _ => Err(format!("Unknown EDGE_WEIGHT_FORMAT: {}", s)),
         }
     }
@@ -74,15 +76,26 @@ impl ProblemParser {
         let problem = problem_builder.build();
         self.problem = Some(problem);
 
-        match Self::parse_edge_weight_type(&lines) {
-            EdgeWeightType::Euclidian2D => {}
-            EdgeWeightType::Explicit => match Self::parse_edge_weight_format(&lines) {
+        self.matrix = Self::parse_matrix(&lines, dimension);
+    }
+
+    /// Lower rows of an explicit distance matrix, where row `i` starts at node `i + 1`
+    fn parse_matrix(lines: &Lines, dimension: usize) -> Option<Vec<Vec<f64>>> {
+        match Self::parse_edge_weight_type(lines) {
+            EdgeWeightType::Euclidian2D => None,
+            EdgeWeightType::Explicit => match Self::parse_edge_weight_format(lines) {
                 EdgeWeightFormat::LowerRow => {
-                    let matrix = Self::parse_lower_row_matrix(&lines, dimension);
-                    self.matrix = Some(matrix);
+                    Some(Self::parse_lower_row_matrix(lines, dimension - 1))
+                }
+                EdgeWeightFormat::LowerDiagRow => {
+                    // Rows end with the diagonal entry. The first row only holds the diagonal
+                    // entry of the depot, which is never visited as a customer
+                    let mut matrix = Self::parse_lower_row_matrix(lines, dimension);
+                    matrix.remove(0);
+                    Some(matrix)
                 }
             },
-        };
+        }
     }
 
     fn read_file(path: &Path) -> Lines {
@@ -182,13 +195,13 @@ impl ProblemParser {
         panic!("Could not find DEMAND_SECTION");
     }
 
-    fn parse_lower_row_matrix(lines: &Lines, number: usize) -> Vec<Vec<f64>> {
+    fn parse_lower_row_matrix(lines: &Lines, rows: usize) -> Vec<Vec<f64>> {
         for (line_number, line) in lines.iter().enumerate() {
             if !line.is_empty() && line[0] == "EDGE_WEIGHT_SECTION" {
                 return lines
                     .iter()
                     .skip(line_number + 1)
-                    .take(number - 1)
+                    .take(rows)
                     .map(|line| {
                         line.iter()
                             .map(|val| val.parse::<f64>().expect("Failed to parse edge weight"))
@@ -217,3 +230,42 @@ impl ProblemParser {
         Vehicle { id, cap: capacity }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &str) -> Lines {
+        text.lines()
+            .map(|line| line.split_whitespace().map(str::to_owned).collect())
+            .collect()
+    }
+
+    #[test]
+    fn lower_diag_row_matrix() {
+        let matrix = ProblemParser::parse_matrix(
+            &lines(
+                "EDGE_WEIGHT_TYPE EXPLICIT
+                EDGE_WEIGHT_FORMAT LOWER_DIAG_ROW
+                EDGE_WEIGHT_SECTION
+                0
+                10 1
+                10 5 2",
+            ),
+            3,
+        );
+        assert_eq!(Some(vec![vec![10.0, 1.0], vec![10.0, 5.0, 2.0]]), matrix);
+
+        let matrix = ProblemParser::parse_matrix(
+            &lines(
+                "EDGE_WEIGHT_TYPE EXPLICIT
+                EDGE_WEIGHT_FORMAT LOWER_ROW
+                EDGE_WEIGHT_SECTION
+                10
+                10 5",
+            ),
+            3,
+        );
+        assert_eq!(Some(vec![vec![10.0], vec![10.0, 5.0]]), matrix);
+    }
+}
@@ -1,12 +1,39 @@
 use fastrand::Rng;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, MutexGuard};
 
 use ahash::RandomState;
+use serde::{Deserialize, Serialize};
+
+/// A single value drawn from the random number generator
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub enum RandomDraw {
+    Real(f64),
+    Usize(usize),
+}
+
+/// Where the random values are taken from
+#[derive(Debug)]
+enum Stream {
+    // Values are drawn from the generator
+    Live,
+    // Values are drawn from the generator and stored
+    Record(Vec<RandomDraw>),
+    // Values are taken from a previously recorded stream
+    Replay(VecDeque<RandomDraw>),
+}
 
 #[derive(Clone, Debug)]
 pub struct Random {
     pub rng: RefCell<Rng>,
     pub seed: Option<u64>,
+    // Shared between clones such that all draws end up in the same stream. A mutex keeps the
+    // generator `Send`
+    stream: Arc<Mutex<Stream>>,
+    // Whether the stream is live, such that the draws skip the lock. Clones copy it, so the
+    // stream can only leave the live state before the generator is cloned
+    live: Cell<bool>,
 }
 
 impl Random {
@@ -14,6 +41,8 @@ impl Random {
         Self {
             rng: RefCell::new(Rng::new()),
             seed: None,
+            stream: Arc::new(Mutex::new(Stream::Live)),
+            live: Cell::new(true),
         }
     }
 
@@ -21,20 +50,131 @@ impl Random {
         Self {
             rng: RefCell::new(Rng::with_seed(seed)),
             seed: Some(seed),
+            stream: Arc::new(Mutex::new(Stream::Live)),
+            live: Cell::new(true),
+        }
+    }
+
+    /// Current state of the generator. A generator set to this state continues the same stream
+    pub fn state(&self) -> u64 {
+        self.rng.borrow().get_seed()
+    }
+
+    /// Continues the stream of a generator with the given state
+    pub fn set_state(&self, state: u64) {
+        self.rng.borrow().seed(state);
+    }
+
+    /// Store every value drawn from now on. The draws are available with `recorded_draws`.
+    ///
+    /// Panics if the generator has been cloned.
+    pub fn start_recording(&self) {
+        self.leave_live(Stream::Record(Vec::new()));
+    }
+
+    /// Take all values from the recorded `draws` instead of the generator.
+    ///
+    /// Panics if the generator has been cloned, if the stream is exhausted or if a draw does not
+    /// match the requested value.
+    pub fn replay(&self, draws: Vec<RandomDraw>) {
+        self.leave_live(Stream::Replay(draws.into()));
+    }
+
+    /// Returns the draws recorded so far
+    pub fn recorded_draws(&self) -> Vec<RandomDraw> {
+        match &*self.stream() {
+            Stream::Record(draws) => draws.clone(),
+            _ => Vec::new(),
         }
     }
 
+    // Replaces the live stream before any clone shares it
+    fn leave_live(&self, stream: Stream) {
+        assert_eq!(
+            1,
+            Arc::strong_count(&self.stream),
+            "Random stream must be set before the generator is cloned"
+        );
+        *self.stream() = stream;
+        self.live.set(false);
+    }
+
+    #[inline]
+    fn is_live(&self) -> bool {
+        self.live.get()
+    }
+
+    fn stream(&self) -> MutexGuard<'_, Stream> {
+        self.stream.lock().expect("Random stream is poisoned")
+    }
+
+    /// Takes the next draw from the replayed stream
+    fn next_replayed(draws: &mut VecDeque<RandomDraw>, expected: &str) -> RandomDraw {
+        draws
+            .pop_front()
+            .unwrap_or_else(|| panic!("Replayed random stream is exhausted, expected {}", expected))
+    }
+
     pub fn shuffle<T>(&self, container: &mut [T]) {
-        self.rng.borrow_mut().shuffle(container);
+        // Same procedure as `Rng::shuffle` such that every draw goes through `range_usize`
+        for i in 1..container.len() {
+            container.swap(i, self.range_usize(0, i + 1));
+        }
     }
 
+    #[inline]
     pub fn real(&self) -> f64 {
-        self.rng.borrow_mut().f64() as f64
+        match self.is_live() {
+            true => self.rng.borrow_mut().f64(),
+            false => self.stream_real(),
+        }
+    }
+
+    // Draws a real through the recorded or replayed stream
+    fn stream_real(&self) -> f64 {
+        match &mut *self.stream() {
+            Stream::Live => self.rng.borrow_mut().f64(),
+            Stream::Record(draws) => {
+                let value = self.rng.borrow_mut().f64();
+                draws.push(RandomDraw::Real(value));
+                value
+            }
+            Stream::Replay(draws) => match Self::next_replayed(draws, "a real") {
+                RandomDraw::Real(value) => value,
+                draw => panic!(
+                    "Replayed random stream mismatch: expected a real, found {:?}",
+                    draw
+                ),
+            },
+        }
     }
 
     // Get random number in range [lower, upper). Upper is not inclusive
+    #[inline]
     pub fn range_usize(&self, lower: usize, upper: usize) -> usize {
-        self.rng.borrow_mut().usize(lower..upper)
+        match self.is_live() {
+            true => self.rng.borrow_mut().usize(lower..upper),
+            false => self.stream_range_usize(lower, upper),
+        }
+    }
+
+    // Draws a usize in [lower, upper) through the recorded or replayed stream
+    fn stream_range_usize(&self, lower: usize, upper: usize) -> usize {
+        match &mut *self.stream() {
+            Stream::Live => self.rng.borrow_mut().usize(lower..upper),
+            Stream::Record(draws) => {
+                let value = self.rng.borrow_mut().usize(lower..upper);
+                draws.push(RandomDraw::Usize(value));
+                value
+            }
+            Stream::Replay(draws) => match Self::next_replayed(draws, "a usize") {
+                RandomDraw::Usize(value) if lower <= value && value < upper => value,
+                draw => panic!(
+                    "Replayed random stream mismatch: expected a usize in [{}, {}), found {:?}",
+                    lower, upper, draw
+                ),
+            },
+        }
     }
 
     // Sample `number` elements from the vec
@@ -45,7 +185,7 @@ impl Random {
 
         // Take an element from the vec until `number` elements have been sampled
         while new_vec.len() < number {
-            new_vec.push(vec.remove(self.rng.borrow_mut().usize(0..vec.len())));
+            new_vec.push(vec.remove(self.range_usize(0, vec.len())));
         }
         new_vec
     }
@@ -58,11 +198,116 @@ impl Random {
         }
     }
 
+    /// Hasher seeded by the generator. The iteration order of the hashed collections can steer
+    /// the search, so the hasher seed is part of a recorded stream
     pub fn random_state(&self) -> RandomState {
-        if let Some(seed) = self.seed {
-            RandomState::with_seeds(seed, seed + 123, seed + 321, seed + 1337)
+        let seed = match &mut *self.stream() {
+            Stream::Live => self.seed,
+            Stream::Record(draws) => {
+                let seed = self.seed.unwrap_or_else(|| fastrand::usize(..) as u64);
+                draws.push(RandomDraw::Usize(seed as usize));
+                Some(seed)
+            }
+            Stream::Replay(draws) => match Self::next_replayed(draws, "a hasher seed") {
+                RandomDraw::Usize(seed) => Some(seed as u64),
+                draw => panic!(
+                    "Replayed random stream mismatch: expected a hasher seed, found {:?}",
+                    draw
+                ),
+            },
+        };
+        if let Some(seed) = seed {
+            RandomState::with_seeds(
+                seed,
+                seed.wrapping_add(123),
+                seed.wrapping_add(321),
+                seed.wrapping_add(1337),
+            )
         } else {
             RandomState::new()
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::hash::BuildHasher;
+
+    use super::*;
+
+    #[test]
+    fn recorded_stream_is_replayed() {
+        let random = Random::from_seed(1);
+        random.start_recording();
+        let mut values = vec![0, 1, 2, 3, 4, 5, 6, 7];
+        random.shuffle(&mut values);
+        let real = random.real();
+        let number = random.range_usize(3, 10);
+        let hash = random.random_state().hash_one(42);
+
+        // The replayed values are independent of the seed
+        let replayed = Random::from_seed(2);
+        replayed.replay(random.recorded_draws());
+        let mut replayed_values = vec![0, 1, 2, 3, 4, 5, 6, 7];
+        replayed.shuffle(&mut replayed_values);
+        assert_eq!(values, replayed_values);
+        assert_eq!(real, replayed.real());
+        assert_eq!(number, replayed.range_usize(3, 10));
+        assert_eq!(hash, replayed.random_state().hash_one(42));
+    }
+
+    #[test]
+    fn random_is_send() {
+        // Clones share the recorded stream, which must not tie the generator to one thread
+        fn assert_send<T: Send>() {}
+        assert_send::<Random>();
+    }
+
+    #[test]
+    fn recording_keeps_the_seeded_stream() {
+        let live = Rng::with_seed(3);
+        let recorded = Random::from_seed(3);
+        recorded.start_recording();
+        let mut live_values: Vec<usize> = (0..20).collect();
+        let mut recorded_values: Vec<usize> = (0..20).collect();
+        live.shuffle(&mut live_values);
+        recorded.shuffle(&mut recorded_values);
+        assert_eq!(live_values, recorded_values);
+        assert_eq!(live.f64(), recorded.real());
+    }
+
+    #[test]
+    fn state_continues_the_stream() {
+        let random = Random::from_seed(4);
+        random.range_usize(0, 10);
+        let continued = Random::from_seed(5);
+        continued.set_state(random.state());
+        assert_eq!(random.real(), continued.real());
+        assert_eq!(random.range_usize(0, 100), continued.range_usize(0, 100));
+    }
+
+    #[test]
+    #[should_panic(expected = "before the generator is cloned")]
+    fn recording_after_clone() {
+        let random = Random::new();
+        let _clone = random.clone();
+        random.start_recording();
+    }
+
+    #[test]
+    #[should_panic(expected = "exhausted")]
+    fn replay_exhausted() {
+        let random = Random::new();
+        random.replay(vec![RandomDraw::Real(0.5)]);
+        random.real();
+        random.real();
+    }
+
+    #[test]
+    #[should_panic(expected = "mismatch")]
+    fn replay_mismatch() {
+        let random = Random::new();
+        random.replay(vec![RandomDraw::Usize(5)]);
+        random.range_usize(0, 3);
+    }
+}
@@ -0,0 +1,57 @@
+use std::time::Instant;
+
+use crate::config::Config;
+use crate::models::{Coordinate, Node, Problem, ProblemBuilder, Vehicle};
+use crate::solver::Context;
+use crate::utils::ProblemParser;
+
+/// Problem on the coordinates, the first of which is the depot, with customers of demand 1
+pub fn coordinate_problem(coords: &[(f64, f64)], capacity: f64) -> Problem {
+    let demands = vec![1.0; coords.len().saturating_sub(1)];
+    demand_problem(coords, &demands, capacity)
+}
+
+/// Problem on the coordinates, the first of which is the depot, with a demand for each of
+/// the customers
+pub fn demand_problem(coords: &[(f64, f64)], demands: &[f64], capacity: f64) -> Problem {
+    assert_eq!(coords.len(), demands.len() + 1);
+    let nodes = coords
+        .iter()
+        .enumerate()
+        .map(|(id, &(lng, lat))| Node {
+            id: id + 1,
+            coord: Coordinate { lng, lat },
+            demand: if id == 0 { 0.0 } else { demands[id - 1] },
+        })
+        .collect();
+    let vehicle = Vehicle {
+        id: 0,
+        cap: capacity,
+    };
+    ProblemBuilder::new(nodes, vehicle).build()
+}
+
+/// Context of the problem, with the lower rows of an explicit distance matrix if given
+pub fn problem_context(problem: Problem, matrix: Option<Vec<Vec<f64>>>, config: Config) -> Context {
+    let mut parser = ProblemParser::new();
+    parser.problem = Some(problem);
+    parser.matrix = matrix;
+    Context::new(parser, config, Instant::now())
+}
+
+/// Context of `coordinate_problem` with capacity 10 and the default configuration
+pub fn coordinate_context(coords: &[(f64, f64)]) -> Context {
+    problem_context(coordinate_problem(coords, 10.0), None, Config::default())
+}
+
+/// Context of the instance X-n101-k25 with a deterministic random stream, after `configure`
+/// changed the configuration. It may also change the instance path
+pub fn instance_context(configure: impl Fn(&mut Config)) -> Context {
+    let mut config = Config::default();
+    config.instance_path = "instances/X-n101-k25.vrp".to_owned();
+    config.deterministic = true;
+    configure(&mut config);
+    let mut parser = ProblemParser::new();
+    parser.parse(&mut config);
+    Context::new(parser, config, Instant::now())
+}